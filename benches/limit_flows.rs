@@ -0,0 +1,58 @@
+//! Time the water update while a thin sheet of water drains off a grid of
+//! bumps, so that half the cells dry out at once and need their outflow
+//! limited.
+//!
+//! The row limiter is timed next to the worklist it replaced, which the
+//! water keeps as a hidden reference.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use mod1::{HeightMap, Water};
+
+const SHEET_DEPTH: f32 = 0.05;
+const STEP: f32 = 0.08333;
+const STEPS: usize = 5;
+
+fn bumps(side: usize) -> HeightMap {
+	let heights = (0..side * side)
+		.map(|index| ((index % side + index / side) % 2) as f32)
+		.collect();
+	HeightMap::from_heights((side, side), heights).unwrap()
+}
+
+fn sheet(side: usize, worklist: bool) -> Water {
+	let mut water = Water::new((side, side));
+	water.set_worklist_limiter(worklist);
+	for y in 0..side {
+		for x in 0..side {
+			water.add_depth(x, y, SHEET_DEPTH);
+		}
+	}
+	water
+}
+
+fn dry_out_front(c: &mut Criterion) {
+	let mut group = c.benchmark_group("dry_out_front");
+	group.sample_size(10);
+	for side in [64, 128, 256].iter() {
+		let terrain = bumps(*side);
+		for (name, worklist) in [("rows", false), ("worklist", true)].iter() {
+			let id = BenchmarkId::new(*name, side);
+			group.bench_with_input(id, side, |b, side| {
+				b.iter_batched(
+					|| sheet(*side, *worklist),
+					|mut water| {
+						for _ in 0..STEPS {
+							water.update(&terrain, STEP);
+						}
+						water
+					},
+					BatchSize::LargeInput,
+				)
+			});
+		}
+	}
+	group.finish();
+}
+
+criterion_group!(benches, dry_out_front);
+criterion_main!(benches);
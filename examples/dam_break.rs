@@ -0,0 +1,109 @@
+//! Break a dam in a flat channel with every solver and compare the depths
+//! with the exact solution of the shallow water equations: Ritter's on a dry
+//! bed, Stoker's on a wet one.
+//!
+//! `cargo run --release --example dam_break`
+
+use mod1::{new_solver, HeightMap, Water, SOLVERS};
+
+const LENGTH: usize = 400;
+const WIDTH: usize = 2;
+const UPSTREAM_DEPTH: f32 = 2.0;
+const DOWNSTREAM_DEPTHS: [f32; 2] = [0.0, 0.5];
+const STEP: f32 = 0.05;
+const STEPS: usize = 300;
+
+fn main() -> Result<(), String> {
+	let terrain = HeightMap::from_heights((LENGTH, WIDTH), vec![0.0; LENGTH * WIDTH])?;
+	for downstream in DOWNSTREAM_DEPTHS.iter() {
+		println!(
+			"dam break from {} to {} after {}s, {} cells",
+			UPSTREAM_DEPTH,
+			downstream,
+			STEP * STEPS as f32,
+			LENGTH
+		);
+		for name in SOLVERS.iter() {
+			let mut water = Water::new((LENGTH, WIDTH));
+			water.set_solver(new_solver(name)?);
+			// The exact solutions have no friction.
+			let mut params = *water.params();
+			params.damping = 0.0;
+			water.set_params(params)?;
+			for y in 0..WIDTH {
+				for x in 0..LENGTH {
+					let depth = if x < LENGTH / 2 {
+						UPSTREAM_DEPTH
+					} else {
+						*downstream
+					};
+					water.add_depth(x, y, depth);
+				}
+			}
+			for _ in 0..STEPS {
+				water.update(&terrain, STEP);
+			}
+			let gravity = water.params().gravity;
+			let time = STEP * STEPS as f32;
+			let error: f32 = (0..LENGTH)
+				.map(|x| {
+					let position = x as f32 - (LENGTH / 2) as f32 + 0.5;
+					let exact = exact_depth(gravity, *downstream, position / time);
+					(water.depth(x, 0) - exact).abs()
+				})
+				.sum::<f32>()
+				/ LENGTH as f32;
+			println!(
+				"  {:8} mean error {:.5} ({:.3}% of the upstream depth)",
+				name,
+				error,
+				error / UPSTREAM_DEPTH * 100.0
+			);
+		}
+	}
+	Ok(())
+}
+
+/// Depth of the exact solution at `speed = position / time` from the dam.
+fn exact_depth(gravity: f32, downstream: f32, speed: f32) -> f32 {
+	let upstream_celerity = (gravity * UPSTREAM_DEPTH).sqrt();
+	if speed <= -upstream_celerity {
+		return UPSTREAM_DEPTH;
+	}
+	let rarefaction = |speed: f32| (2.0 * upstream_celerity - speed).powi(2) / (9.0 * gravity);
+	if downstream <= 0.0 {
+		return if speed < 2.0 * upstream_celerity {
+			rarefaction(speed)
+		} else {
+			0.0
+		};
+	}
+	// Celerity between the rarefaction and the bore, by bisection.
+	let downstream_celerity = (gravity * downstream).sqrt();
+	let stoker = |celerity: f32| {
+		let square = celerity * celerity;
+		let bore = square - downstream_celerity * downstream_celerity;
+		-8.0 * downstream_celerity.powi(2) * square * (upstream_celerity - celerity).powi(2)
+			+ bore * bore * (square + downstream_celerity.powi(2))
+	};
+	let (mut low, mut high) = (downstream_celerity, upstream_celerity);
+	for _ in 0..60 {
+		let middle = (low + high) / 2.0;
+		if (stoker(middle) < 0.0) == (stoker(low) < 0.0) {
+			low = middle;
+		} else {
+			high = middle;
+		}
+	}
+	let celerity = (low + high) / 2.0;
+	let square = celerity * celerity;
+	let bore_speed = 2.0 * square * (upstream_celerity - celerity)
+		/ (square - downstream_celerity * downstream_celerity);
+	if speed < 2.0 * upstream_celerity - 3.0 * celerity {
+		rarefaction(speed)
+	} else if speed < bore_speed {
+		square / gravity
+	} else {
+		downstream
+	}
+}
@@ -0,0 +1,19 @@
+#version 400 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+out vec3 color;
+out vec3 fragPos;
+out vec3 normal;
+
+void main()
+{
+    gl_Position = projection * view * model * vec4(aPos, 1.0f);
+	color = vec3(0.55, 0.35, 0.2);
+	fragPos = vec3(model * vec4(aPos, 1.0));
+	normal = aNormal;
+}
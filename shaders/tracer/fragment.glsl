@@ -0,0 +1,12 @@
+#version 400 core
+out vec4 FragColor;
+
+in vec3 fragPos;
+
+uniform sampler2D screenTexture;
+uniform vec3 viewPos;
+
+void main()
+{
+   FragColor = vec4(vec3(0.95, 0.85, 0.3), 1.0);
+}
\ No newline at end of file
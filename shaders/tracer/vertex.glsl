@@ -0,0 +1,14 @@
+#version 400 core
+layout (location = 0) in vec3 aPos;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+out vec3 fragPos;
+
+void main()
+{
+    gl_Position = projection * view * model * vec4(aPos, 1.0);
+	fragPos = vec3(model * vec4(aPos, 1.0));
+}
\ No newline at end of file
@@ -1,12 +1,51 @@
+mod boundary;
+mod dem;
+mod erosion;
+mod floating;
 mod heightmap;
+mod image;
+mod ledger;
+mod map_error;
+mod map_info;
+mod params;
 mod rain;
+mod roughness;
+mod rows;
+mod sea;
+mod snapshot;
+mod soil;
+mod solver;
+mod source;
+mod tracers;
+mod velocity;
 mod water;
 
+pub use boundary::{Boundaries, Boundary, Edge};
+pub use dem::DemScale;
+pub use erosion::Erosion;
+pub use floating::FloatingBody;
 pub use heightmap::HeightMap;
+pub use ledger::VolumeLedger;
+pub use map_error::{Location, MapError, MapReport, MapWarning};
+pub use map_info::{Interpolation, MapInfo, FORMAT_VERSION};
+pub use params::WaterParams;
 pub use rain::Rain;
-pub use water::Water;
+pub use roughness::{load_roughness, SURFACES};
+pub use sea::Sea;
+pub use snapshot::{load_state, save_state, STATE_VERSION};
+pub use soil::Soil;
+pub use solver::{new_solver, WaterSolver, SOLVERS};
+pub use source::{Rate, Source, SourceKind};
+pub use tracers::Tracers;
+pub use velocity::save_velocities;
+pub use water::{PipeSolver, ShallowWater, StepReport, Water, ZERO_DEPTH};
 
 const G: f32 = 9.81;
-pub const DIM: usize = 100;
+pub const DEFAULT_DIM: usize = 100;
+pub const MIN_DIM: usize = 2;
+pub const MAX_DIM: usize = 2048;
 
-pub type Map<const SIZE: usize> = [f32; SIZE];
+pub type Map = Vec<f32>;
+/// Random generator used for every random choice of the simulation, seed it
+/// to get reproducible runs.
+pub type SimRng = rand_chacha::ChaCha8Rng;
@@ -0,0 +1,139 @@
+/// One side of the grid. The west and east edges are the `x = 0` and
+/// `x = width - 1` columns, the south and north edges the `y = 0` and
+/// `y = height - 1` rows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+	West,
+	East,
+	South,
+	North,
+}
+
+impl Edge {
+	pub const ALL: [Edge; 4] = [Edge::West, Edge::East, Edge::South, Edge::North];
+
+	pub fn parse(value: &str) -> Result<Self, String> {
+		match value {
+			"west" => Ok(Edge::West),
+			"east" => Ok(Edge::East),
+			"south" => Ok(Edge::South),
+			"north" => Ok(Edge::North),
+			_ => Err(format!(
+				"Unknown edge '{}' (expected west, east, south, north or all)",
+				value
+			)),
+		}
+	}
+
+	pub fn opposite(self) -> Self {
+		match self {
+			Edge::West => Edge::East,
+			Edge::East => Edge::West,
+			Edge::South => Edge::North,
+			Edge::North => Edge::South,
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			Edge::West => "west",
+			Edge::East => "east",
+			Edge::South => "south",
+			Edge::North => "north",
+		}
+	}
+}
+
+/// How water behaves when it reaches an edge of the grid.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Boundary {
+	/// A wall, nothing goes through.
+	#[default]
+	Closed,
+	/// Water flows off the map and never comes back.
+	Open,
+	/// The edge opens on a sea held at the given surface height, water flows
+	/// in or out depending on the level on the grid.
+	Reservoir(f32),
+	/// Water leaving the edge comes back from the opposite one, which must be
+	/// periodic too.
+	Periodic,
+}
+
+impl Boundary {
+	/// Parse `closed`, `open`, `periodic` or `reservoir:<level>`.
+	pub fn parse(value: &str) -> Result<Self, String> {
+		match value.split_once(':') {
+			Some(("reservoir", level)) => match level.parse::<f32>() {
+				Ok(level) if level.is_finite() => Ok(Boundary::Reservoir(level)),
+				Ok(level) => Err(format!("Invalid reservoir level '{}'", level)),
+				Err(err) => Err(format!("Invalid reservoir level '{}' ({})", level, err)),
+			},
+			None if value == "closed" => Ok(Boundary::Closed),
+			None if value == "open" => Ok(Boundary::Open),
+			None if value == "periodic" => Ok(Boundary::Periodic),
+			_ => Err(format!(
+				"Unknown boundary '{}' (expected closed, open, periodic or reservoir:<level>)",
+				value
+			)),
+		}
+	}
+}
+
+/// Boundary of each edge of the grid, all closed by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Boundaries {
+	pub west: Boundary,
+	pub east: Boundary,
+	pub south: Boundary,
+	pub north: Boundary,
+}
+
+impl Boundaries {
+	pub fn get(&self, edge: Edge) -> Boundary {
+		match edge {
+			Edge::West => self.west,
+			Edge::East => self.east,
+			Edge::South => self.south,
+			Edge::North => self.north,
+		}
+	}
+
+	pub fn set(&mut self, edge: Edge, boundary: Boundary) {
+		match edge {
+			Edge::West => self.west = boundary,
+			Edge::East => self.east = boundary,
+			Edge::South => self.south = boundary,
+			Edge::North => self.north = boundary,
+		}
+	}
+
+	/// Apply an `<edge> <boundary>` pair, where the edge can also be `all`.
+	pub fn apply(&mut self, edge: &str, boundary: &str) -> Result<(), String> {
+		let boundary = Boundary::parse(boundary)?;
+		if edge == "all" {
+			for edge in Edge::ALL.iter() {
+				self.set(*edge, boundary);
+			}
+		} else {
+			self.set(Edge::parse(edge)?, boundary);
+		}
+		Ok(())
+	}
+
+	/// Check that periodic edges come in opposite pairs.
+	pub fn check(&self) -> Result<(), String> {
+		for edge in Edge::ALL.iter() {
+			if self.get(*edge) == Boundary::Periodic
+				&& self.get(edge.opposite()) != Boundary::Periodic
+			{
+				return Err(format!(
+					"The {} edge is periodic, the {} edge must be periodic too",
+					edge.name(),
+					edge.opposite().name()
+				));
+			}
+		}
+		Ok(())
+	}
+}
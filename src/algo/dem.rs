@@ -0,0 +1,544 @@
+use super::{HeightMap, MapError, WaterParams};
+
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+
+/// Highest relief of an elevation model kept at its true proportions, in
+/// height units. Steeper models are flattened to fit.
+const MAX_RELIEF: f32 = 40.0;
+/// Most cells read from an elevation model, those of a 4096x4096 grid, so a
+/// broken header can not make it allocate without bound.
+const MAX_SOURCE_CELLS: usize = 4096 * 4096;
+
+/// How the real-world coordinates of an elevation model were rescaled to the
+/// simulation grid.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DemScale {
+	/// Width and depth of a cell, in the unit of the model.
+	pub cell: (f64, f64),
+	/// Elevation difference of one height unit, in the unit of the model.
+	pub height_unit: f64,
+	/// Elevation of the model at height 0.
+	pub base: f64,
+}
+
+impl fmt::Display for DemScale {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(
+			f,
+			"1 cell is {}x{} units, 1 height unit is {} units, elevation {} is at height 0",
+			significant(self.cell.0),
+			significant(self.cell.1),
+			significant(self.height_unit),
+			significant(self.base)
+		)
+	}
+}
+
+/// `value` rounded to 4 significant digits, with at least the units.
+fn significant(value: f64) -> String {
+	let digits = if value == 0.0 {
+		0
+	} else {
+		(3 - value.abs().log10().floor() as i32).max(0) as usize
+	};
+	format!("{:.*}", digits, value)
+}
+
+/// Elevations of a model on a grid, row by row from the south, `None` where
+/// it has no data.
+struct Elevations {
+	dims: (usize, usize),
+	values: Vec<Option<f64>>,
+	/// Distance between two columns and two rows, in the unit of the model.
+	spacing: (f64, f64),
+}
+
+impl HeightMap {
+	/// Load an ESRI ASCII grid, its `ncols`, `nrows`, `xllcorner` or
+	/// `xllcenter`, `yllcorner` or `yllcenter`, `cellsize` and optional
+	/// `NODATA_value` header followed by its rows from the north.
+	///
+	/// The grid is resampled on `dims` cells, or kept at its size when `dims`
+	/// is `None`, and its elevations rescaled as described in
+	/// [`HeightMap::from_elevations`]. Cells without data are filled from
+	/// their neighbours.
+	pub fn from_esri_ascii(
+		filename: &str,
+		dims: Option<(usize, usize)>,
+		relief: Option<f32>,
+		offset: f32,
+	) -> Result<Self, String> {
+		let elevations = read_esri_ascii(filename)?;
+		HeightMap::from_elevations(elevations, dims, relief, offset)
+	}
+
+	/// Load a cloud of `x y elevation` points, one per line. Blank lines, `#`
+	/// comments and a first line of column names are skipped.
+	///
+	/// The points are averaged on `dims` cells covering their extent, or on
+	/// about one cell per point when `dims` is `None`, and their elevations
+	/// rescaled as described in [`HeightMap::from_elevations`]. Cells
+	/// without points are filled from their neighbours.
+	pub fn from_xyz(
+		filename: &str,
+		dims: Option<(usize, usize)>,
+		relief: Option<f32>,
+		offset: f32,
+	) -> Result<Self, String> {
+		let points = read_xyz(filename)?;
+		let elevations = grid_points(&points, dims)?;
+		HeightMap::from_elevations(elevations, dims, relief, offset)
+	}
+
+	/// Resample `elevations` on `dims` cells and bring them in the range of
+	/// the heights, the lowest at `offset`. The highest is `relief` above it,
+	/// which must be positive, or as far as the true proportions of the model
+	/// give, up to `MAX_RELIEF`. The scale used is kept in [`MapInfo::scale`], and the
+	/// cell size given to the water follows the proportions of the model.
+	///
+	/// [`MapInfo::scale`]: super::MapInfo::scale
+	fn from_elevations(
+		mut elevations: Elevations,
+		dims: Option<(usize, usize)>,
+		relief: Option<f32>,
+		offset: f32,
+	) -> Result<Self, String> {
+		fill_holes(&mut elevations)?;
+		let from = elevations.dims;
+		let dims = dims.unwrap_or_else(|| HeightMap::fit_dims(from));
+		HeightMap::check_dims(dims)?;
+		let values: Vec<f32> = elevations
+			.values
+			.iter()
+			.map(|value| value.unwrap_or_default() as f32)
+			.collect();
+		let resampled = HeightMap::resample(&values, from, dims);
+		let (min, max) = resampled
+			.iter()
+			.fold((f32::MAX, f32::MIN), |(min, max), value| {
+				(min.min(*value), max.max(*value))
+			});
+		let stretch = |from: usize, to: usize| (from - 1) as f64 / (to - 1).max(1) as f64;
+		let cell = (
+			elevations.spacing.0 * stretch(from.0, dims.0),
+			elevations.spacing.1 * stretch(from.1, dims.1),
+		);
+		let mean_cell = (cell.0 + cell.1) / 2.0;
+		let real_relief = (max - min) as f64;
+		let relief = match relief {
+			Some(relief) if relief.is_finite() && relief > 0.0 => relief as f64,
+			Some(relief) => {
+				return Err(format!(
+					"Invalid relief '{}' of the elevation model (must be positive)",
+					relief
+				))
+			}
+			None => (real_relief / mean_cell).min(MAX_RELIEF as f64),
+		};
+		// A flat model keeps its true proportions.
+		let height_unit = if real_relief > 0.0 && relief > 0.0 {
+			real_relief / relief
+		} else {
+			mean_cell
+		};
+		let heights: Vec<f32> = resampled
+			.iter()
+			.map(|value| offset + ((*value - min) as f64 / height_unit) as f32)
+			.collect();
+		let limit = MapError::MAX_HEIGHT;
+		if heights
+			.iter()
+			.any(|height| height.is_nan() || height.abs() >= limit)
+		{
+			let (low, high) = heights
+				.iter()
+				.fold((f32::MAX, f32::MIN), |(low, high), height| {
+					(low.min(*height), high.max(*height))
+				});
+			return Err(format!(
+				"Invalid heights {} to {} of the elevation model (not between -{} and {})",
+				low, high, limit, limit
+			));
+		}
+		let mut map = HeightMap::from_heights(dims, heights)?;
+		let mut cell_size = (mean_cell / height_unit) as f32;
+		if let Some((min_size, max_size)) = WaterParams::range("cell_size") {
+			cell_size = cell_size.clamp(min_size, max_size);
+		}
+		map.info_mut().cell_size = Some(cell_size);
+		map.info_mut().scale = Some(DemScale {
+			cell,
+			height_unit,
+			base: min as f64 - offset as f64 * height_unit,
+		});
+		Ok(map)
+	}
+}
+
+fn read_esri_ascii(filename: &str) -> Result<Elevations, String> {
+	let mut text = String::new();
+	File::open(filename)
+		.and_then(|mut file| file.read_to_string(&mut text))
+		.map_err(|err| format!("Failed to read file: {}", err))?;
+	parse_esri_ascii(&text)
+}
+
+fn parse_esri_ascii(text: &str) -> Result<Elevations, String> {
+	let mut lines = text.lines().enumerate().peekable();
+	let mut header: Vec<(String, f64)> = Vec::new();
+	while let Some((index_l, line)) = lines.peek() {
+		let values: Vec<&str> = line.split_ascii_whitespace().collect();
+		let key = match values.first() {
+			Some(key) if key.starts_with(|c: char| c.is_ascii_alphabetic()) => {
+				key.to_ascii_lowercase()
+			}
+			_ => break,
+		};
+		if values.len() != 2 {
+			return Err(format!(
+				"[line: {}] Header '{}' needs a single value",
+				index_l + 1,
+				key
+			));
+		}
+		let value = values[1].parse().map_err(|err| {
+			format!(
+				"[line: {}] Invalid value '{}' for {} ({})",
+				index_l + 1,
+				values[1],
+				key,
+				err
+			)
+		})?;
+		header.push((key, value));
+		lines.next();
+	}
+	let get = |keys: &[&str]| {
+		header
+			.iter()
+			.find(|(key, _)| keys.contains(&key.as_str()))
+			.map(|(_, value)| *value)
+	};
+	let mut size = [0; 2];
+	for (side, key) in size.iter_mut().zip(["ncols", "nrows"].iter()) {
+		*side = match get(&[key]) {
+			Some(value) if value >= 2.0 && value.fract() == 0.0 => value as usize,
+			Some(value) => return Err(format!("Invalid {} '{}'", key, value)),
+			None => return Err(format!("Missing {} in the header", key)),
+		};
+	}
+	for keys in [["xllcorner", "xllcenter"], ["yllcorner", "yllcenter"]].iter() {
+		if get(keys).is_none() {
+			return Err(format!("Missing {} in the header", keys.join(" or ")));
+		}
+	}
+	let cell = match get(&["cellsize"]) {
+		Some(cell) if cell > 0.0 => cell,
+		Some(cell) => return Err(format!("Invalid cellsize '{}' (must be positive)", cell)),
+		None => return Err(String::from("Missing cellsize in the header")),
+	};
+	let no_data = get(&["nodata_value"]);
+	let [width, height] = size;
+	let count = width
+		.checked_mul(height)
+		.filter(|count| *count <= MAX_SOURCE_CELLS)
+		.ok_or_else(|| {
+			format!(
+				"Invalid size {}x{} (more than {} cells)",
+				width, height, MAX_SOURCE_CELLS
+			)
+		})?;
+	// Every value takes at least a digit and a separator.
+	if count > text.len() / 2 {
+		return Err(format!(
+			"Not enough values for {} rows of {} columns",
+			height, width
+		));
+	}
+	let mut rows: Vec<Vec<Option<f64>>> = Vec::with_capacity(height);
+	let mut row = Vec::with_capacity(width);
+	for (index_l, line) in lines {
+		for value in line.split_ascii_whitespace() {
+			if rows.len() == height {
+				return Err(format!(
+					"[line: {}] Too many values for {} rows of {} columns",
+					index_l + 1,
+					height,
+					width
+				));
+			}
+			let number: f64 = value.parse().map_err(|err| {
+				format!(
+					"[line: {}] Invalid elevation '{}' ({})",
+					index_l + 1,
+					value,
+					err
+				)
+			})?;
+			row.push(Some(number).filter(|number| number.is_finite() && Some(*number) != no_data));
+			if row.len() == width {
+				rows.push(std::mem::replace(&mut row, Vec::with_capacity(width)));
+			}
+		}
+	}
+	if rows.len() < height {
+		return Err(format!(
+			"Not enough values for {} rows of {} columns",
+			height, width
+		));
+	}
+	// The file starts from the north, the grid from the south.
+	Ok(Elevations {
+		dims: (width, height),
+		values: rows.into_iter().rev().flatten().collect(),
+		spacing: (cell, cell),
+	})
+}
+
+fn read_xyz(filename: &str) -> Result<Vec<[f64; 3]>, String> {
+	let mut text = String::new();
+	File::open(filename)
+		.and_then(|mut file| file.read_to_string(&mut text))
+		.map_err(|err| format!("Failed to read file: {}", err))?;
+	let points = parse_xyz(&text)?;
+	if points.is_empty() {
+		return Err(format!("No point in '{}'", filename));
+	}
+	Ok(points)
+}
+
+/// Points of an XYZ file, which can start with a single line of column names.
+fn parse_xyz(text: &str) -> Result<Vec<[f64; 3]>, String> {
+	let mut points = Vec::new();
+	let mut header = true;
+	for (index_l, line) in text.lines().enumerate() {
+		let content = line.split('#').next().unwrap_or_default();
+		let values: Vec<&str> = content.split_ascii_whitespace().collect();
+		if values.is_empty() {
+			continue;
+		}
+		if std::mem::replace(&mut header, false) && values[0].parse::<f64>().is_err() {
+			// Column names.
+			continue;
+		}
+		if values.len() != 3 {
+			return Err(format!(
+				"[line: {}] Point needs x, y and elevation values",
+				index_l + 1
+			));
+		}
+		let mut point = [0.0; 3];
+		for (index_v, value) in values.iter().enumerate() {
+			point[index_v] = value
+				.parse()
+				.ok()
+				.filter(|number: &f64| number.is_finite())
+				.ok_or_else(|| {
+					format!(
+						"[line: {}, pos: {}] Invalid value '{}'",
+						index_l + 1,
+						index_v + 1,
+						value
+					)
+				})?;
+		}
+		points.push(point);
+	}
+	Ok(points)
+}
+
+/// Average `points` on the cells of a grid of `dims` cells covering their
+/// extent, or of about one cell per point.
+fn grid_points(points: &[[f64; 3]], dims: Option<(usize, usize)>) -> Result<Elevations, String> {
+	let (mut min, mut max) = ([f64::MAX; 2], [f64::MIN; 2]);
+	for point in points.iter() {
+		for k in 0..2 {
+			min[k] = min[k].min(point[k]);
+			max[k] = max[k].max(point[k]);
+		}
+	}
+	let extent = [max[0] - min[0], max[1] - min[1]];
+	if extent[0] <= 0.0 || extent[1] <= 0.0 {
+		return Err(String::from("Points do not span an area"));
+	}
+	let dims = dims.unwrap_or_else(|| {
+		let aspect = extent[0] / extent[1];
+		let count = points.len() as f64;
+		let side = |side: f64| (side.round() as usize).max(2);
+		HeightMap::fit_dims((side((count * aspect).sqrt()), side((count / aspect).sqrt())))
+	});
+	HeightMap::check_dims(dims)?;
+	let spacing = (
+		extent[0] / (dims.0 - 1) as f64,
+		extent[1] / (dims.1 - 1) as f64,
+	);
+	let mut sums = vec![(0.0, 0); dims.0 * dims.1];
+	for point in points.iter() {
+		let x = ((point[0] - min[0]) / spacing.0).round() as usize;
+		let y = ((point[1] - min[1]) / spacing.1).round() as usize;
+		let sum = &mut sums[x.min(dims.0 - 1) + y.min(dims.1 - 1) * dims.0];
+		sum.0 += point[2];
+		sum.1 += 1;
+	}
+	Ok(Elevations {
+		dims,
+		values: sums
+			.into_iter()
+			.map(|(sum, count)| Some(sum / count as f64).filter(|_| count > 0))
+			.collect(),
+		spacing,
+	})
+}
+
+/// Give the cells without data the mean of their neighbours with data, and
+/// again until every cell has some. Each front of holes is filled at once
+/// from the cells filled before it, then leads to the holes around it, so
+/// every cell is only visited once.
+fn fill_holes(elevations: &mut Elevations) -> Result<(), String> {
+	if elevations.values.iter().all(Option::is_none) {
+		return Err(String::from("No elevation data"));
+	}
+	let (width, height) = elevations.dims;
+	let neighbours = move |index: usize| {
+		let (x, y) = (index % width, index / width);
+		(y.saturating_sub(1)..(y + 2).min(height)).flat_map(move |ny| {
+			(x.saturating_sub(1)..(x + 2).min(width)).map(move |nx| nx + ny * width)
+		})
+	};
+	let values = &mut elevations.values;
+	let mut queued: Vec<bool> = values.iter().map(Option::is_some).collect();
+	let mut front: Vec<usize> = (0..values.len())
+		.filter(|index| values[*index].is_none())
+		.filter(|index| neighbours(*index).any(|next| values[next].is_some()))
+		.collect();
+	for index in front.iter() {
+		queued[*index] = true;
+	}
+	while !front.is_empty() {
+		let means: Vec<f64> = front
+			.iter()
+			.map(|index| {
+				let (mut sum, mut count) = (0.0, 0);
+				for value in neighbours(*index).filter_map(|next| values[next]) {
+					sum += value;
+					count += 1;
+				}
+				sum / count as f64
+			})
+			.collect();
+		for (index, mean) in front.iter().zip(means) {
+			values[*index] = Some(mean);
+		}
+		let mut next_front = Vec::new();
+		for index in front.iter() {
+			for next in neighbours(*index) {
+				if !queued[next] {
+					queued[next] = true;
+					next_front.push(next);
+				}
+			}
+		}
+		front = next_front;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const HEADER: &str = "xllcorner 0\nyllcorner 0\ncellsize 10\nNODATA_value -9999\n";
+
+	#[test]
+	fn parses_rows_from_the_north() {
+		let text = format!("ncols 2\nnrows 2\n{}1 2\n3 -9999\n", HEADER);
+		let elevations = parse_esri_ascii(&text).unwrap();
+		assert_eq!(elevations.dims, (2, 2));
+		assert_eq!(elevations.values, [Some(3.0), None, Some(1.0), Some(2.0)]);
+	}
+
+	#[test]
+	fn reads_xyz_after_a_single_header() {
+		let points = parse_xyz("# Survey\nx y z\n\n1 2 3 # First\n4.5 -6 7e2\n");
+		assert_eq!(points, Ok(vec![[1.0, 2.0, 3.0], [4.5, -6.0, 700.0]]));
+		assert_eq!(parse_xyz("1 2 3\n"), Ok(vec![[1.0, 2.0, 3.0]]));
+		assert_eq!(parse_xyz("x y z\n"), Ok(vec![]));
+		assert_eq!(
+			parse_xyz("x y z\nEasting Northing Elevation\n1 2 3\n"),
+			Err(String::from("[line: 2, pos: 1] Invalid value 'Easting'"))
+		);
+		assert_eq!(
+			parse_xyz("x y z\n1 2 3\n\nend\n"),
+			Err(String::from(
+				"[line: 4] Point needs x, y and elevation values"
+			))
+		);
+		assert_eq!(
+			parse_xyz("1 2 3\n4 five 6\n"),
+			Err(String::from("[line: 2, pos: 2] Invalid value 'five'"))
+		);
+	}
+
+	#[test]
+	fn rejects_oversized_header_before_allocating() {
+		for size in ["ncols 100000\nnrows 100000\n", "ncols 1e300\nnrows 1e300\n"].iter() {
+			let err = parse_esri_ascii(&format!("{}{}1 2 3\n", size, HEADER)).err();
+			assert!(err.unwrap_or_default().starts_with("Invalid size"));
+		}
+		let err = parse_esri_ascii(&format!("ncols 4000\nnrows 4000\n{}1 2\n", HEADER));
+		assert_eq!(
+			err.err().as_deref(),
+			Some("Not enough values for 4000 rows of 4000 columns")
+		);
+	}
+
+	fn ramp() -> Elevations {
+		Elevations {
+			dims: (2, 2),
+			values: vec![Some(100.0), Some(200.0), Some(300.0), Some(400.0)],
+			spacing: (10.0, 10.0),
+		}
+	}
+
+	#[test]
+	fn rejects_invalid_relief() {
+		for relief in [0.0, -5.0, f32::NAN, f32::INFINITY].iter() {
+			let err = HeightMap::from_elevations(ramp(), None, Some(*relief), 0.0).err();
+			assert!(err.unwrap_or_default().starts_with("Invalid relief"));
+		}
+	}
+
+	#[test]
+	fn checks_the_highest_height() {
+		let map = HeightMap::from_elevations(ramp(), None, Some(30.0), -10.0).unwrap();
+		assert_eq!(map.height_points(), [-10.0, 0.0, 10.0, 20.0]);
+		let err = HeightMap::from_elevations(ramp(), None, Some(30.0), 25.0).err();
+		assert_eq!(
+			err.as_deref(),
+			Some("Invalid heights 25 to 55 of the elevation model (not between -50 and 50)")
+		);
+	}
+
+	#[test]
+	fn fills_holes_front_by_front() {
+		let mut elevations = Elevations {
+			dims: (5, 1),
+			values: vec![Some(1.0), None, None, None, Some(5.0)],
+			spacing: (1.0, 1.0),
+		};
+		fill_holes(&mut elevations).unwrap();
+		let values: Vec<f64> = elevations.values.into_iter().flatten().collect();
+		assert_eq!(values, [1.0, 1.0, 3.0, 5.0, 5.0]);
+		elevations = Elevations {
+			dims: (2, 2),
+			values: vec![None; 4],
+			spacing: (1.0, 1.0),
+		};
+		assert_eq!(
+			fill_holes(&mut elevations),
+			Err(String::from("No elevation data"))
+		);
+	}
+}
@@ -0,0 +1,158 @@
+use super::{HeightMap, Map, Water};
+
+/// Sediment a unit of water can carry per unit of speed on a unit slope.
+const CAPACITY: f32 = 0.4;
+/// Fraction of the missing sediment picked up from the terrain per second.
+const DISSOLVE_RATE: f32 = 0.3;
+/// Fraction of the extra sediment laid down on the terrain per second.
+const DEPOSIT_RATE: f32 = 0.3;
+/// Slope used for flat cells, so still water on a plain can carry some.
+const MIN_TILT: f32 = 0.05;
+/// Deepest water taken into account, deeper water does not reach the ground.
+const MAX_SCOUR_DEPTH: f32 = 1.0;
+
+/// Hydraulic erosion driven by a [`Water`] grid: fast water picks up terrain
+/// as sediment, carries it along its pipes and lays it down where it slows.
+#[derive(Debug)]
+pub struct Erosion {
+	width: usize,
+	height: usize,
+	sediment: Map,
+	eroded: f64,
+	deposited: f64,
+}
+
+impl Erosion {
+	/// Create a grid of `dims` cells without any sediment, matching the water
+	/// and terrain it will work on.
+	pub fn new(dims: (usize, usize)) -> Self {
+		let (width, height) = dims;
+		Erosion {
+			width,
+			height,
+			sediment: vec![0.0; width * height],
+			eroded: 0.0,
+			deposited: 0.0,
+		}
+	}
+
+	/// Exchange sediment between the water and the terrain for `delta_time`,
+	/// then move it with the water flow. Sediment in a dry cell settles.
+	pub fn update(&mut self, delta_time: f32, water: &Water, terrain: &mut HeightMap) {
+		self.exchange(delta_time, water, terrain);
+		self.transport(delta_time, water);
+	}
+
+	fn exchange(&mut self, delta_time: f32, water: &Water, terrain: &mut HeightMap) {
+		let (dim_x, dim_y) = (self.width, self.height);
+		let tilts: Vec<f32> = (0..dim_x * dim_y)
+			.map(|index| Erosion::tilt(terrain, index % dim_x, index / dim_x))
+			.collect();
+		let heights = terrain.height_points_mut();
+		for (index, sediment) in self.sediment.iter_mut().enumerate() {
+			let (x, y) = (index % dim_x, index / dim_x);
+			let depth = water.depth(x, y);
+			if depth <= water.params().zero_depth {
+				heights[index] += *sediment;
+				self.deposited += *sediment as f64;
+				*sediment = 0.0;
+				continue;
+			}
+			let speed = water.velocity(x, y).length();
+			let capacity =
+				CAPACITY * speed * tilts[index].max(MIN_TILT) * depth.min(MAX_SCOUR_DEPTH);
+			if capacity > *sediment {
+				let amount = DISSOLVE_RATE * (capacity - *sediment) * delta_time;
+				heights[index] -= amount;
+				*sediment += amount;
+				self.eroded += amount as f64;
+			} else {
+				let amount = DEPOSIT_RATE * (*sediment - capacity) * delta_time;
+				heights[index] += amount;
+				*sediment -= amount;
+				self.deposited += amount as f64;
+			}
+		}
+	}
+
+	/// Move the sediment of each cell along the pipes draining it, in
+	/// proportion to the water leaving the cell. Sediment carried off the grid
+	/// is lost.
+	fn transport(&mut self, delta_time: f32, water: &Water) {
+		let mut leaving = vec![0.0; self.sediment.len()];
+		water.visit_outflows(|from, _, flow| leaving[from] += flow * delta_time);
+		// Sediment per unit of volume leaving, a cell can not send more water
+		// than the volume it holds.
+		let area = water.params().cell_size * water.params().cell_size;
+		let shares: Vec<f32> = self
+			.sediment
+			.iter()
+			.zip(leaving.iter())
+			.zip(water.depths().iter())
+			.map(|((sediment, leaving), depth)| {
+				if *leaving > 0.0 {
+					sediment / f32::max(depth * area, *leaving)
+				} else {
+					0.0
+				}
+			})
+			.collect();
+		let sediment = &mut self.sediment;
+		water.visit_outflows(|from, to, flow| {
+			let moved = flow * delta_time * shares[from];
+			sediment[from] -= moved;
+			if let Some(to) = to {
+				sediment[to] += moved;
+			}
+		});
+		for value in sediment.iter_mut() {
+			*value = value.max(0.0);
+		}
+	}
+
+	/// Sine of the terrain slope at `(x, y)`.
+	fn tilt(terrain: &HeightMap, x: usize, y: usize) -> f32 {
+		let (dim_x, dim_y) = terrain.dims();
+		let heights = terrain.height_points();
+		let at = |x: usize, y: usize| heights[x + y * dim_x];
+		let dx = (at((x + 1).min(dim_x - 1), y) - at(x.saturating_sub(1), y)) / 2.0;
+		let dy = (at(x, (y + 1).min(dim_y - 1)) - at(x, y.saturating_sub(1))) / 2.0;
+		let gradient = (dx * dx + dy * dy).sqrt();
+		gradient / (1.0 + gradient * gradient).sqrt()
+	}
+
+	/// Sediment carried by the water of every cell, stored row by row.
+	pub fn sediment(&self) -> &[f32] {
+		&self.sediment
+	}
+
+	/// Total terrain height picked up by the water since the start.
+	pub fn eroded(&self) -> f64 {
+		self.eroded
+	}
+
+	/// Total terrain height laid down by the water since the start.
+	pub fn deposited(&self) -> f64 {
+		self.deposited
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::WaterParams;
+
+	#[test]
+	fn transport_moves_sediment_by_volume() {
+		// 2 units of water leave the first cell of a 4x4 area holding 16.
+		let mut water = Water::from_state((2, 1), vec![1.0, 0.0], vec![-2.0], Vec::new(), None);
+		let mut params = WaterParams::default();
+		params.set("cell_size", 4.0).unwrap();
+		water.set_params(params).unwrap();
+		let mut erosion = Erosion::new((2, 1));
+		erosion.sediment[0] = 0.8;
+		erosion.transport(1.0, &water);
+		assert!((erosion.sediment[1] - 0.1).abs() < 1e-6);
+		assert!((erosion.sediment[0] - 0.7).abs() < 1e-6);
+	}
+}
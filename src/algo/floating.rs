@@ -0,0 +1,332 @@
+use super::{Boundary, Edge, HeightMap, Water};
+
+use glam::{Vec2, Vec3};
+
+/// Mass of a unit of volume of water, masses are given in this unit.
+const WATER_DENSITY: f32 = 1.0;
+const DRAG_COEFFICIENT: f32 = 1.0;
+/// Fraction of the vertical speed lost per second when fully submerged, so
+/// the bodies stop bobbing.
+const HEAVE_DAMPING: f32 = 2.0;
+/// Friction coefficient of a body resting on the terrain.
+const GROUND_FRICTION: f32 = 0.6;
+/// Turn rate, in radians per second, of a submerged body aligning its length
+/// with the current.
+const ALIGN_RATE: f32 = 1.0;
+const MAX_SUBSTEP: f32 = 0.02;
+
+/// A rigid box floating on the water, pushed by the buoyancy of the water it
+/// displaces and dragged by the current, resting on the terrain where the
+/// water is too shallow. The water does not feel the bodies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FloatingBody {
+	/// Center of the bottom face, `x` and `y` in cells, `z` as a height.
+	position: Vec3,
+	/// Speed in terrain units per second.
+	velocity: Vec3,
+	/// Length along the heading, width and height, in terrain units.
+	size: Vec3,
+	mass: f32,
+	/// Angle of the length of the body from the `x` axis.
+	heading: f32,
+	grounded: bool,
+}
+
+impl FloatingBody {
+	/// Create a body of `size` and `mass` resting at `position`, in cells, at
+	/// the height of the water surface there.
+	pub fn new(
+		position: Vec2,
+		size: Vec3,
+		mass: f32,
+		terrain: &HeightMap,
+		water: &Water,
+	) -> Result<Self, String> {
+		if size.cmple(Vec3::ZERO).any() || !size.is_finite() {
+			return Err(format!("Invalid body size {:?}", size));
+		}
+		if mass <= 0.0 || !mass.is_finite() {
+			return Err(format!("Invalid body mass '{}'", mass));
+		}
+		let (dim_x, dim_y) = water.dims();
+		if !(0.0..=(dim_x - 1) as f32).contains(&position.x)
+			|| !(0.0..=(dim_y - 1) as f32).contains(&position.y)
+		{
+			return Err(format!(
+				"Body position {}x{} is not on the {}x{} grid",
+				position.x, position.y, dim_x, dim_y
+			));
+		}
+		let (x, y) = (position.x.round() as usize, position.y.round() as usize);
+		Ok(FloatingBody {
+			position: position.extend(water.get_height(terrain, x, y)),
+			velocity: Vec3::ZERO,
+			size,
+			mass,
+			heading: 0.0,
+			grounded: false,
+		})
+	}
+
+	/// A wooden crate of one cubic unit, floating half submerged.
+	pub fn crate_box(position: Vec2, terrain: &HeightMap, water: &Water) -> Result<Self, String> {
+		FloatingBody::new(position, Vec3::ONE, 0.5, terrain, water)
+	}
+
+	/// A long log, floating with most of its thickness under water.
+	pub fn log(position: Vec2, terrain: &HeightMap, water: &Water) -> Result<Self, String> {
+		let size = Vec3::new(3.0, 0.6, 0.6);
+		FloatingBody::new(
+			position,
+			size,
+			0.7 * size.x * size.y * size.z,
+			terrain,
+			water,
+		)
+	}
+
+	pub fn position(&self) -> Vec3 {
+		self.position
+	}
+
+	pub fn velocity(&self) -> Vec3 {
+		self.velocity
+	}
+
+	pub fn size(&self) -> Vec3 {
+		self.size
+	}
+
+	pub fn mass(&self) -> f32 {
+		self.mass
+	}
+
+	pub fn heading(&self) -> f32 {
+		self.heading
+	}
+
+	/// The body rests on the terrain.
+	pub fn is_grounded(&self) -> bool {
+		self.grounded
+	}
+
+	/// Move the body for `delta`. Returns `false` once it left the grid
+	/// through an open or reservoir edge, closed edges bounce it back.
+	pub fn update(&mut self, delta: f32, terrain: &HeightMap, water: &Water) -> bool {
+		let substeps = (delta / MAX_SUBSTEP).ceil().max(1.0);
+		for _ in 0..substeps as u32 {
+			self.substep(delta / substeps, terrain, water);
+			if !self.wrap(water) {
+				return false;
+			}
+		}
+		true
+	}
+
+	fn substep(&mut self, delta: f32, terrain: &HeightMap, water: &Water) {
+		let params = water.params();
+		let footprint = self.footprint(params.cell_size);
+		let (mut ground, mut surface, mut flow) = (f32::MIN, 0.0, Vec2::ZERO);
+		for point in footprint.iter() {
+			let (x, y) = self.cell(*point, water);
+			ground = ground.max(terrain.height_points()[x + y * water.dims().0]);
+			surface += water.get_height(terrain, x, y) / footprint.len() as f32;
+			flow += water.velocity_at(*point) / footprint.len() as f32;
+		}
+		let draft = (surface - self.position.z).clamp(0.0, self.size.z);
+		let submerged = draft / self.size.z;
+
+		// Buoyancy of the displaced water against the weight of the body.
+		let buoyancy = WATER_DENSITY * self.size.x * self.size.y * draft * params.gravity;
+		let weight = self.mass * params.gravity;
+		self.velocity.z += (buoyancy - weight) / self.mass * delta;
+		self.velocity.z /= 1.0 + HEAVE_DAMPING * submerged * delta;
+
+		// Quadratic drag pulling the body to the speed of the current, solved
+		// implicitly so it never overshoots.
+		let area = draft * (self.size.x + self.size.y) / 2.0;
+		let drag = 0.5 * WATER_DENSITY * DRAG_COEFFICIENT * area / self.mass;
+		let relative = self.velocity.truncate() - flow;
+		let relative = relative / (1.0 + drag * relative.length() * delta);
+		let mut horizontal = flow + relative;
+
+		self.position.z += self.velocity.z * delta;
+		self.grounded = self.position.z <= ground;
+		if self.grounded {
+			self.position.z = ground;
+			self.velocity.z = self.velocity.z.max(0.0);
+			// The terrain carries the weight the water does not.
+			let support = (1.0 - buoyancy / weight).max(0.0);
+			let speed = horizontal.length();
+			let friction = GROUND_FRICTION * params.gravity * support * delta;
+			horizontal *= (speed - friction).max(0.0) / speed.max(f32::EPSILON);
+		}
+		self.velocity = horizontal.extend(self.velocity.z);
+		let step = horizontal * delta / params.cell_size;
+		self.position += step.extend(0.0);
+
+		// A submerged body turns its length along the current.
+		if submerged > 0.0 && flow.length() > f32::EPSILON {
+			let turn = wrap_half_turn(flow.y.atan2(flow.x) - self.heading);
+			let max_turn = ALIGN_RATE * submerged * delta;
+			self.heading += turn.clamp(-max_turn, max_turn);
+		}
+	}
+
+	/// Corners of the body on a grid of cells of `cell_size`, `x` and `y` in
+	/// cells and `z` as a height: the bottom face then the top face, both
+	/// going around counterclockwise.
+	pub fn corners(&self, cell_size: f32) -> [Vec3; 8] {
+		let footprint = self.footprint(cell_size);
+		let mut corners = [Vec3::ZERO; 8];
+		for (k, corner) in footprint[1..].iter().enumerate() {
+			corners[k] = corner.extend(self.position.z);
+			corners[k + 4] = corner.extend(self.position.z + self.size.z);
+		}
+		corners
+	}
+
+	/// Center and corners of the bottom face, in cells.
+	fn footprint(&self, cell_size: f32) -> [Vec2; 5] {
+		let (sin, cos) = self.heading.sin_cos();
+		let length = Vec2::new(cos, sin) * self.size.x / (2.0 * cell_size);
+		let width = Vec2::new(-sin, cos) * self.size.y / (2.0 * cell_size);
+		let center = self.position.truncate();
+		[
+			center,
+			center + length + width,
+			center - length + width,
+			center - length - width,
+			center + length - width,
+		]
+	}
+
+	fn cell(&self, point: Vec2, water: &Water) -> (usize, usize) {
+		let (dim_x, dim_y) = water.dims();
+		(
+			(point.x.round().max(0.0) as usize).min(dim_x - 1),
+			(point.y.round().max(0.0) as usize).min(dim_y - 1),
+		)
+	}
+
+	/// Bring the body back from the other side of periodic edges and bounce
+	/// it off closed ones, returns `false` if it left the grid through an
+	/// open or reservoir edge.
+	fn wrap(&mut self, water: &Water) -> bool {
+		let (dim_x, dim_y) = water.dims();
+		let boundaries = water.boundaries();
+		let mut position = self.position.truncate();
+		let mut velocity = self.velocity.truncate();
+		let mut on_grid = true;
+		let axes = [
+			(dim_x, Edge::West, Edge::East),
+			(dim_y, Edge::South, Edge::North),
+		];
+		for (k, (dim, low, high)) in axes.iter().enumerate() {
+			let (min, max) = (-0.5, *dim as f32 - 0.5);
+			if boundaries.get(*high) == Boundary::Periodic {
+				position[k] = (position[k] - min).rem_euclid(max - min) + min;
+				continue;
+			}
+			let (edge, side) = if position[k] < min {
+				(*low, min)
+			} else if position[k] >= max {
+				(*high, max)
+			} else {
+				continue;
+			};
+			if boundaries.get(edge) == Boundary::Closed {
+				position[k] = (2.0 * side - position[k]).clamp(min, max);
+				velocity[k] = -velocity[k];
+			} else {
+				on_grid = false;
+			}
+		}
+		self.position = position.extend(self.position.z);
+		self.velocity = velocity.extend(self.velocity.z);
+		on_grid
+	}
+}
+
+/// Bring `angle` between `-PI / 2` and `PI / 2`, a body looking the same
+/// when turned around.
+fn wrap_half_turn(angle: f32) -> f32 {
+	use std::f32::consts::{FRAC_PI_2, PI};
+	(angle + FRAC_PI_2).rem_euclid(PI) - FRAC_PI_2
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algo::Boundaries;
+
+	/// A crate drifting towards the east edge of a still pond, when the east
+	/// and west edges are `boundary`.
+	fn drift(boundary: Boundary) -> (FloatingBody, Water) {
+		let terrain = HeightMap::from_heights((8, 4), vec![0.0; 32]).unwrap();
+		let mut water = Water::new((8, 4));
+		for y in 0..4 {
+			for x in 0..8 {
+				water.add_depth(x, y, 2.0);
+			}
+		}
+		let mut boundaries = Boundaries::default();
+		boundaries.set(Edge::West, boundary);
+		boundaries.set(Edge::East, boundary);
+		water.set_boundaries(boundaries).unwrap();
+		let mut body = FloatingBody::crate_box(Vec2::new(3.5, 1.5), &terrain, &water).unwrap();
+		body.position.x = 7.4;
+		body.velocity.x = 5.0;
+		(body, water)
+	}
+
+	#[test]
+	fn closed_edges_bounce_bodies_back() {
+		let (mut body, water) = drift(Boundary::Closed);
+		assert!(body.wrap(&water));
+		body.position.x = 7.7;
+		assert!(body.wrap(&water));
+		assert!((body.position.x - 7.3).abs() < 1e-6);
+		assert_eq!(body.velocity.x, -5.0);
+		body.position.x = -0.6;
+		assert!(body.wrap(&water));
+		assert!((body.position.x + 0.4).abs() < 1e-6);
+		assert_eq!(body.velocity.x, 5.0);
+	}
+
+	#[test]
+	fn open_and_reservoir_edges_take_bodies_away() {
+		for boundary in [Boundary::Open, Boundary::Reservoir(2.0)].iter() {
+			let (mut body, water) = drift(*boundary);
+			body.position.x = 7.7;
+			assert!(!body.wrap(&water), "{:?}", boundary);
+			let (mut body, water) = drift(*boundary);
+			body.position.x = -0.6;
+			assert!(!body.wrap(&water), "{:?}", boundary);
+		}
+	}
+
+	#[test]
+	fn periodic_edges_bring_bodies_back_from_the_other_side() {
+		let (mut body, water) = drift(Boundary::Periodic);
+		body.position.x = 7.7;
+		assert!(body.wrap(&water));
+		assert!((body.position.x + 0.3).abs() < 1e-6);
+		body.position.x = -0.6;
+		assert!(body.wrap(&water));
+		assert!((body.position.x - 7.4).abs() < 1e-6);
+		assert_eq!(body.velocity.x, 5.0);
+	}
+
+	#[test]
+	fn drifting_body_stays_on_a_closed_grid() {
+		let terrain = HeightMap::from_heights((8, 4), vec![0.0; 32]).unwrap();
+		let (mut body, water) = drift(Boundary::Closed);
+		for _ in 0..50 {
+			assert!(body.update(0.1, &terrain, &water));
+			assert!((-0.5..=7.5).contains(&body.position.x));
+		}
+		let (mut body, water) = drift(Boundary::Open);
+		assert!((0..50).any(|_| !body.update(0.1, &terrain, &water)));
+	}
+}
@@ -1,111 +1,524 @@
-use super::{Map, DIM};
-use crate::engine::{Entity, EntityStore, Mesh};
+use super::image::load_image;
+use super::map_error::{strip_comment, tokens};
+use super::rows::rows_mut;
+use super::{
+	Boundaries, Interpolation, Location, Map, MapError, MapInfo, MapReport, MapWarning, Sea,
+	Source, DEFAULT_DIM, FORMAT_VERSION, MAX_DIM, MIN_DIM,
+};
 
 use glam::{Vec2, Vec3};
-use std::any::Any;
+use std::collections::hash_map::{Entry, HashMap};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
 const WEIGHT_DIST: f32 = 5.0;
-const MAP_SIZE: usize = DIM * DIM;
 const MAP_SCALE: f32 = 1.0;
+/// Edge cells on each side of an inner cell whose weight is summed one by one,
+/// the others are integrated.
+const BORDER_WINDOW: f64 = 8.0;
+
+/// Content of a map file, in its own coordinates.
+struct MapFile {
+	dims: (usize, usize),
+	points: Vec<Vec3>,
+	boundaries: Boundaries,
+	sources: Vec<Source>,
+	sea: Sea,
+	info: MapInfo,
+}
+
+impl Default for MapFile {
+	fn default() -> Self {
+		MapFile {
+			dims: (DEFAULT_DIM, DEFAULT_DIM),
+			points: Vec::new(),
+			boundaries: Boundaries::default(),
+			sources: Vec::new(),
+			sea: Sea::default(),
+			info: MapInfo::default(),
+		}
+	}
+}
 
 #[derive(Debug)]
 pub struct HeightMap {
-	points: Map<MAP_SIZE>,
+	width: usize,
+	height: usize,
+	points: Map,
+	boundaries: Boundaries,
+	sources: Vec<Source>,
+	sea: Sea,
+	info: MapInfo,
 }
 
 impl HeightMap {
-	pub fn new(filename: &str) -> Result<Self, String> {
-		let mut poi: Vec<Vec3> = Vec::new();
-		let file = File::open(filename).map_err(|err| format!("Failed to read file: {}", err))?;
-		let reader = BufReader::new(file);
+	/// Load a map file and interpolate it on a grid of `dims` cells, or on the
+	/// size declared by the file when `dims` is `None`.
+	///
+	/// Before its points, the file can declare its size with a `size <width>
+	/// <height>` line and the boundary of the water on its edges with
+	/// `boundary <edge> <boundary>` lines. It can also declare named water
+	/// sources with `<spring|inlet|drain> <name> <x> <y> <rate> [repeat]` lines
+	/// and the sea tides fill with a `sea <level> [<x> <y>]` line.
+	///
+	/// The header can also give the format version with a first `mod1
+	/// <version>` line, and the [`MapInfo`] of the map with `name <text>`,
+	/// `description <text>`, `cell_size <size>`, `height_range <min> <max>`
+	/// and `interpolation <idw|nearest>` lines. Blank lines are skipped and
+	/// `#` starts a comment running to the end of the line.
+	pub fn new(filename: &str, dims: Option<(usize, usize)>) -> Result<Self, MapError> {
+		let mut report = MapReport::default();
+		let file = HeightMap::read(filename, &mut report);
+		if let Some(error) = report.errors.into_iter().next() {
+			return Err(error);
+		}
+		let (width, height) = dims.unwrap_or(file.dims);
+		HeightMap::check_dims((width, height)).map_err(|message| MapError::InvalidMap {
+			path: String::from(filename),
+			message,
+		})?;
+		let scaled = HeightMap::scale_points(file.points, file.dims, (width, height));
+		let map = HeightMap::poi_to_map(scaled, width, height, file.info.interpolation);
+		let mut sources = file.sources;
+		for source in sources.iter_mut() {
+			source.scale(file.dims, (width, height));
+		}
+		let mut sea = file.sea;
+		sea.scale(file.dims, (width, height));
+		Ok(HeightMap {
+			width,
+			height,
+			points: map,
+			boundaries: file.boundaries,
+			sources,
+			sea,
+			info: file.info,
+		})
+	}
+
+	/// Validate a map file without interpolating it, collecting all its errors
+	/// and warnings instead of stopping at the first error.
+	pub fn check(filename: &str) -> MapReport {
+		let mut report = MapReport::default();
+		HeightMap::read(filename, &mut report);
+		report
+	}
+
+	/// Parse a map file, adding its errors and warnings to `report` and going
+	/// on with the next line after an invalid one.
+	fn read(filename: &str, report: &mut MapReport) -> MapFile {
+		match File::open(filename) {
+			Ok(handle) => HeightMap::read_lines(filename, BufReader::new(handle), report),
+			Err(error) => {
+				report.errors.push(MapError::Io {
+					path: String::from(filename),
+					error,
+				});
+				MapFile::default()
+			}
+		}
+	}
+
+	/// Parse the lines of the map file `filename` from `reader`, like
+	/// [`HeightMap::read`].
+	fn read_lines(filename: &str, reader: impl BufRead, report: &mut MapReport) -> MapFile {
+		let mut file = MapFile::default();
+		let io_error = |error| MapError::Io {
+			path: String::from(filename),
+			error,
+		};
+		// Line of the first point declared at each coordinates.
+		let mut lines: HashMap<(u32, u32), usize> = HashMap::new();
+		let mut first = true;
 		for (index_l, line) in reader.lines().enumerate() {
-			let line = line.map_err(|err| format!("Failed to read file: {}", err))?;
-			let mut point: [f32; 3] = [0.0; 3];
-			let mut count = 0;
-			for (index_v, value) in line.split_ascii_whitespace().enumerate() {
-				count += 1;
-				if count > 3 {
-					return Err(format!("[line: {}] Too many values", index_l + 1));
+			let line = match line {
+				Ok(line) => line,
+				Err(error) => {
+					report.errors.push(io_error(error));
+					break;
 				}
-				point[index_v] = value
-					.parse()
-					.map_err(|err| {
-						format!(
-							"[line: {}, pos: {}] Invalid value '{}' ({})",
-							index_l + 1,
-							index_v + 1,
-							value,
-							err
-						)
-					})
-					.and_then(|number| {
-						if index_v < 2 {
-							match number {
-								n if n < 100.0 && n > 0.0 => Ok(n),
-								n => Err(format!(
-								"[line: {}, pos: {}] Invalid coordinate number '{}' (not between 0 and 99)",
-								index_l + 1, index_v + 1, n
-							)),
-							}
-						} else {
-							match number {
-								n if n < 50.0 && n > -50.0 => Ok(n),
-								n => Err(format!(
-								"[line: {}, pos: {}] Invalid height number '{}' (not between -50 and 50)",
-								index_l + 1, index_v + 1, n
-							)),
-							}
-						}
-					})?;
-			}
-			if count < 3 {
-				return Err(format!("[line: {}] Not enough values", index_l + 1));
-			}
-			poi.push(Vec3::from(point));
-		}
-		let scaled = poi.into_iter().map(|pt| pt * MAP_SCALE).collect();
-		let map = HeightMap::poi_to_map(scaled);
-		Ok(HeightMap { points: map })
-	}
-
-	pub fn height_points(&self) -> &Map<MAP_SIZE> {
+			};
+			if strip_comment(&line).trim().is_empty() {
+				continue;
+			}
+			let count = file.points.len();
+			let read = HeightMap::read_line(filename, index_l + 1, &line, first, &mut file);
+			first = false;
+			if let Err(error) = read {
+				// The rest of a file in an unknown version can not be trusted to
+				// mean anything, its errors would only be noise.
+				let stop = matches!(error, MapError::UnsupportedVersion { .. });
+				report.errors.push(error);
+				if stop {
+					return file;
+				}
+				continue;
+			}
+			let point = match file.points.get(count) {
+				Some(point) => *point,
+				None => continue,
+			};
+			let location = Location::line(filename, index_l + 1, &line);
+			match lines.entry((point.x.to_bits(), point.y.to_bits())) {
+				Entry::Occupied(first) => report.warnings.push(MapWarning::DuplicatePoint {
+					location: location.clone(),
+					first_line: *first.get(),
+				}),
+				Entry::Vacant(entry) => {
+					entry.insert(index_l + 1);
+				}
+			}
+			let (max_x, max_y) = ((file.dims.0 - 1) as f32, (file.dims.1 - 1) as f32);
+			if point.z != 0.0 && (point.x >= max_x || point.y >= max_y) {
+				report.warnings.push(MapWarning::PointOnBorder(location));
+			}
+		}
+		if let Err(message) = file.boundaries.check() {
+			report.errors.push(MapError::InvalidMap {
+				path: String::from(filename),
+				message,
+			});
+		}
+		if file.points.is_empty() {
+			report.warnings.push(MapWarning::NoPoints {
+				path: String::from(filename),
+			});
+		}
+		file
+	}
+
+	/// Parse the line `index_l` of a map file into `file`, `first` telling if
+	/// it is the first line besides blank lines and comments.
+	fn read_line(
+		filename: &str,
+		index_l: usize,
+		text: &str,
+		first: bool,
+		file: &mut MapFile,
+	) -> Result<(), MapError> {
+		let directive = |message| MapError::InvalidDirective {
+			location: Location::line(filename, index_l, text),
+			message,
+		};
+		let line = strip_comment(text);
+		let header = file.points.is_empty();
+		if header && line.trim_start().starts_with("size") {
+			file.dims = HeightMap::parse_size(line).map_err(directive)?;
+			return Ok(());
+		}
+		if header && line.trim_start().starts_with("boundary") {
+			return HeightMap::parse_boundary(line, &mut file.boundaries).map_err(directive);
+		}
+		let keyword = line.split_ascii_whitespace().next().unwrap_or_default();
+		if header && MapInfo::KEYWORDS.contains(&keyword) {
+			file.info.parse(keyword, line, first).map_err(directive)?;
+			let version = file.info.version;
+			if keyword == "mod1" && (version == 0 || version > FORMAT_VERSION) {
+				let (column, token) = tokens(line)[1];
+				return Err(MapError::UnsupportedVersion {
+					location: Location::new(filename, index_l, text, column, token),
+					version,
+				});
+			}
+			return Ok(());
+		}
+		if header && keyword == "sea" {
+			file.sea = Sea::parse(line, file.dims).map_err(directive)?;
+			return Ok(());
+		}
+		if header && ["spring", "inlet", "drain"].contains(&keyword) {
+			let source = Source::parse(line, file.dims).map_err(directive)?;
+			if file
+				.sources
+				.iter()
+				.any(|other| other.name() == source.name())
+			{
+				let tokens = tokens(line);
+				let (column, name) = tokens[1];
+				return Err(MapError::DuplicateSource {
+					location: Location::new(filename, index_l, text, column, name),
+					name: String::from(name),
+				});
+			}
+			file.sources.push(source);
+			return Ok(());
+		}
+		let directives = ["size", "boundary", "sea", "spring", "inlet", "drain"];
+		if directives.contains(&keyword) || MapInfo::KEYWORDS.contains(&keyword) {
+			let (column, token) = tokens(line)[0];
+			return Err(MapError::InvalidDirective {
+				location: Location::new(filename, index_l, text, column, token),
+				message: format!("The {} directive must come before the points", keyword),
+			});
+		}
+		let point = HeightMap::parse_point(filename, index_l, text, file)?;
+		file.points.push(point);
+		Ok(())
+	}
+
+	/// Parse the `x y height` point on the line `index_l` of a map file with
+	/// the header of `file`.
+	fn parse_point(
+		filename: &str,
+		index_l: usize,
+		text: &str,
+		file: &MapFile,
+	) -> Result<Vec3, MapError> {
+		let line = strip_comment(text);
+		let tokens = tokens(line);
+		let at =
+			|(column, token): (usize, &str)| Location::new(filename, index_l, text, column, token);
+		if tokens.len() > 3 {
+			return Err(MapError::TooManyValues(at(tokens[3])));
+		}
+		if tokens.len() < 3 {
+			let end = line.trim_end().chars().count() + 1;
+			return Err(MapError::NotEnoughValues(at((end, ""))));
+		}
+		let mut point: [f32; 3] = [0.0; 3];
+		for (index_v, token) in tokens.iter().enumerate() {
+			let number: f32 = token.1.parse().map_err(|error| MapError::InvalidNumber {
+				location: at(*token),
+				error,
+			})?;
+			if index_v < 2 {
+				let max = if index_v == 0 {
+					file.dims.0
+				} else {
+					file.dims.1
+				};
+				if !(number < max as f32 && number > 0.0) {
+					return Err(MapError::CoordinateOutOfRange {
+						location: at(*token),
+						value: number,
+						max: max - 1,
+					});
+				}
+			} else if !HeightMap::in_range(number, file.info.height_range) {
+				return Err(MapError::HeightOutOfRange {
+					location: at(*token),
+					value: number,
+					range: file.info.height_range,
+				});
+			}
+			point[index_v] = number;
+		}
+		Ok(Vec3::from(point))
+	}
+
+	/// Build a map from `dims.0 * dims.1` heights stored row by row.
+	pub fn from_heights(dims: (usize, usize), heights: Vec<f32>) -> Result<Self, String> {
+		HeightMap::check_dims(dims)?;
+		if heights.len() != dims.0 * dims.1 {
+			return Err(format!(
+				"Invalid height count {} for a {}x{} grid",
+				heights.len(),
+				dims.0,
+				dims.1
+			));
+		}
+		Ok(HeightMap {
+			width: dims.0,
+			height: dims.1,
+			points: heights,
+			boundaries: Boundaries::default(),
+			sources: Vec::new(),
+			sea: Sea::default(),
+			info: MapInfo::default(),
+		})
+	}
+
+	/// Load a grayscale PNG or PGM image as a dense heightmap, black pixels
+	/// at `offset` and white ones at `offset + scale`. The image is resampled
+	/// on a grid of `dims` cells, or kept at its size when `dims` is `None`,
+	/// shrunk if needed to fit in `MAX_DIM`. Its top row is the north edge.
+	pub fn from_image(
+		filename: &str,
+		dims: Option<(usize, usize)>,
+		scale: f32,
+		offset: f32,
+	) -> Result<Self, String> {
+		let limit = MapError::MAX_HEIGHT;
+		for height in [offset, offset + scale].iter() {
+			if height.is_nan() || height.abs() >= limit {
+				return Err(format!(
+					"Invalid height {} of the image levels (not between -{} and {})",
+					height, limit, limit
+				));
+			}
+		}
+		let image = load_image(filename)?;
+		let image_dims = (image.width, image.height);
+		let dims = dims.unwrap_or_else(|| HeightMap::fit_dims(image_dims));
+		// Images go from the top, the grid from the south edge.
+		let mut levels = Vec::with_capacity(image.levels.len());
+		for row in image.levels.chunks_exact(image.width).rev() {
+			levels.extend_from_slice(row);
+		}
+		let heights = HeightMap::resample(&levels, image_dims, dims)
+			.into_iter()
+			.map(|level| offset + scale * level)
+			.collect();
+		HeightMap::from_heights(dims, heights)
+	}
+
+	/// Grid size closest to `dims` whose sides are between `MIN_DIM` and
+	/// `MAX_DIM`, keeping its proportions when it has to shrink.
+	pub(crate) fn fit_dims(dims: (usize, usize)) -> (usize, usize) {
+		let largest = dims.0.max(dims.1);
+		let fit = |side: usize| {
+			let side = if largest > MAX_DIM {
+				(side as f64 * MAX_DIM as f64 / largest as f64).round() as usize
+			} else {
+				side
+			};
+			side.max(MIN_DIM)
+		};
+		(fit(dims.0), fit(dims.1))
+	}
+
+	/// Bilinear interpolation of the `from` grid of `values`, stored row by
+	/// row, on a `to` grid covering the same extent.
+	pub(crate) fn resample(values: &[f32], from: (usize, usize), to: (usize, usize)) -> Map {
+		let step = |from: usize, to: usize| {
+			if to > 1 {
+				(from - 1) as f32 / (to - 1) as f32
+			} else {
+				0.0
+			}
+		};
+		let (step_x, step_y) = (step(from.0, to.0), step(from.1, to.1));
+		let mut map = Vec::with_capacity(to.0 * to.1);
+		for j in 0..to.1 {
+			let y = j as f32 * step_y;
+			let (y0, ty) = (y.floor() as usize, y.fract());
+			let y1 = (y0 + 1).min(from.1 - 1);
+			for i in 0..to.0 {
+				let x = i as f32 * step_x;
+				let (x0, tx) = (x.floor() as usize, x.fract());
+				let x1 = (x0 + 1).min(from.0 - 1);
+				let value = |x: usize, y: usize| values[x + y * from.0];
+				let south = value(x0, y0) * (1.0 - tx) + value(x1, y0) * tx;
+				let north = value(x0, y1) * (1.0 - tx) + value(x1, y1) * tx;
+				map.push(south * (1.0 - ty) + north * ty);
+			}
+		}
+		map
+	}
+
+	/// `height` is in the declared `(min, max)` range, and strictly between
+	/// the limits of the format.
+	fn in_range(height: f32, range: (f32, f32)) -> bool {
+		let limit = MapError::MAX_HEIGHT;
+		height > -limit && height < limit && (range.0..=range.1).contains(&height)
+	}
+
+	/// Move points given in the coordinates of a `file_dims` grid to the
+	/// coordinates of a `dims` grid.
+	pub(crate) fn scale_points(
+		poi: Vec<Vec3>,
+		file_dims: (usize, usize),
+		dims: (usize, usize),
+	) -> Vec<Vec3> {
+		let scale = Vec3::new(
+			(dims.0 - 1) as f32 / (file_dims.0 - 1) as f32,
+			(dims.1 - 1) as f32 / (file_dims.1 - 1) as f32,
+			1.0,
+		) * MAP_SCALE;
+		poi.into_iter().map(|pt| pt * scale).collect()
+	}
+
+	pub(crate) fn parse_size(line: &str) -> Result<(usize, usize), String> {
+		let values: Vec<&str> = line.split_ascii_whitespace().skip(1).collect();
+		if values.len() != 2 {
+			return Err(String::from("Grid size needs a width and a height"));
+		}
+		let mut dims = [0; 2];
+		for (index_v, value) in values.iter().enumerate() {
+			dims[index_v] = value
+				.parse()
+				.map_err(|err| format!("Invalid grid size '{}' ({})", value, err))?;
+		}
+		HeightMap::check_dims((dims[0], dims[1]))?;
+		Ok((dims[0], dims[1]))
+	}
+
+	fn parse_boundary(line: &str, boundaries: &mut Boundaries) -> Result<(), String> {
+		let values: Vec<&str> = line.split_ascii_whitespace().skip(1).collect();
+		if values.len() != 2 {
+			return Err(String::from("Boundary needs an edge and a boundary"));
+		}
+		boundaries.apply(values[0], values[1])
+	}
+
+	pub(crate) fn check_dims(dims: (usize, usize)) -> Result<(), String> {
+		match dims {
+			(w, h) if w < MIN_DIM || h < MIN_DIM || w > MAX_DIM || h > MAX_DIM => Err(format!(
+				"Invalid grid size {}x{} (each side must be between {} and {})",
+				w, h, MIN_DIM, MAX_DIM
+			)),
+			_ => Ok(()),
+		}
+	}
+
+	/// Boundaries declared by the map file, all closed if it declares none.
+	pub fn boundaries(&self) -> &Boundaries {
+		&self.boundaries
+	}
+
+	/// Water sources and drains declared by the map file.
+	pub fn sources(&self) -> &[Source] {
+		&self.sources
+	}
+
+	/// Sea declared by the map file, at height 0 and reached from the border if
+	/// it declares none.
+	pub fn sea(&self) -> &Sea {
+		&self.sea
+	}
+
+	/// Version, name, description and settings declared by the map file.
+	pub fn info(&self) -> &MapInfo {
+		&self.info
+	}
+
+	pub(crate) fn info_mut(&mut self) -> &mut MapInfo {
+		&mut self.info
+	}
+
+	pub fn height_points(&self) -> &[f32] {
 		&self.points
 	}
 
+	pub(crate) fn height_points_mut(&mut self) -> &mut [f32] {
+		&mut self.points
+	}
+
+	pub fn dims(&self) -> (usize, usize) {
+		(self.width, self.height)
+	}
+
 	pub fn border_wall(&self) -> Vec<Vec3> {
-		let dim = (DIM - 1) as f32;
+		let dim_x = (self.width - 1) as f32;
+		let dim_y = (self.height - 1) as f32;
 		vec![
 			Vec3::new(0.0, 0.0, -50.0),
 			Vec3::new(0.0, 0.0, 0.0),
-			Vec3::new(dim, 0.0, -50.0),
-			Vec3::new(dim, 0.0, 0.0),
-			Vec3::new(dim, dim, -50.0),
-			Vec3::new(dim, dim, 0.0),
-			Vec3::new(0.0, dim, -50.0),
-			Vec3::new(0.0, dim, 0.0),
+			Vec3::new(dim_x, 0.0, -50.0),
+			Vec3::new(dim_x, 0.0, 0.0),
+			Vec3::new(dim_x, dim_y, -50.0),
+			Vec3::new(dim_x, dim_y, 0.0),
+			Vec3::new(0.0, dim_y, -50.0),
+			Vec3::new(0.0, dim_y, 0.0),
 			Vec3::new(0.0, 0.0, -50.0),
 			Vec3::new(0.0, 0.0, 0.0),
 		]
 	}
 
-	fn add_border_zero(poi: &mut Vec<Vec3>) {
-		let max_val = (DIM - 1) as f32;
-		poi.push(Vec3::new(0.0, 0.0, 0.0));
-		poi.push(Vec3::new(0.0, max_val, 0.0));
-		poi.push(Vec3::new(max_val, 0.0, 0.0));
-		poi.push(Vec3::new(max_val, max_val, 0.0));
-		for i in 1..(DIM - 1) {
-			let variant = i as f32;
-			poi.push(Vec3::new(variant, 0.0, 0.0));
-			poi.push(Vec3::new(0.0, variant, 0.0));
-			poi.push(Vec3::new(variant, max_val, 0.0));
-			poi.push(Vec3::new(max_val, variant, 0.0));
-		}
-	}
-
-	fn idw(coord: Vec2, poi: &Vec<Vec3>) -> f32 {
+	fn idw(coord: Vec2, poi: &[Vec3], border_weight: f32) -> f32 {
 		let mut top = 0.0;
 		let mut bot = 0.0;
 		for i in poi.iter() {
@@ -115,42 +528,441 @@ impl HeightMap {
 			top += inv_dist * k_value;
 			bot += inv_dist;
 		}
-		return top / bot;
+		top / (bot + border_weight)
 	}
 
-	fn poi_to_map(mut poi: Vec<Vec3>) -> [f32; MAP_SIZE] {
-		HeightMap::add_border_zero(&mut poi);
-		let mut map = [0.0; MAP_SIZE];
-		for i in 0..DIM {
-			let x = i as f32;
-			for j in 0..DIM {
-				let y = j as f32;
-				if let Some(point) = poi.iter().find(|pt| pt.x == x && pt.y == y) {
-					map[i + j * DIM] = point.z
-				} else {
-					map[i + j * DIM] = HeightMap::idw(Vec2::new(x, y), &poi);
+	/// Weight of the cells around the edges of a `width * height` grid, all
+	/// points at height 0, seen from the inner cell `coord`.
+	fn border_weight(coord: Vec2, width: usize, height: usize) -> f32 {
+		let (max_x, max_y) = ((width - 1) as f64, (height - 1) as f64);
+		let (x, y) = (coord.x as f64, coord.y as f64);
+		// The south and north edges hold the corners.
+		let weight = HeightMap::edge_weight(x, y, 0.0, max_x)
+			+ HeightMap::edge_weight(x, max_y - y, 0.0, max_x)
+			+ HeightMap::edge_weight(y, x, 1.0, max_y - 1.0)
+			+ HeightMap::edge_weight(y, max_x - x, 1.0, max_y - 1.0);
+		weight as f32
+	}
+
+	/// Sum of the inverse distance weights of the points at `(i, 0)` for every
+	/// whole `i` from `first` to `last`, seen from `(along, across)` with
+	/// `across` at least 1. The closest points are summed one by one and the
+	/// others replaced by the integral of their weight, so the cost does not
+	/// grow with the size of the grid.
+	fn edge_weight(along: f64, across: f64, first: f64, last: f64) -> f64 {
+		if first > last {
+			return 0.0;
+		}
+		let near_first = first.max(along - BORDER_WINDOW);
+		let near_last = last.min(along + BORDER_WINDOW);
+		let across_2 = across * across;
+		let mut sum = 0.0;
+		let mut i = near_first;
+		while i <= near_last {
+			let distance_2 = (i - along) * (i - along) + across_2;
+			sum += 1.0 / (distance_2 * distance_2 * distance_2.sqrt());
+			i += 1.0;
+		}
+		// Antiderivative of the weight (u² + across²)^(-5/2), for the
+		// `WEIGHT_DIST` of 5, less the midpoint rule correction with its
+		// derivative so it matches the sum of the whole points.
+		let integral = |u: f64| {
+			let u_2 = u * u;
+			let distance_2 = u_2 + across_2;
+			let distance_3 = distance_2 * distance_2.sqrt();
+			let antiderivative =
+				u * (2.0 * u_2 + 3.0 * across_2) / (3.0 * across_2 * across_2 * distance_3);
+			let derivative = -5.0 * u / (distance_2 * distance_2 * distance_3);
+			antiderivative - derivative / 24.0
+		};
+		if near_first > first {
+			sum += integral(near_first - 0.5 - along) - integral(first - 0.5 - along);
+		}
+		if near_last < last {
+			sum += integral(last + 0.5 - along) - integral(near_last + 0.5 - along);
+		}
+		sum
+	}
+
+	/// Interpolate the points on a `width * height` grid whose edges are held
+	/// at height 0.
+	fn poi_to_map(
+		poi: Vec<Vec3>,
+		width: usize,
+		height: usize,
+		interpolation: Interpolation,
+	) -> Map {
+		match interpolation {
+			Interpolation::InverseDistance => HeightMap::weighted(&poi, width, height, true),
+			Interpolation::Nearest => HeightMap::nearest(&poi, width, height),
+		}
+	}
+
+	/// Value of every cell of a `width * height` grid, taken from the closest
+	/// point or edge cell, the edges being at height 0. Points win ties, then
+	/// the first declared one.
+	fn nearest(poi: &[Vec3], width: usize, height: usize) -> Map {
+		let mut map = vec![0.0; width * height];
+		let (max_x, max_y) = (width - 1, height - 1);
+		rows_mut(&mut map, width).for_each(|(j, row)| {
+			for (i, value) in row.iter_mut().enumerate() {
+				let cell = Vec2::new(i as f32, j as f32);
+				let mut closest = (f32::INFINITY, 0.0);
+				for point in poi.iter() {
+					let distance = cell.distance_squared(point.truncate());
+					if distance < closest.0 {
+						closest = (distance, point.z);
+					}
 				}
+				let edge = i.min(j).min(max_x - i).min(max_y - j) as f32;
+				*value = if edge * edge < closest.0 {
+					0.0
+				} else {
+					closest.1
+				};
+			}
+		});
+		map
+	}
+
+	/// Value of every cell of a `width * height` grid, taken from the point at
+	/// its coordinates or interpolated from all the points.
+	pub(crate) fn interpolate(poi: &[Vec3], width: usize, height: usize) -> Map {
+		HeightMap::weighted(poi, width, height, false)
+	}
+
+	/// Interpolate the points like [`HeightMap::interpolate`], with the edges
+	/// of the grid held at height 0 when `border` is set.
+	fn weighted(poi: &[Vec3], width: usize, height: usize, border: bool) -> Map {
+		// Points right on a cell, the first declared one on duplicates.
+		let mut exact: HashMap<(usize, usize), f32> = HashMap::new();
+		for point in poi.iter() {
+			if point.x >= 0.0 && point.y >= 0.0 && point.x.fract() == 0.0 && point.y.fract() == 0.0
+			{
+				exact
+					.entry((point.x as usize, point.y as usize))
+					.or_insert(point.z);
 			}
 		}
-		return map;
+		let mut map = vec![0.0; width * height];
+		rows_mut(&mut map, width).for_each(|(j, row)| {
+			for (i, value) in row.iter_mut().enumerate() {
+				let on_edge = i == 0 || j == 0 || i == width - 1 || j == height - 1;
+				let cell = Vec2::new(i as f32, j as f32);
+				*value = match exact.get(&(i, j)) {
+					Some(z) => *z,
+					None if border && on_edge => 0.0,
+					None if border => {
+						HeightMap::idw(cell, poi, HeightMap::border_weight(cell, width, height))
+					}
+					None => HeightMap::idw(cell, poi, 0.0),
+				};
+			}
+		});
+		map
 	}
 }
 
-impl Entity for HeightMap {
-	fn as_any(&self) -> &dyn Any {
-		self
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const PATH: &str = "test.mod1";
+
+	fn check(text: &str) -> MapReport {
+		let mut report = MapReport::default();
+		HeightMap::read_lines(PATH, text.as_bytes(), &mut report);
+		report
+	}
+
+	/// The only error of `text`, with its line and column.
+	fn only_error(text: &str) -> (MapError, usize, usize) {
+		let mut report = check(text);
+		assert_eq!(report.errors.len(), 1, "{}", report.diagnostics());
+		let error = report.errors.remove(0);
+		let location = error.location().expect("no location");
+		let (line, column) = (location.line, location.column);
+		(error, line, column)
+	}
+
+	#[test]
+	fn reports_invalid_number() {
+		match only_error("10 10 5\n10 abc 5") {
+			(MapError::InvalidNumber { location, .. }, 2, 4) => assert_eq!(location.token, "abc"),
+			other => panic!("{:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_wrong_value_count() {
+		match only_error("10 10 5 3") {
+			(MapError::TooManyValues(location), 1, 9) => assert_eq!(location.token, "3"),
+			other => panic!("{:?}", other),
+		}
+		match only_error("10 10") {
+			(MapError::NotEnoughValues(_), 1, 6) => (),
+			other => panic!("{:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_coordinate_out_of_range() {
+		match only_error("size 20 20\n10 25 5") {
+			(MapError::CoordinateOutOfRange { value, max, .. }, 2, 4) => {
+				assert_eq!((value, max), (25.0, 19))
+			}
+			other => panic!("{:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_height_out_of_range() {
+		match only_error("10 10 80") {
+			(MapError::HeightOutOfRange { value, range, .. }, 1, 7) => {
+				assert_eq!((value, range), (80.0, (-50.0, 50.0)))
+			}
+			other => panic!("{:?}", other),
+		}
+		match only_error("height_range 0 10\n10 10 -2") {
+			(MapError::HeightOutOfRange { range, .. }, 2, 7) => assert_eq!(range, (0.0, 10.0)),
+			other => panic!("{:?}", other),
+		}
+	}
+
+	#[test]
+	fn reports_header_errors() {
+		match only_error("size 20\n10 10 5") {
+			(MapError::InvalidDirective { message, .. }, 1, 1) => {
+				assert_eq!(message, "Grid size needs a width and a height")
+			}
+			other => panic!("{:?}", other),
+		}
+		match only_error("spring well 5 5 1\ndrain well 8 8 1\n10 10 5") {
+			(MapError::DuplicateSource { name, .. }, 2, 7) => assert_eq!(name, "well"),
+			other => panic!("{:?}", other),
+		}
+		let report = check("boundary west periodic\n10 10 5");
+		match &report.errors[..] {
+			[MapError::InvalidMap { path, .. }] => assert_eq!(path, PATH),
+			other => panic!("{:?}", other),
+		}
+		match &HeightMap::check("resources/missing.mod1").errors[..] {
+			[MapError::Io { .. }] => (),
+			other => panic!("{:?}", other),
+		}
+	}
+
+	#[test]
+	fn collects_every_error_and_warning_in_order() {
+		let report = check(
+			"size 20 20\n10 10 5\n10 10 6\n10 abc 5\n19 5 3\n5 5 80\n# Corner\n5 5 1 2\n1 1 0\n",
+		);
+		let lines: Vec<usize> = report
+			.errors
+			.iter()
+			.map(|error| error.location().unwrap().line)
+			.collect();
+		assert_eq!(lines, [4, 6, 8]);
+		assert!(matches!(report.errors[0], MapError::InvalidNumber { .. }));
+		assert!(matches!(
+			report.errors[1],
+			MapError::HeightOutOfRange { .. }
+		));
+		assert!(matches!(report.errors[2], MapError::TooManyValues(_)));
+		match &report.warnings[..] {
+			[MapWarning::DuplicatePoint {
+				location,
+				first_line: 2,
+			}, MapWarning::PointOnBorder(border)] => {
+				assert_eq!((location.line, border.line), (3, 5))
+			}
+			other => panic!("{:?}", other),
+		}
+		assert!(report.diagnostics().ends_with("\n\n3 errors, 2 warnings"));
+	}
+
+	#[test]
+	fn warns_about_empty_file() {
+		for text in ["", "\n\n", "# Nothing yet\nsize 20 20\n"].iter() {
+			let report = check(text);
+			assert!(report.is_ok());
+			match &report.warnings[..] {
+				[MapWarning::NoPoints { path }] => assert_eq!(path, PATH),
+				other => panic!("{:?}", other),
+			}
+			assert!(report.diagnostics().ends_with("0 errors, 1 warning"));
+		}
+	}
+
+	#[test]
+	fn rejects_unsupported_versions() {
+		for (text, version) in [("mod1 3\nname Next\n10 10 abc", 3), ("mod1 0\n", 0)].iter() {
+			let report = check(text);
+			match &report.errors[..] {
+				[MapError::UnsupportedVersion {
+					location,
+					version: found,
+				}] => {
+					assert_eq!((location.line, location.column), (1, 6));
+					assert_eq!(found, version);
+				}
+				other => panic!("{:?}", other),
+			}
+			assert!(report.warnings.is_empty());
+		}
+		for text in ["mod1 two\n10 10 5", "name Late\nmod1 2\n10 10 5"].iter() {
+			match only_error(text) {
+				(MapError::InvalidDirective { .. }, _, _) => (),
+				other => panic!("{:?}", other),
+			}
+		}
 	}
 
-	fn as_any_mut(&mut self) -> &mut dyn Any {
-		self
+	#[test]
+	fn parses_resources_unchanged() {
+		let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/resources");
+		let mut count = 0;
+		for entry in std::fs::read_dir(dir).unwrap() {
+			let path = entry.unwrap().path();
+			if path
+				.extension()
+				.map_or(true, |extension| extension != "mod1")
+			{
+				continue;
+			}
+			let text = std::fs::read_to_string(&path).unwrap();
+			let mut report = MapReport::default();
+			let file = HeightMap::read_lines(PATH, text.as_bytes(), &mut report);
+			assert!(report.is_ok(), "{:?}\n{}", path, report.diagnostics());
+			// Every `x y height` line is a point, in the order of the file.
+			let points: Vec<Vec3> = text
+				.lines()
+				.map(|line| strip_comment(line).split_ascii_whitespace())
+				.filter_map(|values| values.map(|value| value.parse().ok()).collect())
+				.filter_map(|values: Vec<f32>| match values[..] {
+					[x, y, z] => Some(Vec3::new(x, y, z)),
+					_ => None,
+				})
+				.collect();
+			assert_eq!(file.points, points, "{:?}", path);
+			// Declaring the current version changes nothing else.
+			let body: Vec<&str> = text
+				.lines()
+				.filter(|line| !line.starts_with("mod1 "))
+				.collect();
+			let versioned = format!("mod1 {}\n{}", FORMAT_VERSION, body.join("\n"));
+			let current = HeightMap::read_lines(PATH, versioned.as_bytes(), &mut report);
+			assert!(report.is_ok(), "{:?}\n{}", path, report.diagnostics());
+			assert_eq!(current.points, file.points);
+			assert_eq!(current.dims, file.dims);
+			assert_eq!(current.info.height_range, file.info.height_range);
+			assert_eq!(current.info.interpolation, file.info.interpolation);
+			count += 1;
+		}
+		assert!(count >= 5);
+	}
+
+	#[test]
+	fn renders_caret_under_token() {
+		let (error, _, _) = only_error("size 20 20\n10 10 80");
+		assert_eq!(
+			error.diagnostic(),
+			"error: Invalid height number '80' (not between -50 and 50)\n\
+			 \x20--> test.mod1:2:7\n\
+			 \x20 |\n\
+			 2 | 10 10 80\n\
+			 \x20 |       ^^"
+		);
+		assert_eq!(
+			error.to_string(),
+			"test.mod1:2:7: Invalid height number '80' (not between -50 and 50)"
+		);
+		// Tabs are kept so the caret still lines up.
+		let (error, _, _) = only_error("\t10\t10 abc # bad");
+		assert!(error
+			.diagnostic()
+			.ends_with("1 | \t10\t10 abc # bad\n  | \t  \t   ^^^"));
 	}
 
-	fn start(&mut self, store: &EntityStore) {
-		let terrain_vert = Mesh::heights_gen_vertices(DIM, &Vec::from(self.points));
-		let terrain_mesh = Box::new(Mesh::new("terrain", &terrain_vert, DIM, true, true, None));
-		let border_vert = Mesh::wall_gen_vertices(&self.border_wall());
-		let border_mesh = Box::new(Mesh::new("border", &border_vert, DIM, true, true, None));
-		store.to_new_queue(terrain_mesh);
-		store.to_new_queue(border_mesh);
+	#[test]
+	fn reports_directive_after_the_points() {
+		let (error, line, column) = only_error("size 20 20\n10 10 5\n  sea 2 # Too late\n5 5 1");
+		assert!(matches!(error, MapError::InvalidDirective { .. }));
+		assert_eq!((line, column), (3, 3));
+		assert_eq!(
+			error.to_string(),
+			"test.mod1:3:3: The sea directive must come before the points"
+		);
+		assert!(error
+			.diagnostic()
+			.ends_with("3 |   sea 2 # Too late\n  |   ^^^"));
+		let directives = [
+			"size 20 20",
+			"boundary east open",
+			"name Late",
+			"drain d 1 1 1",
+		];
+		for directive in directives.iter() {
+			let text = format!("10 10 5\n{}", directive);
+			match only_error(&text) {
+				(MapError::InvalidDirective { .. }, 2, 1) => (),
+				other => panic!("{:?}", other),
+			}
+		}
+	}
+
+	/// Every edge cell of a `width * height` grid as a point at height 0.
+	fn border_points(width: usize, height: usize) -> Vec<Vec3> {
+		(0..width * height)
+			.map(|index| (index % width, index / width))
+			.filter(|(i, j)| *i == 0 || *j == 0 || *i == width - 1 || *j == height - 1)
+			.map(|(i, j)| Vec3::new(i as f32, j as f32, 0.0))
+			.collect()
+	}
+
+	#[test]
+	fn interpolates_like_explicit_border_points() {
+		let poi = vec![
+			Vec3::new(20.0, 10.0, 30.0),
+			Vec3::new(7.5, 5.25, -12.0),
+			Vec3::new(50.0, 30.0, 8.0),
+		];
+		let (width, height) = (61, 37);
+		let map = HeightMap::poi_to_map(poi.clone(), width, height, Interpolation::InverseDistance);
+		let mut all = poi;
+		all.extend(border_points(width, height));
+		let expected = HeightMap::interpolate(&all, width, height);
+		for (value, expected) in map.iter().zip(expected.iter()) {
+			assert!((value - expected).abs() < 1e-3, "{} != {}", value, expected);
+		}
+		assert_eq!(map[20 + 10 * width], 30.0);
+		assert!(border_points(width, height)
+			.iter()
+			.all(|point| map[point.x as usize + point.y as usize * width] == 0.0));
+	}
+
+	#[test]
+	fn nearest_matches_explicit_border_points() {
+		let poi = vec![
+			Vec3::new(12.0, 9.0, 5.0),
+			Vec3::new(30.0, 6.5, -4.0),
+			Vec3::new(12.0, 9.0, 7.0),
+		];
+		let (width, height) = (41, 19);
+		let map = HeightMap::poi_to_map(poi.clone(), width, height, Interpolation::Nearest);
+		let mut all = poi;
+		all.extend(border_points(width, height));
+		for (index, value) in map.iter().enumerate() {
+			let cell = Vec2::new((index % width) as f32, (index / width) as f32);
+			let mut closest = (f32::INFINITY, 0.0);
+			for point in all.iter() {
+				let distance = cell.distance_squared(point.truncate());
+				if distance < closest.0 {
+					closest = (distance, point.z);
+				}
+			}
+			assert_eq!(*value, closest.1, "{}", cell);
+		}
+		assert_eq!(map[12 + 9 * width], 5.0);
 	}
 }
@@ -0,0 +1,200 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+/// Gray levels of an image, between 0 for black and 1 for white, stored row
+/// by row from the top.
+#[derive(Debug)]
+pub(crate) struct GrayImage {
+	pub width: usize,
+	pub height: usize,
+	pub levels: Vec<f32>,
+}
+
+/// Load a grayscale PNG, or a binary (`P5`) or plain (`P2`) PGM image, picked
+/// from the extension of `filename`. Colored PNG images are converted to
+/// their luminance.
+pub(crate) fn load_image(filename: &str) -> Result<GrayImage, String> {
+	let extension = filename.rsplit('.').next().unwrap_or_default();
+	let image = match extension.to_ascii_lowercase().as_str() {
+		"png" => load_png(filename),
+		"pgm" => load_pgm(filename),
+		_ => Err(format!(
+			"Unknown image format '{}' (expected png or pgm)",
+			extension
+		)),
+	}?;
+	if image.width == 0 || image.height == 0 {
+		return Err(String::from("Empty image"));
+	}
+	Ok(image)
+}
+
+fn load_png(filename: &str) -> Result<GrayImage, String> {
+	let file = File::open(filename).map_err(|err| format!("Failed to read file: {}", err))?;
+	let mut decoder = png::Decoder::new(BufReader::new(file));
+	// Palettes and gray levels under 8 bits become 8 bit values.
+	decoder.set_transformations(png::Transformations::EXPAND);
+	let invalid = |err| format!("Invalid PNG image ({})", err);
+	let (info, mut reader) = decoder.read_info().map_err(invalid)?;
+	let mut data = vec![0; info.buffer_size()];
+	reader.next_frame(&mut data).map_err(invalid)?;
+	let samples: Vec<f32> = match info.bit_depth {
+		png::BitDepth::Sixteen => data
+			.chunks_exact(2)
+			.map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as f32 / 65535.0)
+			.collect(),
+		_ => data.iter().map(|byte| *byte as f32 / 255.0).collect(),
+	};
+	let (width, height) = (info.width as usize, info.height as usize);
+	let channels = samples.len() / (width * height).max(1);
+	let levels = samples
+		.chunks_exact(channels.max(1))
+		.map(|pixel| match info.color_type {
+			png::ColorType::RGB | png::ColorType::RGBA => {
+				0.299 * pixel[0] + 0.587 * pixel[1] + 0.114 * pixel[2]
+			}
+			_ => pixel[0],
+		})
+		.collect();
+	Ok(GrayImage {
+		width,
+		height,
+		levels,
+	})
+}
+
+fn load_pgm(filename: &str) -> Result<GrayImage, String> {
+	let mut data = Vec::new();
+	File::open(filename)
+		.and_then(|mut file| file.read_to_end(&mut data))
+		.map_err(|err| format!("Failed to read file: {}", err))?;
+	parse_pgm(&data)
+}
+
+fn parse_pgm(data: &[u8]) -> Result<GrayImage, String> {
+	let mut cursor = 0;
+	let magic = pgm_token(data, &mut cursor)?;
+	if magic != "P2" && magic != "P5" {
+		return Err(format!(
+			"Invalid PGM image (magic number '{}' is not P2 or P5)",
+			magic
+		));
+	}
+	let mut header = [0usize; 3];
+	for value in header.iter_mut() {
+		let token = pgm_token(data, &mut cursor)?;
+		*value = token
+			.parse()
+			.map_err(|err| format!("Invalid PGM image (header value '{}': {})", token, err))?;
+	}
+	let [width, height, max] = header;
+	if max == 0 || max > 65535 {
+		return Err(format!(
+			"Invalid PGM image (maximum gray level {} is not between 1 and 65535)",
+			max
+		));
+	}
+	let count = width
+		.checked_mul(height)
+		.ok_or_else(|| format!("Invalid PGM image (size {}x{} is too large)", width, height))?;
+	// A binary pixel takes one or two bytes and a plain one at least a digit
+	// and a separator, so a header promising more pixels than the file can
+	// hold is rejected before allocating them. A single whitespace separates
+	// the header from binary pixels.
+	let bytes = if max > 255 { 2 } else { 1 };
+	let needed = if magic == "P2" {
+		count.saturating_mul(2)
+	} else {
+		count.saturating_mul(bytes).saturating_add(1)
+	};
+	if data.len() - cursor < needed {
+		return Err(String::from("Invalid PGM image (not enough pixels)"));
+	}
+	let mut levels = Vec::with_capacity(count);
+	if magic == "P2" {
+		for _ in 0..count {
+			let token = pgm_token(data, &mut cursor)?;
+			let level: usize = token
+				.parse()
+				.map_err(|err| format!("Invalid PGM image (gray level '{}': {})", token, err))?;
+			levels.push(level.min(max) as f32 / max as f32);
+		}
+	} else {
+		let pixels = &data[cursor + 1..cursor + needed];
+		for pixel in pixels.chunks_exact(bytes) {
+			let level = match pixel {
+				[high, low] => u16::from_be_bytes([*high, *low]) as usize,
+				_ => pixel[0] as usize,
+			};
+			levels.push(level.min(max) as f32 / max as f32);
+		}
+	}
+	Ok(GrayImage {
+		width,
+		height,
+		levels,
+	})
+}
+
+/// Next token of a PGM image from `cursor`, skipping whitespace and `#`
+/// comments, leaving `cursor` right after it.
+fn pgm_token<'a>(data: &'a [u8], cursor: &mut usize) -> Result<&'a str, String> {
+	while *cursor < data.len() {
+		match data[*cursor] {
+			b'#' => {
+				while *cursor < data.len() && data[*cursor] != b'\n' {
+					*cursor += 1;
+				}
+			}
+			byte if byte.is_ascii_whitespace() => *cursor += 1,
+			_ => break,
+		}
+	}
+	let start = *cursor;
+	while *cursor < data.len() && !data[*cursor].is_ascii_whitespace() {
+		*cursor += 1;
+	}
+	if start == *cursor {
+		return Err(String::from("Invalid PGM image (unexpected end of file)"));
+	}
+	std::str::from_utf8(&data[start..*cursor])
+		.map_err(|_| String::from("Invalid PGM image (header is not text)"))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_plain_and_binary_pgm() {
+		let plain = parse_pgm(b"P2\n# Ramp\n2 2\n4\n0 1\n2 4\n").unwrap();
+		assert_eq!((plain.width, plain.height), (2, 2));
+		assert_eq!(plain.levels, [0.0, 0.25, 0.5, 1.0]);
+		let binary = parse_pgm(b"P5 2 1 65535\n\x00\x00\xff\xff").unwrap();
+		assert_eq!(binary.levels, [0.0, 1.0]);
+	}
+
+	#[test]
+	fn rejects_truncated_pixels() {
+		let mut data = b"P5 4 4 255\n".to_vec();
+		data.extend_from_slice(&[0; 10]);
+		let err = parse_pgm(&data).unwrap_err();
+		assert_eq!(err, "Invalid PGM image (not enough pixels)");
+		let err = parse_pgm(b"P2 3 1 255\n1 2").unwrap_err();
+		assert_eq!(err, "Invalid PGM image (not enough pixels)");
+	}
+
+	#[test]
+	fn rejects_oversized_header_before_allocating() {
+		for header in [
+			&b"P5 100000 100000 255\n\x00"[..],
+			b"P2 4000000000 4000000000 255\n1 2 3",
+			b"P5 18446744073709551615 2 65535\n",
+		]
+		.iter()
+		{
+			let err = parse_pgm(header).unwrap_err();
+			assert!(err.starts_with("Invalid PGM image"), "{}", err);
+		}
+	}
+}
@@ -0,0 +1,80 @@
+use std::fmt;
+
+/// Running account of the water volume of a [`Water`](super::Water) grid.
+///
+/// Every input and output goes through the ledger, so anything left in
+/// `numerical_error` was created or lost by the solver itself.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VolumeLedger {
+	/// Volume when the grid was created or loaded.
+	pub initial: f64,
+	/// Volume currently on the grid.
+	pub total: f64,
+	/// Volume added by waves, tides, rain and `add_depth`.
+	pub inflow: f64,
+	/// Volume removed by drains and `remove_depth`.
+	pub outflow: f64,
+	/// Volume change of the last update, which should move water around
+	/// without adding or removing any.
+	pub step_error: f64,
+	/// Sum of the volume changes of every update.
+	pub numerical_error: f64,
+}
+
+impl VolumeLedger {
+	pub(crate) fn new(volume: f64) -> Self {
+		VolumeLedger {
+			initial: volume,
+			total: volume,
+			..VolumeLedger::default()
+		}
+	}
+
+	pub(crate) fn record_inflow(&mut self, volume: f64) {
+		self.inflow += volume;
+		self.total += volume;
+	}
+
+	pub(crate) fn record_outflow(&mut self, volume: f64) {
+		self.outflow += volume;
+		self.total -= volume;
+	}
+
+	pub(crate) fn record_step(&mut self, volume: f64) {
+		self.step_error = volume - self.total;
+		self.numerical_error += self.step_error;
+		self.total = volume;
+	}
+
+	/// Volume the grid should hold if the solver conserved it exactly.
+	pub fn expected(&self) -> f64 {
+		self.initial + self.inflow - self.outflow
+	}
+
+	/// Numerical error relative to the volume that went through the grid.
+	pub fn relative_error(&self) -> f64 {
+		let scale = self.initial + self.inflow;
+		if scale > 0.0 {
+			self.numerical_error / scale
+		} else {
+			0.0
+		}
+	}
+}
+
+impl fmt::Display for VolumeLedger {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"volume {:.4} (initial {:.4}, inflow {:.4}, outflow {:.4}), \
+			 numerical error {:+.6} ({:+.3e} relative, {:+.6} last step)",
+			self.total,
+			self.initial,
+			self.inflow,
+			self.outflow,
+			self.numerical_error,
+			self.relative_error(),
+			self.step_error
+		)
+	}
+}
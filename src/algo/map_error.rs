@@ -0,0 +1,360 @@
+use super::FORMAT_VERSION;
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::num::ParseFloatError;
+
+/// Where a [`MapError`] was found in a map file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Location {
+	pub path: String,
+	/// Line number, starting at 1.
+	pub line: usize,
+	/// Column of the first character of the token, starting at 1.
+	pub column: usize,
+	/// The offending token, or the whole line when no single token is to
+	/// blame.
+	pub token: String,
+	/// Text of the whole line.
+	pub text: String,
+}
+
+impl Location {
+	pub(crate) fn new(path: &str, line: usize, text: &str, column: usize, token: &str) -> Self {
+		Location {
+			path: String::from(path),
+			line,
+			column,
+			token: String::from(token),
+			text: String::from(text),
+		}
+	}
+
+	/// Location of the whole `text` of a line, without its comment.
+	pub(crate) fn line(path: &str, line: usize, text: &str) -> Self {
+		let content = strip_comment(text);
+		let (column, token) = match tokens(content).first() {
+			Some((column, _)) => (*column, content.trim()),
+			None => (1, ""),
+		};
+		Location::new(path, line, text, column, token)
+	}
+}
+
+/// Failure to load a map file with [`HeightMap::new`](super::HeightMap::new).
+#[derive(Debug)]
+pub enum MapError {
+	/// The file could not be opened or read.
+	Io {
+		path: String,
+		error: io::Error,
+	},
+	/// A point has more than its `x y height` values, the location is the
+	/// first extra one.
+	TooManyValues(Location),
+	/// A point has less than its `x y height` values, the location is the end
+	/// of the line.
+	NotEnoughValues(Location),
+	InvalidNumber {
+		location: Location,
+		error: ParseFloatError,
+	},
+	/// A point coordinate is off the grid declared by the file.
+	CoordinateOutOfRange {
+		location: Location,
+		value: f32,
+		max: usize,
+	},
+	/// A point height is not in the height range of the file, between
+	/// `-MAX_HEIGHT` and `MAX_HEIGHT` by default.
+	HeightOutOfRange {
+		location: Location,
+		value: f32,
+		range: (f32, f32),
+	},
+	/// A `size`, `boundary`, `sea` or source line is invalid.
+	InvalidDirective {
+		location: Location,
+		message: String,
+	},
+	/// The `mod1` line declares a format version newer than
+	/// [`FORMAT_VERSION`] or 0, the lines after it are not read.
+	UnsupportedVersion {
+		location: Location,
+		version: u32,
+	},
+	DuplicateSource {
+		location: Location,
+		name: String,
+	},
+	/// The map as a whole is invalid, like boundaries that do not pair up or
+	/// a grid size out of range.
+	InvalidMap {
+		path: String,
+		message: String,
+	},
+}
+
+impl MapError {
+	/// Highest absolute height of a map point.
+	pub const MAX_HEIGHT: f32 = 50.0;
+
+	pub fn path(&self) -> &str {
+		match self {
+			MapError::Io { path, .. } | MapError::InvalidMap { path, .. } => path,
+			_ => self
+				.location()
+				.map_or("", |location| location.path.as_str()),
+		}
+	}
+
+	/// Where the error is in the file, `None` if it is not on a single line.
+	pub fn location(&self) -> Option<&Location> {
+		match self {
+			MapError::Io { .. } | MapError::InvalidMap { .. } => None,
+			MapError::TooManyValues(location)
+			| MapError::NotEnoughValues(location)
+			| MapError::InvalidNumber { location, .. }
+			| MapError::CoordinateOutOfRange { location, .. }
+			| MapError::HeightOutOfRange { location, .. }
+			| MapError::InvalidDirective { location, .. }
+			| MapError::UnsupportedVersion { location, .. }
+			| MapError::DuplicateSource { location, .. } => Some(location),
+		}
+	}
+
+	/// Description of the error, without its location.
+	pub fn message(&self) -> String {
+		match self {
+			MapError::Io { error, .. } => format!("Failed to read file: {}", error),
+			MapError::TooManyValues(_) => String::from("Too many values"),
+			MapError::NotEnoughValues(_) => String::from("Not enough values"),
+			MapError::InvalidNumber { location, error } => {
+				format!("Invalid value '{}' ({})", location.token, error)
+			}
+			MapError::CoordinateOutOfRange { value, max, .. } => format!(
+				"Invalid coordinate number '{}' (not between 0 and {})",
+				value, max
+			),
+			MapError::HeightOutOfRange { value, range, .. } => format!(
+				"Invalid height number '{}' (not between {} and {})",
+				value, range.0, range.1
+			),
+			MapError::InvalidDirective { message, .. } | MapError::InvalidMap { message, .. } => {
+				message.clone()
+			}
+			MapError::UnsupportedVersion { version, .. } => format!(
+				"Unsupported format version {} (not between 1 and {})",
+				version, FORMAT_VERSION
+			),
+			MapError::DuplicateSource { name, .. } => {
+				format!("Source '{}' is already declared", name)
+			}
+		}
+	}
+
+	/// Describe the error over several lines, showing the offending line with
+	/// a caret under the token to blame:
+	///
+	/// ```text
+	/// error: Invalid height number '80' (not between -50 and 50)
+	///  --> resources/demo1.mod1:4:7
+	///   |
+	/// 4 | 20 30 80
+	///   |       ^^
+	/// ```
+	pub fn diagnostic(&self) -> String {
+		render("error", &self.message(), self.path(), self.location())
+	}
+}
+
+impl fmt::Display for MapError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.location() {
+			Some(location) => write!(
+				f,
+				"{}:{}:{}: {}",
+				location.path,
+				location.line,
+				location.column,
+				self.message()
+			),
+			None => write!(f, "{}: {}", self.path(), self.message()),
+		}
+	}
+}
+
+impl Error for MapError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			MapError::Io { error, .. } => Some(error),
+			MapError::InvalidNumber { error, .. } => Some(error),
+			_ => None,
+		}
+	}
+}
+
+/// Something suspicious in a map file that still loads, found by
+/// [`HeightMap::check`](super::HeightMap::check).
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapWarning {
+	/// The file declares no point, the terrain is flat.
+	NoPoints { path: String },
+	/// A point has the coordinates of the point on line `first_line`.
+	DuplicatePoint {
+		location: Location,
+		first_line: usize,
+	},
+	/// A point above or below 0 lies on the border of the map, where the
+	/// terrain is held at 0.
+	PointOnBorder(Location),
+}
+
+impl MapWarning {
+	pub fn path(&self) -> &str {
+		match self {
+			MapWarning::NoPoints { path } => path,
+			MapWarning::DuplicatePoint { location, .. } | MapWarning::PointOnBorder(location) => {
+				&location.path
+			}
+		}
+	}
+
+	/// Where the warning is in the file, `None` if it is not on a single line.
+	pub fn location(&self) -> Option<&Location> {
+		match self {
+			MapWarning::NoPoints { .. } => None,
+			MapWarning::DuplicatePoint { location, .. } | MapWarning::PointOnBorder(location) => {
+				Some(location)
+			}
+		}
+	}
+
+	/// Description of the warning, without its location.
+	pub fn message(&self) -> String {
+		match self {
+			MapWarning::NoPoints { .. } => {
+				String::from("No point declared, the terrain is flat at 0")
+			}
+			MapWarning::DuplicatePoint { first_line, .. } => {
+				format!("Duplicate point, already declared on line {}", first_line)
+			}
+			MapWarning::PointOnBorder(_) => {
+				String::from("Point on the border of the map, which is held at height 0")
+			}
+		}
+	}
+
+	/// Describe the warning like [`MapError::diagnostic`].
+	pub fn diagnostic(&self) -> String {
+		render("warning", &self.message(), self.path(), self.location())
+	}
+}
+
+impl fmt::Display for MapWarning {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self.location() {
+			Some(location) => write!(
+				f,
+				"{}:{}:{}: {}",
+				location.path,
+				location.line,
+				location.column,
+				self.message()
+			),
+			None => write!(f, "{}: {}", self.path(), self.message()),
+		}
+	}
+}
+
+/// Every error and warning of a map file, in the order of its lines.
+#[derive(Debug, Default)]
+pub struct MapReport {
+	pub errors: Vec<MapError>,
+	pub warnings: Vec<MapWarning>,
+}
+
+impl MapReport {
+	/// The map loads, possibly with warnings.
+	pub fn is_ok(&self) -> bool {
+		self.errors.is_empty()
+	}
+
+	/// Diagnostics of all the errors then all the warnings, followed by a
+	/// count of both.
+	pub fn diagnostics(&self) -> String {
+		let mut lines: Vec<String> = self.errors.iter().map(MapError::diagnostic).collect();
+		lines.extend(self.warnings.iter().map(MapWarning::diagnostic));
+		lines.push(format!(
+			"{} error{}, {} warning{}",
+			self.errors.len(),
+			if self.errors.len() == 1 { "" } else { "s" },
+			self.warnings.len(),
+			if self.warnings.len() == 1 { "" } else { "s" }
+		));
+		lines.join("\n\n")
+	}
+}
+
+/// Show a `level` message over several lines, with the line of `location` and
+/// a caret under its token.
+fn render(level: &str, message: &str, path: &str, location: Option<&Location>) -> String {
+	let location = match location {
+		Some(location) => location,
+		None => return format!("{}: {}\n --> {}", level, message, path),
+	};
+	let number = location.line.to_string();
+	let margin = " ".repeat(number.len());
+	// Keep the tabs of the line so the caret lines up with the token.
+	let indent: String = location
+		.text
+		.chars()
+		.take(location.column - 1)
+		.map(|c| if c == '\t' { '\t' } else { ' ' })
+		.collect();
+	let carets = "^".repeat(location.token.chars().count().max(1));
+	format!(
+		"{}: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}",
+		level,
+		message,
+		margin,
+		location.path,
+		location.line,
+		location.column,
+		margin,
+		number,
+		location.text,
+		margin,
+		indent,
+		carets
+	)
+}
+
+/// `line` without its comment, starting at the first `#`.
+pub(crate) fn strip_comment(line: &str) -> &str {
+	match line.find('#') {
+		Some(index) => &line[..index],
+		None => line,
+	}
+}
+
+/// Tokens of `line` separated by whitespace, with the column they start at.
+pub(crate) fn tokens(line: &str) -> Vec<(usize, &str)> {
+	let mut tokens = Vec::new();
+	let mut start = None;
+	for (column, (index, c)) in line.char_indices().enumerate() {
+		match (c.is_ascii_whitespace(), start) {
+			(true, Some((start_column, start_index))) => {
+				tokens.push((start_column + 1, &line[start_index..index]));
+				start = None;
+			}
+			(false, None) => start = Some((column, index)),
+			_ => (),
+		}
+	}
+	if let Some((start_column, start_index)) = start {
+		tokens.push((start_column + 1, &line[start_index..]));
+	}
+	tokens
+}
@@ -0,0 +1,135 @@
+use super::{DemScale, MapError, WaterParams};
+
+/// Latest version of the map file format. Files without a `mod1 <version>`
+/// line are version 1, which has the same syntax, and files declaring a newer
+/// version are rejected.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// How the heights between the points of a map file are filled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Interpolation {
+	/// Mean of all the points weighted by their inverse distance, giving
+	/// smooth hills.
+	InverseDistance,
+	/// Height of the closest point, giving flat terraces.
+	Nearest,
+}
+
+impl Interpolation {
+	pub fn parse(name: &str) -> Result<Self, String> {
+		match name {
+			"idw" => Ok(Interpolation::InverseDistance),
+			"nearest" => Ok(Interpolation::Nearest),
+			_ => Err(format!(
+				"Unknown interpolation '{}' (expected idw or nearest)",
+				name
+			)),
+		}
+	}
+}
+
+/// Header of a map file, declared by its directives before the first point.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapInfo {
+	pub version: u32,
+	pub name: Option<String>,
+	/// Every `description` line, joined by newlines.
+	pub description: Option<String>,
+	/// Width of a cell in the unit of the heights, given to the water unless
+	/// the parameters override it.
+	pub cell_size: Option<f32>,
+	/// Lowest and highest height of the points.
+	pub height_range: (f32, f32),
+	pub interpolation: Interpolation,
+	/// How an imported elevation model was rescaled, `None` for map files.
+	pub scale: Option<DemScale>,
+}
+
+impl Default for MapInfo {
+	fn default() -> Self {
+		MapInfo {
+			version: 1,
+			name: None,
+			description: None,
+			cell_size: None,
+			height_range: (-MapError::MAX_HEIGHT, MapError::MAX_HEIGHT),
+			interpolation: Interpolation::InverseDistance,
+			scale: None,
+		}
+	}
+}
+
+impl MapInfo {
+	/// Keywords of the directives handled by [`MapInfo::parse`].
+	pub(crate) const KEYWORDS: [&'static str; 6] = [
+		"mod1",
+		"name",
+		"description",
+		"cell_size",
+		"height_range",
+		"interpolation",
+	];
+
+	/// Apply the directive `line` starting with `keyword`, `first` telling
+	/// if it is the first line of the file besides comments. The version is
+	/// stored as is, the reader checks it is supported.
+	pub(crate) fn parse(&mut self, keyword: &str, line: &str, first: bool) -> Result<(), String> {
+		let text = line.trim().strip_prefix(keyword).unwrap_or_default().trim();
+		let values: Vec<&str> = text.split_ascii_whitespace().collect();
+		match keyword {
+			"mod1" => {
+				if !first {
+					return Err(String::from("Format version must be the first line"));
+				}
+				self.version = MapInfo::number(&values, "Format version needs a number")?;
+			}
+			"name" => self.name = Some(String::from(text)),
+			"description" => {
+				self.description = Some(match self.description.take() {
+					Some(description) => format!("{}\n{}", description, text),
+					None => String::from(text),
+				})
+			}
+			"cell_size" => {
+				let cell_size = MapInfo::number(&values, "Cell size needs a value")?;
+				WaterParams::default().set("cell_size", cell_size)?;
+				self.cell_size = Some(cell_size);
+			}
+			"height_range" => {
+				if values.len() != 2 {
+					return Err(String::from("Height range needs a minimum and a maximum"));
+				}
+				let min: f32 = MapInfo::number(&values[..1], "")?;
+				let max: f32 = MapInfo::number(&values[1..], "")?;
+				let limit = MapError::MAX_HEIGHT;
+				if !(-limit < min && min < max && max < limit) {
+					return Err(format!(
+						"Invalid height range '{} {}' (not increasing between -{} and {})",
+						min, max, limit, limit
+					));
+				}
+				self.height_range = (min, max);
+			}
+			_ => {
+				self.interpolation = match values[..] {
+					[name] => Interpolation::parse(name)?,
+					_ => return Err(String::from("Interpolation needs a method")),
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn number<T>(values: &[&str], missing: &str) -> Result<T, String>
+	where
+		T: std::str::FromStr,
+		T::Err: std::fmt::Display,
+	{
+		match values {
+			[value] => value
+				.parse()
+				.map_err(|err| format!("Invalid value '{}' ({})", value, err)),
+			_ => Err(String::from(missing)),
+		}
+	}
+}
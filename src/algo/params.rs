@@ -0,0 +1,241 @@
+use super::map_error::strip_comment;
+use super::{Water, G, ZERO_DEPTH};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Physical parameters of the water solver, checked by
+/// [`Water::set_params`](super::Water::set_params).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaterParams {
+	/// Gravity pulling the water down the slopes.
+	pub gravity: f32,
+	/// Depth under which a cell counts as dry and stops flowing.
+	pub zero_depth: f32,
+	/// Width of a cell, in the unit of the terrain heights.
+	pub cell_size: f32,
+	/// Deepest water taken into account to push the flow through a pipe.
+	pub max_upwind_depth: f32,
+	/// Fraction of the flow through the pipes lost per second, acting as
+	/// viscosity and friction.
+	pub damping: f32,
+	/// Manning coefficient of the ground, slowing shallow and fast water the
+	/// most. Cells can have their own with
+	/// [`Water::set_roughness`](super::Water::set_roughness).
+	pub manning: f32,
+	/// Average depth above which waves, tides and rain stop adding water.
+	pub max_height: f32,
+}
+
+impl Default for WaterParams {
+	fn default() -> Self {
+		WaterParams {
+			gravity: G,
+			zero_depth: ZERO_DEPTH,
+			cell_size: 1.0,
+			max_upwind_depth: 5.0,
+			damping: 0.05,
+			manning: 0.0,
+			max_height: Water::MAX_HEIGHT,
+		}
+	}
+}
+
+impl WaterParams {
+	pub const MAX_MANNING: f32 = 1.0;
+
+	/// Name and valid range of every parameter.
+	pub const RANGES: [(&'static str, f32, f32); 7] = [
+		("gravity", 0.01, 100.0),
+		("zero_depth", 0.0001, 1.0),
+		("cell_size", 0.01, 100.0),
+		("max_upwind_depth", 0.01, 50.0),
+		("damping", 0.0, 10.0),
+		("manning", 0.0, WaterParams::MAX_MANNING),
+		("max_height", 0.01, 1000.0),
+	];
+
+	/// Lowest and highest valid value of the parameter `name`, `None` if there
+	/// is no such parameter.
+	pub fn range(name: &str) -> Option<(f32, f32)> {
+		WaterParams::RANGES
+			.iter()
+			.find(|range| range.0 == name)
+			.map(|(_, min, max)| (*min, *max))
+	}
+
+	/// Load a parameter file of `<name> <value>` lines, parameters it does not
+	/// set keep their default value. Blank lines and `#` comments are skipped.
+	pub fn from_file(filename: &str) -> Result<Self, String> {
+		let mut params = WaterParams::default();
+		params.read_file(filename)?;
+		Ok(params)
+	}
+
+	/// Set the parameters given by a parameter file, keeping the others.
+	pub fn read_file(&mut self, filename: &str) -> Result<(), String> {
+		let file = File::open(filename).map_err(|err| format!("Failed to read file: {}", err))?;
+		self.read_lines(BufReader::new(file))
+	}
+
+	fn read_lines(&mut self, reader: impl BufRead) -> Result<(), String> {
+		for (index_l, line) in reader.lines().enumerate() {
+			let line = line.map_err(|err| format!("Failed to read file: {}", err))?;
+			let values: Vec<&str> = strip_comment(&line).split_ascii_whitespace().collect();
+			match values.len() {
+				0 => continue,
+				2 => self
+					.parse(values[0], values[1])
+					.map_err(|err| format!("[line: {}] {}", index_l + 1, err))?,
+				_ => {
+					return Err(format!(
+						"[line: {}] Parameter needs a name and a value",
+						index_l + 1
+					))
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/// Set the parameter `name` from its textual `value`.
+	pub fn parse(&mut self, name: &str, value: &str) -> Result<(), String> {
+		let number: f32 = value
+			.parse()
+			.map_err(|err| format!("Invalid value '{}' for {} ({})", value, name, err))?;
+		self.set(name, number)
+	}
+
+	pub fn get(&self, name: &str) -> Option<f32> {
+		match name {
+			"gravity" => Some(self.gravity),
+			"zero_depth" => Some(self.zero_depth),
+			"cell_size" => Some(self.cell_size),
+			"max_upwind_depth" => Some(self.max_upwind_depth),
+			"damping" => Some(self.damping),
+			"manning" => Some(self.manning),
+			"max_height" => Some(self.max_height),
+			_ => None,
+		}
+	}
+
+	/// Set the parameter `name` to `value` if it is in its range.
+	pub fn set(&mut self, name: &str, value: f32) -> Result<(), String> {
+		WaterParams::check_value(name, value)?;
+		let param = match name {
+			"gravity" => &mut self.gravity,
+			"zero_depth" => &mut self.zero_depth,
+			"cell_size" => &mut self.cell_size,
+			"max_upwind_depth" => &mut self.max_upwind_depth,
+			"damping" => &mut self.damping,
+			"manning" => &mut self.manning,
+			_ => &mut self.max_height,
+		};
+		*param = value;
+		Ok(())
+	}
+
+	/// Check every parameter is in its range.
+	pub fn check(&self) -> Result<(), String> {
+		for (name, _, _) in WaterParams::RANGES.iter() {
+			WaterParams::check_value(name, self.get(name).unwrap_or_default())?;
+		}
+		Ok(())
+	}
+
+	fn check_value(name: &str, value: f32) -> Result<(), String> {
+		let (min, max) = WaterParams::range(name).ok_or_else(|| {
+			format!(
+				"Unknown parameter '{}' (expected {})",
+				name,
+				WaterParams::RANGES
+					.iter()
+					.map(|range| range.0)
+					.collect::<Vec<&str>>()
+					.join(", ")
+			)
+		})?;
+		if !(min..=max).contains(&value) {
+			return Err(format!(
+				"Invalid {} '{}' (not between {} and {})",
+				name, value, min, max
+			));
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for WaterParams {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let params: Vec<String> = WaterParams::RANGES
+			.iter()
+			.map(|(name, _, _)| format!("{} {}", name, self.get(name).unwrap_or_default()))
+			.collect();
+		write!(f, "{}", params.join(", "))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn read(text: &str) -> Result<WaterParams, String> {
+		let mut params = WaterParams::default();
+		params.read_lines(text.as_bytes())?;
+		Ok(params)
+	}
+
+	#[test]
+	fn reads_parameters_and_skips_comments() {
+		let params = read("# Muddy water\ngravity 3.7\n\n  # Slow\ndamping 0.5 # per second\n");
+		assert_eq!(
+			params,
+			Ok(WaterParams {
+				gravity: 3.7,
+				damping: 0.5,
+				..WaterParams::default()
+			})
+		);
+		assert_eq!(read("# Nothing\n"), Ok(WaterParams::default()));
+	}
+
+	#[test]
+	fn rejects_invalid_lines() {
+		assert_eq!(
+			read("gravity\n"),
+			Err(String::from("[line: 1] Parameter needs a name and a value"))
+		);
+		assert_eq!(
+			read("gravity 1 2\n"),
+			Err(String::from("[line: 1] Parameter needs a name and a value"))
+		);
+		assert!(read("\nviscosity 1\n")
+			.unwrap_err()
+			.starts_with("[line: 2] Unknown parameter 'viscosity'"));
+		assert!(read("damping fast\n")
+			.unwrap_err()
+			.starts_with("[line: 1] Invalid value 'fast' for damping"));
+	}
+
+	#[test]
+	fn rejects_values_out_of_range() {
+		for (name, min, max) in WaterParams::RANGES.iter() {
+			assert_eq!(WaterParams::range(name), Some((*min, *max)));
+			let mut params = WaterParams::default();
+			assert_eq!(params.set(name, *min), Ok(()));
+			assert_eq!(params.set(name, *max), Ok(()));
+			assert_eq!(params.get(name), Some(*max));
+			for value in [*min - 0.001, *max * 2.0, f32::NAN].iter() {
+				assert!(params.set(name, *value).is_err(), "{} {}", name, value);
+			}
+		}
+		assert_eq!(WaterParams::range("viscosity"), None);
+		assert_eq!(
+			read("cell_size 0\n"),
+			Err(String::from(
+				"[line: 1] Invalid cell_size '0' (not between 0.01 and 100)"
+			))
+		);
+	}
+}
@@ -0,0 +1,123 @@
+use super::map_error::strip_comment;
+use super::{HeightMap, Map, WaterParams, DEFAULT_DIM};
+
+use glam::Vec3;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Manning coefficient of common surfaces, usable by name in a roughness
+/// file.
+pub const SURFACES: [(&str, f32); 6] = [
+	("pavement", 0.013),
+	("channel", 0.03),
+	("grass", 0.035),
+	("crops", 0.04),
+	("brush", 0.07),
+	("forest", 0.1),
+];
+
+/// Load a roughness layer file and interpolate it on a grid of `dims` cells,
+/// to give to [`Water::set_roughness`](super::Water::set_roughness).
+///
+/// Like a map file it can start with a `size <width> <height>` line, then
+/// gives one `x y roughness` point per line, the roughness being a Manning
+/// coefficient or the name of a surface from [`SURFACES`]. Blank lines and
+/// `#` comments are skipped.
+pub fn load_roughness(filename: &str, dims: (usize, usize)) -> Result<Map, String> {
+	let file = File::open(filename).map_err(|err| format!("Failed to read file: {}", err))?;
+	let mut file_dims = (DEFAULT_DIM, DEFAULT_DIM);
+	let mut poi = Vec::new();
+	for (index_l, line) in BufReader::new(file).lines().enumerate() {
+		let line = line.map_err(|err| format!("Failed to read file: {}", err))?;
+		let line = strip_comment(&line);
+		if line.trim().is_empty() {
+			continue;
+		}
+		if poi.is_empty() && line.trim_start().starts_with("size") {
+			file_dims = HeightMap::parse_size(line)
+				.map_err(|err| format!("[line: {}] {}", index_l + 1, err))?;
+			continue;
+		}
+		poi.push(parse_point(line, index_l + 1, file_dims)?);
+	}
+	if poi.is_empty() {
+		return Err(format!("No roughness point in '{}'", filename));
+	}
+	let poi = HeightMap::scale_points(poi, file_dims, dims);
+	Ok(HeightMap::interpolate(&poi, dims.0, dims.1))
+}
+
+fn parse_point(line: &str, index_l: usize, file_dims: (usize, usize)) -> Result<Vec3, String> {
+	let values: Vec<&str> = line.split_ascii_whitespace().collect();
+	match values.len() {
+		n if n < 3 => return Err(format!("[line: {}] Not enough values", index_l)),
+		n if n > 3 => return Err(format!("[line: {}] Too many values", index_l)),
+		_ => (),
+	}
+	let mut point = [0.0; 3];
+	for (index_v, max) in [file_dims.0, file_dims.1].iter().enumerate() {
+		let value = values[index_v];
+		let number: f32 = value.parse().map_err(|err| {
+			format!(
+				"[line: {}, pos: {}] Invalid value '{}' ({})",
+				index_l,
+				index_v + 1,
+				value,
+				err
+			)
+		})?;
+		if number < 0.0 || number > (*max - 1) as f32 {
+			return Err(format!(
+				"[line: {}, pos: {}] Invalid coordinate number '{}' (not between 0 and {})",
+				index_l,
+				index_v + 1,
+				number,
+				max - 1
+			));
+		}
+		point[index_v] = number;
+	}
+	point[2] = match SURFACES.iter().find(|surface| surface.0 == values[2]) {
+		Some((_, manning)) => *manning,
+		None => values[2].parse().map_err(|_| {
+			format!(
+				"[line: {}, pos: 3] Invalid roughness '{}' (expected a number or {})",
+				index_l,
+				values[2],
+				SURFACES
+					.iter()
+					.map(|surface| surface.0)
+					.collect::<Vec<&str>>()
+					.join(", ")
+			)
+		})?,
+	};
+	if !(0.0..=WaterParams::MAX_MANNING).contains(&point[2]) {
+		return Err(format!(
+			"[line: {}, pos: 3] Invalid roughness '{}' (not between 0 and {})",
+			index_l,
+			point[2],
+			WaterParams::MAX_MANNING
+		));
+	}
+	Ok(Vec3::from(point))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn skips_comments_and_blank_lines() {
+		let path = std::env::temp_dir().join("mod1_roughness_test.rough");
+		let path = path.to_str().unwrap();
+		std::fs::write(
+			path,
+			"# Fields\nsize 10 10\n\n5 5 grass # Meadow\n  \n3 3 0.1\n",
+		)
+		.unwrap();
+		let roughness = load_roughness(path, (10, 10));
+		std::fs::remove_file(path).unwrap();
+		assert_eq!(roughness.map(|map| map.len()), Ok(100));
+	}
+}
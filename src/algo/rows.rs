@@ -0,0 +1,63 @@
+//! Iteration over the rows of a grid, spread over every core with the
+//! `parallel` feature. Each row is processed on its own and per row results
+//! are collected in order, so the outcome does not depend on the threads.
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+#[cfg(all(feature = "parallel", test))]
+thread_local! {
+	static SERIAL: std::cell::Cell<bool> = std::cell::Cell::new(false);
+}
+
+/// Fewest rows in a rayon job, tests can ask for a single job so the rows
+/// run one after the other like without the `parallel` feature.
+#[cfg(feature = "parallel")]
+fn min_rows() -> usize {
+	#[cfg(test)]
+	{
+		if SERIAL.with(|serial| serial.get()) {
+			return usize::MAX;
+		}
+	}
+	1
+}
+
+/// Run `f` with every row pass of this thread in order on the thread itself.
+#[cfg(all(feature = "parallel", test))]
+pub fn serial<R>(f: impl FnOnce() -> R) -> R {
+	SERIAL.with(|serial| serial.set(true));
+	let result = f();
+	SERIAL.with(|serial| serial.set(false));
+	result
+}
+
+/// Rows of `width` values of `data`, with their index.
+#[cfg(feature = "parallel")]
+pub fn rows<T: Sync>(
+	data: &[T],
+	width: usize,
+) -> impl IndexedParallelIterator<Item = (usize, &[T])> {
+	data.par_chunks(width).with_min_len(min_rows()).enumerate()
+}
+
+/// Mutable rows of `width` values of `data`, with their index.
+#[cfg(feature = "parallel")]
+pub fn rows_mut<T: Send>(
+	data: &mut [T],
+	width: usize,
+) -> impl IndexedParallelIterator<Item = (usize, &mut [T])> {
+	data.par_chunks_mut(width)
+		.with_min_len(min_rows())
+		.enumerate()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn rows<T>(data: &[T], width: usize) -> impl Iterator<Item = (usize, &[T])> {
+	data.chunks(width).enumerate()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn rows_mut<T>(data: &mut [T], width: usize) -> impl Iterator<Item = (usize, &mut [T])> {
+	data.chunks_mut(width).enumerate()
+}
@@ -0,0 +1,169 @@
+use super::{HeightMap, MapError, ZERO_DEPTH};
+
+use glam::Vec2;
+
+/// The part of the terrain tides fill and drains empty: every cell below the
+/// sea level connected to the border of the grid, or to a chosen cell.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sea {
+	level: f32,
+	/// Cell the sea spreads from, in grid coordinates, the whole border when
+	/// `None`.
+	from: Option<Vec2>,
+}
+
+impl Default for Sea {
+	fn default() -> Self {
+		Sea {
+			level: ZERO_DEPTH,
+			from: None,
+		}
+	}
+}
+
+impl Sea {
+	pub fn new(level: f32, from: Option<(usize, usize)>) -> Self {
+		Sea {
+			level,
+			from: from.map(|(x, y)| Vec2::new(x as f32, y as f32)),
+		}
+	}
+
+	/// Parse a `sea <level> [<x> <y>]` map file line, with coordinates on a
+	/// `file_dims` grid.
+	pub(crate) fn parse(line: &str, file_dims: (usize, usize)) -> Result<Self, String> {
+		let values: Vec<&str> = line.split_ascii_whitespace().skip(1).collect();
+		if values.len() != 1 && values.len() != 3 {
+			return Err(String::from(
+				"Sea needs a level, and optionally the x and y coordinates it spreads from",
+			));
+		}
+		let mut numbers = [0.0f32; 3];
+		for (index_v, value) in values.iter().enumerate() {
+			numbers[index_v] = value
+				.parse()
+				.map_err(|err| format!("Invalid value '{}' ({})", value, err))?;
+		}
+		let limit = MapError::MAX_HEIGHT;
+		if numbers[0].is_nan() || numbers[0].abs() >= limit {
+			return Err(format!(
+				"Invalid sea level '{}' (not between -{} and {})",
+				numbers[0], limit, limit
+			));
+		}
+		let mut sea = Sea::new(numbers[0], None);
+		if values.len() == 3 {
+			for (index_v, max) in [file_dims.0, file_dims.1].iter().enumerate() {
+				let max = (*max - 1) as f32;
+				if !(0.0..=max).contains(&numbers[1 + index_v]) {
+					return Err(format!(
+						"Invalid coordinate number '{}' (not between 0 and {})",
+						numbers[1 + index_v],
+						max
+					));
+				}
+			}
+			sea.from = Some(Vec2::new(numbers[1], numbers[2]));
+		}
+		Ok(sea)
+	}
+
+	/// Move the cell the sea spreads from, given in the coordinates of a
+	/// `file_dims` grid, to the coordinates of a `dims` grid.
+	pub(crate) fn scale(&mut self, file_dims: (usize, usize), dims: (usize, usize)) {
+		if let Some(from) = &mut self.from {
+			*from *= Vec2::new(
+				(dims.0 - 1) as f32 / (file_dims.0 - 1) as f32,
+				(dims.1 - 1) as f32 / (file_dims.1 - 1) as f32,
+			);
+		}
+	}
+
+	pub fn level(&self) -> f32 {
+		self.level
+	}
+
+	pub fn set_level(&mut self, level: f32) {
+		self.level = level;
+	}
+
+	/// Cell the sea spreads from, or `None` when it spreads from the border.
+	pub fn from(&self) -> Option<(usize, usize)> {
+		self.from
+			.map(|from| (from.x.round() as usize, from.y.round() as usize))
+	}
+
+	pub fn set_from(&mut self, from: Option<(usize, usize)>) {
+		self.from = from.map(|(x, y)| Vec2::new(x as f32, y as f32));
+	}
+
+	/// Index of every cell of `terrain` covered by the sea, found by flooding
+	/// the cells at or below the sea level from the border or the chosen cell.
+	pub fn cells(&self, terrain: &HeightMap) -> Vec<usize> {
+		let (dim_x, dim_y) = terrain.dims();
+		let heights = terrain.height_points();
+		let mut reached = vec![false; dim_x * dim_y];
+		let mut pending: Vec<usize> = match self.from() {
+			Some((x, y)) => vec![x + y * dim_x],
+			None => (0..dim_x)
+				.flat_map(|i| vec![i, i + (dim_y - 1) * dim_x])
+				.chain((1..dim_y - 1).flat_map(|j| vec![j * dim_x, dim_x - 1 + j * dim_x]))
+				.collect(),
+		};
+		let mut cells = Vec::new();
+		while let Some(index) = pending.pop() {
+			if reached[index] || heights[index] > self.level {
+				continue;
+			}
+			reached[index] = true;
+			cells.push(index);
+			let (i, j) = (index % dim_x, index / dim_x);
+			if i > 0 {
+				pending.push(index - 1);
+			}
+			if i < dim_x - 1 {
+				pending.push(index + 1);
+			}
+			if j > 0 {
+				pending.push(index - dim_x);
+			}
+			if j < dim_y - 1 {
+				pending.push(index + dim_x);
+			}
+		}
+		cells.sort_unstable();
+		cells
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A valley along the west edge, and a basin as deep behind a rim.
+	fn terrain() -> HeightMap {
+		let rows = ["0 0 1 1 1 1", "0 0 1 0 0 1", "0 0 1 0 0 1", "0 0 1 1 1 1"];
+		let heights = rows
+			.iter()
+			.flat_map(|row| row.split(' ').map(|value| value.parse().unwrap()))
+			.collect();
+		HeightMap::from_heights((6, 4), heights).unwrap()
+	}
+
+	#[test]
+	fn floods_from_the_border_only() {
+		let sea = Sea::new(0.5, None);
+		assert_eq!(sea.cells(&terrain()), vec![0, 1, 6, 7, 12, 13, 18, 19]);
+		// Up to the rim, the sea spills into the basin.
+		let sea = Sea::new(1.0, None);
+		assert_eq!(sea.cells(&terrain()), (0..24).collect::<Vec<usize>>());
+	}
+
+	#[test]
+	fn floods_from_a_chosen_cell() {
+		let sea = Sea::new(0.5, Some((3, 1)));
+		assert_eq!(sea.cells(&terrain()), vec![9, 10, 15, 16]);
+		let sea = Sea::new(0.5, Some((5, 0)));
+		assert!(sea.cells(&terrain()).is_empty());
+	}
+}
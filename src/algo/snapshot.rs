@@ -0,0 +1,276 @@
+use super::{Edge, Rain, Water};
+
+use glam::Vec3;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+
+const MAGIC: &[u8; 8] = b"MOD1STAT";
+pub const STATE_VERSION: u32 = 2;
+
+/// Write the water depths and pipe flows, and the falling droplets, to a
+/// binary snapshot file.
+///
+/// The file starts with the `MOD1STAT` magic, the format version and the grid
+/// dimensions as little-endian `u32`, followed by the depths, the `x` pipes,
+/// the `y` pipes, the west, east, south and north edge pipes, the droplet
+/// count and the droplets, all little-endian. Version 1 files have no edge
+/// pipes and can still be loaded.
+pub fn save_state(path: &str, water: &Water, rain: &Rain) -> Result<(), String> {
+	let write = || -> std::io::Result<()> {
+		let mut writer = BufWriter::new(File::create(path)?);
+		let (dim_x, dim_y) = water.dims();
+		writer.write_all(MAGIC)?;
+		writer.write_all(&STATE_VERSION.to_le_bytes())?;
+		writer.write_all(&(dim_x as u32).to_le_bytes())?;
+		writer.write_all(&(dim_y as u32).to_le_bytes())?;
+		let layers = [
+			water.depths(),
+			water.flows_x(),
+			water.flows_y(),
+			water.flows_edge(Edge::West),
+			water.flows_edge(Edge::East),
+			water.flows_edge(Edge::South),
+			water.flows_edge(Edge::North),
+		];
+		for layer in layers.iter() {
+			for value in layer.iter() {
+				writer.write_all(&value.to_le_bytes())?;
+			}
+		}
+		writer.write_all(&(rain.droplets().len() as u32).to_le_bytes())?;
+		for droplet in rain.droplets() {
+			for value in droplet.to_array().iter() {
+				writer.write_all(&value.to_le_bytes())?;
+			}
+		}
+		writer.flush()
+	};
+	write().map_err(|err| format!("Failed to write state file: {}", err))
+}
+
+/// Read back a snapshot written by [`save_state`].
+pub fn load_state(path: &str) -> Result<(Water, Rain), String> {
+	let mut bytes = Vec::new();
+	File::open(path)
+		.and_then(|mut file| file.read_to_end(&mut bytes))
+		.map_err(|err| format!("Failed to read state file: {}", err))?;
+	read_state(&bytes).map_err(|err| format!("{}: {}", path, err))
+}
+
+fn read_state(bytes: &[u8]) -> Result<(Water, Rain), String> {
+	let mut reader = StateReader { bytes };
+
+	if reader.take(MAGIC.len())? != MAGIC {
+		return Err(String::from("Not a mod1 state file"));
+	}
+	let version = reader.u32()?;
+	if version != 1 && version != STATE_VERSION {
+		return Err(format!(
+			"Unsupported state file version {} (expected {})",
+			version, STATE_VERSION
+		));
+	}
+	let dim_x = reader.u32()? as usize;
+	let dim_y = reader.u32()? as usize;
+	if !(super::MIN_DIM..=super::MAX_DIM).contains(&dim_x)
+		|| !(super::MIN_DIM..=super::MAX_DIM).contains(&dim_y)
+	{
+		return Err(format!(
+			"Invalid grid size {}x{} in state file (each side must be between {} and {})",
+			dim_x,
+			dim_y,
+			super::MIN_DIM,
+			super::MAX_DIM
+		));
+	}
+	let depths = reader.f32s(dim_x * dim_y)?;
+	let pipes_x = reader.f32s((dim_x - 1) * dim_y)?;
+	let pipes_y = reader.f32s(dim_x * (dim_y - 1))?;
+	let pipes_edge = if version >= 2 {
+		Some([
+			reader.f32s(dim_y)?,
+			reader.f32s(dim_y)?,
+			reader.f32s(dim_x)?,
+			reader.f32s(dim_x)?,
+		])
+	} else {
+		None
+	};
+	let count = reader.u32()? as usize;
+	if count > Rain::MAX_COUNT {
+		return Err(format!(
+			"Too many droplets in state file ({}, at most {})",
+			count,
+			Rain::MAX_COUNT
+		));
+	}
+	let droplets: Vec<Vec3> = reader
+		.f32s(count * 3)?
+		.chunks(3)
+		.map(Vec3::from_slice)
+		.collect();
+	if !reader.bytes.is_empty() {
+		return Err(String::from("Unexpected data at the end of the state file"));
+	}
+	// The water indexes its grid with these values, a corrupted file must not
+	// reach it.
+	if let Some(depth) = depths
+		.iter()
+		.find(|depth| !depth.is_finite() || **depth < 0.0)
+	{
+		return Err(format!("Invalid depth '{}' in state file", depth));
+	}
+	let mut flows = pipes_x
+		.iter()
+		.chain(pipes_y.iter())
+		.chain(pipes_edge.iter().flatten().flatten());
+	if let Some(flow) = flows.find(|flow| !flow.is_finite()) {
+		return Err(format!("Invalid flow '{}' in state file", flow));
+	}
+	let on_grid = |value: f32, max: usize| value >= 0.0 && value < max as f32;
+	if let Some(droplet) = droplets.iter().find(|droplet| {
+		!(on_grid(droplet.x, dim_x) && on_grid(droplet.y, dim_y) && droplet.z.is_finite())
+	}) {
+		return Err(format!(
+			"Invalid droplet ({}, {}, {}) in state file (not over the {}x{} grid)",
+			droplet.x, droplet.y, droplet.z, dim_x, dim_y
+		));
+	}
+	let water = Water::from_state((dim_x, dim_y), depths, pipes_x, pipes_y, pipes_edge);
+	Ok((water, Rain::from_droplets(droplets)))
+}
+
+struct StateReader<'a> {
+	bytes: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+	fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+		if self.bytes.len() < count {
+			return Err(String::from("Truncated state file"));
+		}
+		let (head, tail) = self.bytes.split_at(count);
+		self.bytes = tail;
+		Ok(head)
+	}
+
+	fn u32(&mut self) -> Result<u32, String> {
+		let mut word = [0; 4];
+		word.copy_from_slice(self.take(4)?);
+		Ok(u32::from_le_bytes(word))
+	}
+
+	fn f32s(&mut self, count: usize) -> Result<Vec<f32>, String> {
+		let size = count
+			.checked_mul(4)
+			.ok_or_else(|| String::from("Truncated state file"))?;
+		// Take the bytes first so a corrupted count fails before allocating.
+		let bytes = self.take(size)?;
+		let mut values = Vec::with_capacity(count);
+		for chunk in bytes.chunks(4) {
+			let mut word = [0; 4];
+			word.copy_from_slice(chunk);
+			values.push(f32::from_le_bytes(word));
+		}
+		Ok(values)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn header(dims: (u32, u32)) -> Vec<u8> {
+		let mut bytes = MAGIC.to_vec();
+		for word in [STATE_VERSION, dims.0, dims.1].iter() {
+			bytes.extend_from_slice(&word.to_le_bytes());
+		}
+		bytes
+	}
+
+	#[test]
+	fn rejects_oversized_grid() {
+		for dims in [(u32::MAX, u32::MAX), (100_000, 100_000), (3, 0)].iter() {
+			let err = read_state(&header(*dims)).unwrap_err();
+			assert!(err.starts_with("Invalid grid size"), "{}", err);
+		}
+	}
+
+	#[test]
+	fn rejects_truncated_layers() {
+		let mut bytes = header((4, 4));
+		bytes.extend_from_slice(&[0; 10]);
+		assert_eq!(read_state(&bytes).unwrap_err(), "Truncated state file");
+	}
+
+	#[test]
+	fn round_trips() {
+		let mut water = Water::new((3, 2));
+		water.add_depth(1, 1, 2.5);
+		let path = std::env::temp_dir().join("mod1_snapshot_test.state");
+		let path = path.to_str().unwrap();
+		save_state(path, &water, &Rain::new()).unwrap();
+		let (loaded, rain) = load_state(path).unwrap();
+		std::fs::remove_file(path).unwrap();
+		assert_eq!(loaded.depths(), water.depths());
+		assert!(rain.droplets().is_empty());
+	}
+
+	/// A 3x2 state with every depth at `depth`, the first `x` pipe at `flow`
+	/// and one droplet.
+	fn state(depth: f32, flow: f32, droplet: [f32; 3]) -> Vec<u8> {
+		let mut bytes = header((3, 2));
+		let mut values = vec![depth; 6];
+		values.push(flow);
+		values.extend_from_slice(&[0.0; 3 + 3 + 2 + 2 + 3 + 3]);
+		for value in values.iter() {
+			bytes.extend_from_slice(&value.to_le_bytes());
+		}
+		bytes.extend_from_slice(&1u32.to_le_bytes());
+		for value in droplet.iter() {
+			bytes.extend_from_slice(&value.to_le_bytes());
+		}
+		bytes
+	}
+
+	#[test]
+	fn loads_valid_state() {
+		let (water, rain) = read_state(&state(0.5, -1.0, [2.5, 1.5, 10.0])).unwrap();
+		assert_eq!(water.depths(), [0.5; 6]);
+		assert_eq!(water.flows_x()[0], -1.0);
+		assert_eq!(rain.droplets(), [Vec3::new(2.5, 1.5, 10.0)]);
+	}
+
+	#[test]
+	fn rejects_corrupted_values() {
+		let cases = [
+			(state(-1.0, 0.0, [1.0, 1.0, 1.0]), "Invalid depth '-1'"),
+			(state(f32::NAN, 0.0, [1.0, 1.0, 1.0]), "Invalid depth 'NaN'"),
+			(
+				state(f32::INFINITY, 0.0, [1.0, 1.0, 1.0]),
+				"Invalid depth 'inf'",
+			),
+			(state(0.0, f32::NAN, [1.0, 1.0, 1.0]), "Invalid flow 'NaN'"),
+			(
+				state(0.0, 0.0, [1e6, 1.0, 1.0]),
+				"Invalid droplet (1000000, 1, 1)",
+			),
+			(
+				state(0.0, 0.0, [1.0, 2.0, 1.0]),
+				"Invalid droplet (1, 2, 1)",
+			),
+			(
+				state(0.0, 0.0, [-0.5, 1.0, 1.0]),
+				"Invalid droplet (-0.5, 1, 1)",
+			),
+			(
+				state(0.0, 0.0, [1.0, 1.0, f32::NAN]),
+				"Invalid droplet (1, 1, NaN)",
+			),
+		];
+		for (bytes, message) in cases.iter() {
+			let err = read_state(bytes).err().unwrap_or_default();
+			assert!(err.starts_with(message), "{}", err);
+		}
+	}
+}
@@ -0,0 +1,247 @@
+use super::map_error::strip_comment;
+use super::{HeightMap, Map, Water, DEFAULT_DIM};
+
+use glam::Vec3;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Depth of water per second soaking into completely dry ground.
+const INFILTRATION_RATE: f32 = 0.5;
+/// Fraction of the soaked water per second draining deeper in the ground,
+/// so that the soil can take in water again after a while.
+const PERCOLATION_RATE: f32 = 0.01;
+
+/// Water leaving a [`Water`] grid by evaporating and soaking into the
+/// ground, with the saturation of the soil under each cell.
+#[derive(Debug)]
+pub struct Soil {
+	width: usize,
+	capacity: Map,
+	evaporation: Map,
+	saturation: Map,
+	evaporated: f64,
+	infiltrated: f64,
+}
+
+impl Soil {
+	pub const MAX_CAPACITY: f32 = 50.0;
+
+	/// Create dry soil of `dims` cells which can all hold `capacity` units of
+	/// water depth, under water evaporating at `evaporation` units per second.
+	pub fn new(dims: (usize, usize), capacity: f32, evaporation: f32) -> Self {
+		let count = dims.0 * dims.1;
+		Soil {
+			width: dims.0,
+			capacity: vec![capacity; count],
+			evaporation: vec![evaporation; count],
+			saturation: vec![0.0; count],
+			evaporated: 0.0,
+			infiltrated: 0.0,
+		}
+	}
+
+	/// Load a soil layer file and interpolate it on a grid of `dims` cells.
+	///
+	/// Like a map file it can start with a `size <width> <height>` line, then
+	/// gives one `x y capacity [evaporation]` point per line. Cells use
+	/// `evaporation` unless points give their own rate. Blank lines and `#`
+	/// comments are skipped.
+	pub fn from_file(
+		filename: &str,
+		dims: (usize, usize),
+		evaporation: f32,
+	) -> Result<Self, String> {
+		let file = File::open(filename).map_err(|err| format!("Failed to read file: {}", err))?;
+		let mut file_dims = (DEFAULT_DIM, DEFAULT_DIM);
+		let mut capacities = Vec::new();
+		let mut rates = Vec::new();
+		for (index_l, line) in BufReader::new(file).lines().enumerate() {
+			let line = line.map_err(|err| format!("Failed to read file: {}", err))?;
+			let line = strip_comment(&line);
+			if line.trim().is_empty() {
+				continue;
+			}
+			if capacities.is_empty() && line.trim_start().starts_with("size") {
+				file_dims = HeightMap::parse_size(line)
+					.map_err(|err| format!("[line: {}] {}", index_l + 1, err))?;
+				continue;
+			}
+			let values = Soil::parse_point(line, index_l + 1, file_dims)?;
+			capacities.push(Vec3::new(values[0], values[1], values[2]));
+			if let Some(rate) = values.get(3) {
+				rates.push(Vec3::new(values[0], values[1], *rate));
+			}
+		}
+		if capacities.is_empty() {
+			return Err(format!("No soil point in '{}'", filename));
+		}
+		let (width, height) = dims;
+		let capacities = HeightMap::scale_points(capacities, file_dims, dims);
+		let rates = HeightMap::scale_points(rates, file_dims, dims);
+		let mut soil = Soil::new(dims, 0.0, evaporation);
+		soil.capacity = HeightMap::interpolate(&capacities, width, height);
+		if !rates.is_empty() {
+			soil.evaporation = HeightMap::interpolate(&rates, width, height);
+		}
+		Ok(soil)
+	}
+
+	fn parse_point(
+		line: &str,
+		index_l: usize,
+		file_dims: (usize, usize),
+	) -> Result<Vec<f32>, String> {
+		let mut values = Vec::new();
+		for (index_v, value) in line.split_ascii_whitespace().enumerate() {
+			if index_v > 3 {
+				return Err(format!("[line: {}] Too many values", index_l));
+			}
+			let number: f32 = value.parse().map_err(|err| {
+				format!(
+					"[line: {}, pos: {}] Invalid value '{}' ({})",
+					index_l,
+					index_v + 1,
+					value,
+					err
+				)
+			})?;
+			if index_v < 2 {
+				let max = [file_dims.0, file_dims.1][index_v] - 1;
+				if !(0.0..=max as f32).contains(&number) {
+					return Err(format!(
+						"[line: {}, pos: {}] Invalid coordinate number '{}' (not between 0 and {})",
+						index_l,
+						index_v + 1,
+						number,
+						max
+					));
+				}
+			}
+			let valid = match index_v {
+				0 | 1 => true,
+				2 => (0.0..=Soil::MAX_CAPACITY).contains(&number),
+				_ => number >= 0.0 && number.is_finite(),
+			};
+			if !valid {
+				return Err(format!(
+					"[line: {}, pos: {}] Invalid value '{}' (out of range)",
+					index_l,
+					index_v + 1,
+					number
+				));
+			}
+			values.push(number);
+		}
+		if values.len() < 3 {
+			return Err(format!("[line: {}] Not enough values", index_l));
+		}
+		Ok(values)
+	}
+
+	/// Evaporate the water of every cell and let it soak into the ground for
+	/// `delta_time`, then let the soaked water drain deeper.
+	pub fn update(&mut self, delta_time: f32, water: &mut Water) {
+		for index in 0..self.saturation.len() {
+			let (x, y) = (index % self.width, index / self.width);
+			let evaporation = self.evaporation[index] * delta_time;
+			if water.depth(x, y) > 0.0 && evaporation > 0.0 {
+				self.evaporated += water.remove_depth(x, y, evaporation) as f64;
+			}
+			let room = self.capacity[index] - self.saturation[index];
+			if water.depth(x, y) > 0.0 && room > 0.0 {
+				let rate = INFILTRATION_RATE * room / self.capacity[index];
+				let soaked = water.remove_depth(x, y, f32::min(rate * delta_time, room));
+				self.saturation[index] += soaked;
+				self.infiltrated += soaked as f64;
+			}
+			self.saturation[index] -= self.saturation[index] * PERCOLATION_RATE * delta_time;
+		}
+	}
+
+	/// Water depth soaked in the ground under every cell, stored row by row.
+	pub fn saturation(&self) -> &[f32] {
+		&self.saturation
+	}
+
+	/// Water depth the ground under every cell can soak, stored row by row.
+	pub fn capacity(&self) -> &[f32] {
+		&self.capacity
+	}
+
+	/// Total water depth evaporated since the start.
+	pub fn evaporated(&self) -> f64 {
+		self.evaporated
+	}
+
+	/// Total water depth soaked into the ground since the start.
+	pub fn infiltrated(&self) -> f64 {
+		self.infiltrated
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn skips_comments_and_blank_lines() {
+		let path = std::env::temp_dir().join("mod1_soil_test.soil");
+		let path = path.to_str().unwrap();
+		std::fs::write(path, "# Soil\nsize 10 10\n\n5 5 2 # Clay\n  \n3 3 4 0.1\n").unwrap();
+		let soil = Soil::from_file(path, (10, 10), 0.0);
+		std::fs::remove_file(path).unwrap();
+		assert!(soil.is_ok(), "{:?}", soil.err());
+	}
+
+	#[test]
+	fn coordinates_span_the_whole_grid() {
+		for line in ["0 0 1", "9 4 1", "4.5 0 1"].iter() {
+			assert!(Soil::parse_point(line, 1, (10, 5)).is_ok(), "{}", line);
+		}
+		assert_eq!(
+			Soil::parse_point("10 0 1", 1, (10, 5)),
+			Err(String::from(
+				"[line: 1, pos: 1] Invalid coordinate number '10' (not between 0 and 9)"
+			))
+		);
+		assert!(Soil::parse_point("0 -1 1", 1, (10, 5)).is_err());
+		assert!(Soil::parse_point("0 NaN 1", 1, (10, 5)).is_err());
+	}
+
+	#[test]
+	fn evaporation_stops_at_zero_depth() {
+		let mut soil = Soil::new((3, 1), 0.0, 0.2);
+		let mut water = Water::new((3, 1));
+		water.add_depth(0, 0, 0.5);
+		water.add_depth(1, 0, 0.1);
+		soil.update(1.0, &mut water);
+		assert!((water.depth(0, 0) - 0.3).abs() < 1e-6);
+		assert_eq!((water.depth(1, 0), water.depth(2, 0)), (0.0, 0.0));
+		assert!((soil.evaporated() - 0.3).abs() < 1e-6);
+		assert_eq!(soil.infiltrated(), 0.0);
+	}
+
+	#[test]
+	fn infiltration_stops_when_the_soil_is_full() {
+		let mut soil = Soil::new((2, 1), 0.2, 0.0);
+		let mut water = Water::new((2, 1));
+		water.add_depth(0, 0, 5.0);
+		water.add_depth(1, 0, 0.05);
+		// Dry soil soaks the infiltration rate, capped by the room left.
+		soil.update(0.1, &mut water);
+		assert!((water.depth(0, 0) - (5.0 - INFILTRATION_RATE * 0.1)).abs() < 1e-6);
+		assert_eq!(water.depth(1, 0), 0.0);
+		for _ in 0..100 {
+			let (depth, infiltrated) = (water.depth(0, 0), soil.infiltrated());
+			soil.update(1.0, &mut water);
+			let soaked = depth - water.depth(0, 0);
+			assert!((soaked as f64 - (soil.infiltrated() - infiltrated)).abs() < 1e-6);
+			assert!(soil.saturation()[0] <= 0.2);
+		}
+		// Once full, the soil only takes in what percolates deeper.
+		let depth = water.depth(0, 0);
+		soil.update(1.0, &mut water);
+		assert!(depth - water.depth(0, 0) <= 0.2 * PERCOLATION_RATE + 1e-6);
+		assert!(water.depth(0, 0) > 4.0);
+	}
+}
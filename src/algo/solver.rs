@@ -0,0 +1,38 @@
+use super::{HeightMap, PipeSolver, ShallowWater, Water};
+
+use std::fmt;
+
+/// Names of the solvers [`new_solver`] can create.
+pub const SOLVERS: [&str; 2] = ["pipes", "shallow"];
+
+/// A way to move the water of a [`Water`] grid over a terrain.
+///
+/// [`Water::update`] splits each update in substeps of at most
+/// [`stable_step`](WaterSolver::stable_step), asks the solver for the flow
+/// through every cell side during the substep, then moves the water along
+/// those flows. The depths stay positive and the volume is accounted for
+/// whatever the solver.
+pub trait WaterSolver: fmt::Debug + Send + Sync {
+	fn name(&self) -> &'static str;
+
+	/// Longest substep the solver stays stable for with the current water.
+	fn stable_step(&self, water: &Water, terrain: &HeightMap) -> f32;
+
+	/// Set the flow through every side of the cells of `water` for the next
+	/// `delta_time`, see [`Water::flows_x`], [`Water::flows_y`] and
+	/// [`Water::flows_edge`].
+	fn update_flows(&mut self, water: &mut Water, terrain: &HeightMap, delta_time: f32);
+}
+
+/// Create the solver called `name`, one of [`SOLVERS`].
+pub fn new_solver(name: &str) -> Result<Box<dyn WaterSolver>, String> {
+	match name {
+		"pipes" => Ok(Box::new(PipeSolver)),
+		"shallow" => Ok(Box::new(ShallowWater::default())),
+		_ => Err(format!(
+			"Unknown solver '{}' (expected {})",
+			name,
+			SOLVERS.join(" or ")
+		)),
+	}
+}
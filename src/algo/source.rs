@@ -0,0 +1,280 @@
+use glam::Vec2;
+
+/// What a [`Source`] does with the water of its cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceKind {
+	/// Water welling up anywhere on the grid.
+	Spring,
+	/// A river entering the grid, on one of its edges.
+	Inlet,
+	/// Water leaving the grid, as long as the cell holds some.
+	Drain,
+}
+
+impl SourceKind {
+	pub fn parse(value: &str) -> Result<Self, String> {
+		match value {
+			"spring" => Ok(SourceKind::Spring),
+			"inlet" => Ok(SourceKind::Inlet),
+			"drain" => Ok(SourceKind::Drain),
+			_ => Err(format!(
+				"Unknown source '{}' (expected spring, inlet or drain)",
+				value
+			)),
+		}
+	}
+
+	pub fn name(self) -> &'static str {
+		match self {
+			SourceKind::Spring => "spring",
+			SourceKind::Inlet => "inlet",
+			SourceKind::Drain => "drain",
+		}
+	}
+}
+
+/// Flow of a [`Source`] in units of water depth per second.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rate {
+	Constant(f32),
+	/// `(time, rate)` pairs sorted by time, the rate is interpolated between
+	/// them and held after the last one, or starts over from the first one
+	/// when `repeat` is set.
+	Schedule {
+		points: Vec<(f32, f32)>,
+		repeat: bool,
+	},
+}
+
+impl Rate {
+	pub const MAX_RATE: f32 = 50.0;
+
+	/// Parse a constant `<rate>` or a `<time>:<rate>,<time>:<rate>...`
+	/// schedule starting at time 0.
+	pub fn parse(value: &str, repeat: bool) -> Result<Self, String> {
+		if !value.contains(':') {
+			return Rate::parse_rate(value).map(Rate::Constant);
+		}
+		let mut points: Vec<(f32, f32)> = Vec::new();
+		for pair in value.split(',') {
+			let mut parts = pair.splitn(2, ':');
+			let time = parts.next().unwrap_or_default();
+			let rate = parts.next().ok_or_else(|| {
+				format!("Invalid schedule entry '{}' (expected <time>:<rate>)", pair)
+			})?;
+			let time: f32 = time
+				.parse()
+				.map_err(|err| format!("Invalid schedule time '{}' ({})", time, err))?;
+			let previous = points.last().map_or(-1.0, |point| point.0);
+			if points.is_empty() && time != 0.0 {
+				return Err(format!("Schedule must start at time 0, not {}", time));
+			}
+			if !time.is_finite() || time <= previous {
+				return Err(format!(
+					"Invalid schedule time '{}' (times must increase)",
+					time
+				));
+			}
+			points.push((time, Rate::parse_rate(rate)?));
+		}
+		if repeat && points.len() < 2 {
+			return Err(String::from("A repeated schedule needs at least two times"));
+		}
+		Ok(Rate::Schedule { points, repeat })
+	}
+
+	fn parse_rate(value: &str) -> Result<f32, String> {
+		let rate: f32 = value
+			.parse()
+			.map_err(|err| format!("Invalid rate '{}' ({})", value, err))?;
+		if !(0.0..=Rate::MAX_RATE).contains(&rate) {
+			return Err(format!(
+				"Invalid rate '{}' (not between 0 and {})",
+				rate,
+				Rate::MAX_RATE
+			));
+		}
+		Ok(rate)
+	}
+
+	/// Rate `time` seconds after the start.
+	pub fn at(&self, time: f32) -> f32 {
+		match self {
+			Rate::Constant(rate) => *rate,
+			Rate::Schedule { points, repeat } => {
+				let last = points[points.len() - 1];
+				let time = if *repeat { time % last.0 } else { time };
+				match points.iter().position(|point| point.0 > time) {
+					None => last.1,
+					Some(0) => points[0].1,
+					Some(next) => {
+						let (t0, r0) = points[next - 1];
+						let (t1, r1) = points[next];
+						r0 + (r1 - r0) * (time - t0) / (t1 - t0)
+					}
+				}
+			}
+		}
+	}
+}
+
+/// A named cell where water is added or removed at a given rate every step
+/// of the [`Water`](super::Water) it is set on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+	name: String,
+	kind: SourceKind,
+	position: Vec2,
+	rate: Rate,
+	total: f64,
+}
+
+impl Source {
+	pub fn new(name: &str, kind: SourceKind, cell: (usize, usize), rate: Rate) -> Self {
+		Source {
+			name: String::from(name),
+			kind,
+			position: Vec2::new(cell.0 as f32, cell.1 as f32),
+			rate,
+			total: 0.0,
+		}
+	}
+
+	/// Parse a `<spring|inlet|drain> <name> <x> <y> <rate> [repeat]` map file
+	/// line, with coordinates on a `file_dims` grid. Inlets must lie on an
+	/// edge of the grid.
+	pub(crate) fn parse(line: &str, file_dims: (usize, usize)) -> Result<Self, String> {
+		let values: Vec<&str> = line.split_ascii_whitespace().collect();
+		let repeat = match values.get(5) {
+			None => false,
+			Some(&"repeat") => true,
+			Some(value) => return Err(format!("Unexpected '{}' after the rate", value)),
+		};
+		if values.len() < 5 || values.len() > 6 {
+			return Err(format!(
+				"{} needs a name, x and y coordinates and a rate",
+				values[0]
+			));
+		}
+		let kind = SourceKind::parse(values[0])?;
+		let mut position = [0.0; 2];
+		for (index_v, max) in [file_dims.0, file_dims.1].iter().enumerate() {
+			let value = values[2 + index_v];
+			let number: f32 = value
+				.parse()
+				.map_err(|err| format!("Invalid value '{}' ({})", value, err))?;
+			let max = (*max - 1) as f32;
+			if !(0.0..=max).contains(&number) {
+				return Err(format!(
+					"Invalid coordinate number '{}' (not between 0 and {})",
+					number, max
+				));
+			}
+			position[index_v] = number;
+		}
+		let on_edge = position[0] == 0.0
+			|| position[1] == 0.0
+			|| position[0] == (file_dims.0 - 1) as f32
+			|| position[1] == (file_dims.1 - 1) as f32;
+		if kind == SourceKind::Inlet && !on_edge {
+			return Err(format!(
+				"Inlet '{}' is not on an edge of the map",
+				values[1]
+			));
+		}
+		Ok(Source {
+			name: String::from(values[1]),
+			kind,
+			position: Vec2::from(position),
+			rate: Rate::parse(values[4], repeat)?,
+			total: 0.0,
+		})
+	}
+
+	/// Move a source given in the coordinates of a `file_dims` grid to the
+	/// coordinates of a `dims` grid.
+	pub(crate) fn scale(&mut self, file_dims: (usize, usize), dims: (usize, usize)) {
+		self.position *= Vec2::new(
+			(dims.0 - 1) as f32 / (file_dims.0 - 1) as f32,
+			(dims.1 - 1) as f32 / (file_dims.1 - 1) as f32,
+		);
+	}
+
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	pub fn kind(&self) -> SourceKind {
+		self.kind
+	}
+
+	/// Coordinates of the cell the source adds water to or drains.
+	pub fn cell(&self) -> (usize, usize) {
+		(
+			self.position.x.round() as usize,
+			self.position.y.round() as usize,
+		)
+	}
+
+	pub fn rate(&self) -> &Rate {
+		&self.rate
+	}
+
+	/// Total water volume added, or removed for a drain, since the source was
+	/// set on the water.
+	pub fn total(&self) -> f64 {
+		self.total
+	}
+
+	pub(crate) fn record(&mut self, volume: f64) {
+		self.total += volume;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn rates(value: &str, repeat: bool, times: &[f32]) -> Vec<f32> {
+		let rate = Rate::parse(value, repeat).unwrap();
+		times.iter().map(|time| rate.at(*time)).collect()
+	}
+
+	#[test]
+	fn constant_rate_never_changes() {
+		assert_eq!(Rate::parse("2.5", false), Ok(Rate::Constant(2.5)));
+		assert_eq!(rates("2.5", false, &[0.0, 10.0, 1e6]), vec![2.5; 3]);
+	}
+
+	#[test]
+	fn schedule_interpolates_then_holds_the_last_rate() {
+		let times = [0.0, 5.0, 10.0, 15.0, 20.0, 100.0];
+		assert_eq!(
+			rates("0:0,10:4,20:2", false, &times),
+			vec![0.0, 2.0, 4.0, 3.0, 2.0, 2.0]
+		);
+		// A step is a ramp over the shortest time.
+		assert_eq!(
+			rates("0:1,5:1,5.5:3", false, &[4.0, 5.25, 9.0]),
+			vec![1.0, 2.0, 3.0]
+		);
+	}
+
+	#[test]
+	fn repeated_schedule_starts_over() {
+		let times = [0.0, 5.0, 10.0, 15.0, 25.0, 30.0];
+		assert_eq!(
+			rates("0:0,10:4,20:2", true, &times),
+			vec![0.0, 2.0, 4.0, 3.0, 2.0, 4.0]
+		);
+		assert_eq!(rates("0:0,10:4", true, &[10.0, 12.5]), vec![0.0, 1.0]);
+	}
+
+	#[test]
+	fn rejects_invalid_schedules() {
+		for value in ["1:2,3:4", "0:1,0:2", "0:1,2", "0:-1", "0:1,5:51", "nan"].iter() {
+			assert!(Rate::parse(value, false).is_err(), "{}", value);
+		}
+		assert!(Rate::parse("0:1", true).is_err());
+	}
+}
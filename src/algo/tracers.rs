@@ -0,0 +1,195 @@
+use super::{Boundary, Edge, SourceKind, Water};
+
+use glam::Vec2;
+
+/// Angle between two tracers seeded in a row on a disk, spreading them
+/// evenly like the seeds of a sunflower.
+const GOLDEN_ANGLE: f32 = 2.399_963;
+
+/// Massless particles drifting with the water, to show where it goes.
+#[derive(Debug, Default)]
+pub struct Tracers {
+	positions: Vec<Vec2>,
+	ages: Vec<f32>,
+}
+
+impl Tracers {
+	pub const MAX_COUNT: usize = 2000;
+	/// Time after which a tracer disappears.
+	pub const LIFETIME: f32 = 40.0;
+
+	pub fn new() -> Self {
+		Tracers {
+			positions: Vec::new(),
+			ages: Vec::new(),
+		}
+	}
+
+	/// Add a tracer at `position`, in cells, if there is room for it.
+	pub fn seed(&mut self, position: Vec2) {
+		if self.positions.len() < Tracers::MAX_COUNT {
+			self.positions.push(position);
+			self.ages.push(0.0);
+		}
+	}
+
+	/// Add `count` tracers spread on a disk of `radius` cells around `center`.
+	pub fn seed_disk(&mut self, center: Vec2, radius: f32, count: usize) {
+		for k in 0..count {
+			let distance = radius * ((k as f32 + 0.5) / count as f32).sqrt();
+			let angle = k as f32 * GOLDEN_ANGLE;
+			self.seed(center + distance * Vec2::new(angle.cos(), angle.sin()));
+		}
+	}
+
+	/// Add a tracer every `spacing` cells where there is water.
+	pub fn seed_grid(&mut self, water: &Water, spacing: usize) {
+		let (dim_x, dim_y) = water.dims();
+		let zero_depth = water.params().zero_depth;
+		for y in (spacing / 2..dim_y).step_by(spacing.max(1)) {
+			for x in (spacing / 2..dim_x).step_by(spacing.max(1)) {
+				if water.depth(x, y) > zero_depth {
+					self.seed(Vec2::new(x as f32, y as f32));
+				}
+			}
+		}
+	}
+
+	/// Add a few tracers on every spring and inlet of `water`.
+	pub fn seed_sources(&mut self, water: &Water) {
+		for source in water.sources() {
+			if source.kind() != SourceKind::Drain {
+				let (x, y) = source.cell();
+				self.seed_disk(Vec2::new(x as f32, y as f32), 1.0, 5);
+			}
+		}
+	}
+
+	pub fn clear(&mut self) {
+		self.positions.clear();
+		self.ages.clear();
+	}
+
+	/// Move the tracers along the water velocity for `delta` with a midpoint
+	/// step. Tracers leaving the grid through a periodic edge come back from
+	/// the opposite one and closed edges hold them back, the others disappear
+	/// with the old ones.
+	pub fn update(&mut self, delta: f32, water: &Water) {
+		let (dim_x, dim_y) = water.dims();
+		// Outer sides of the border cells.
+		let (min, max) = (
+			Vec2::splat(-0.5),
+			Vec2::new(dim_x as f32 - 0.5, dim_y as f32 - 0.5),
+		);
+		let boundaries = water.boundaries();
+		let periodic_x = boundaries.get(Edge::East) == Boundary::Periodic;
+		let periodic_y = boundaries.get(Edge::North) == Boundary::Periodic;
+		let closed = |edge: Edge| boundaries.get(edge) == Boundary::Closed;
+		let low = Vec2::new(
+			if closed(Edge::West) { min.x } else { f32::MIN },
+			if closed(Edge::South) { min.y } else { f32::MIN },
+		);
+		let high = Vec2::new(
+			if closed(Edge::East) { max.x } else { f32::MAX },
+			if closed(Edge::North) { max.y } else { f32::MAX },
+		);
+		// Velocities are in terrain units, positions in cells.
+		let to_cells = 1.0 / water.params().cell_size;
+		for (position, age) in self.positions.iter_mut().zip(self.ages.iter_mut()) {
+			let half = *position + water.velocity_at(*position) * to_cells * delta * 0.5;
+			*position += water.velocity_at(half) * to_cells * delta;
+			if periodic_x {
+				position.x = wrap(position.x, dim_x as f32);
+			}
+			if periodic_y {
+				position.y = wrap(position.y, dim_y as f32);
+			}
+			*position = position.clamp(low, high);
+			*age += delta;
+		}
+		let mut kept = 0;
+		for k in 0..self.positions.len() {
+			let position = self.positions[k];
+			if self.ages[k] < Tracers::LIFETIME
+				&& position.cmpge(min).all()
+				&& position.cmple(max).all()
+			{
+				self.positions[kept] = position;
+				self.ages[kept] = self.ages[k];
+				kept += 1;
+			}
+		}
+		self.positions.truncate(kept);
+		self.ages.truncate(kept);
+	}
+
+	/// Position of every tracer, in cells.
+	pub fn positions(&self) -> &[Vec2] {
+		&self.positions
+	}
+
+	/// Time since each tracer was seeded, in the order of
+	/// [`Tracers::positions`].
+	pub fn ages(&self) -> &[f32] {
+		&self.ages
+	}
+}
+
+/// Bring `coordinate` back between `-0.5` and `dim - 0.5`, a periodic grid
+/// wrapping around at the outer sides of its border cells.
+fn wrap(coordinate: f32, dim: f32) -> f32 {
+	(coordinate + 0.5).rem_euclid(dim) - 0.5
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algo::{Boundaries, HeightMap};
+
+	/// Drift tracers seeded on every cell of a pond whose water piles up on
+	/// its west side, between `boundary` east and west edges.
+	fn drift(boundary: Boundary) -> Tracers {
+		let terrain = HeightMap::from_heights((12, 6), vec![0.0; 72]).unwrap();
+		let mut water = Water::new((12, 6));
+		for y in 0..6 {
+			for x in 0..12 {
+				water.add_depth(x, y, if x < 4 { 2.0 } else { 0.5 });
+			}
+		}
+		let mut boundaries = Boundaries::default();
+		boundaries.set(Edge::West, boundary);
+		boundaries.set(Edge::East, boundary);
+		water.set_boundaries(boundaries).unwrap();
+		let mut tracers = Tracers::new();
+		tracers.seed_grid(&water, 1);
+		assert_eq!(tracers.positions().len(), 72);
+		for _ in 0..100 {
+			water.update(&terrain, 0.1);
+			tracers.update(0.1, &water);
+		}
+		tracers
+	}
+
+	#[test]
+	fn closed_and_periodic_grids_keep_every_tracer() {
+		for boundary in [Boundary::Closed, Boundary::Periodic].iter() {
+			let tracers = drift(*boundary);
+			assert_eq!(tracers.positions().len(), 72, "{:?}", boundary);
+			for position in tracers.positions() {
+				assert!((-0.5..=11.5).contains(&position.x), "{:?}", boundary);
+				assert!((-0.5..=5.5).contains(&position.y), "{:?}", boundary);
+			}
+		}
+	}
+
+	#[test]
+	fn open_edges_and_age_take_tracers_away() {
+		let tracers = drift(Boundary::Open);
+		assert!(tracers.positions().len() < 72);
+		assert_eq!(tracers.ages().len(), tracers.positions().len());
+		let mut tracers = drift(Boundary::Closed);
+		let water = Water::new((12, 6));
+		tracers.update(Tracers::LIFETIME, &water);
+		assert!(tracers.positions().is_empty());
+	}
+}
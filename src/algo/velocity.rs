@@ -0,0 +1,32 @@
+use super::Water;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// Write the velocity field of `water` to a text file.
+///
+/// The first line is `<width> <height>`, then each cell gets a
+/// `x y velocity_x velocity_y froude` line, row by row. Dry cells have a zero
+/// velocity and Froude number.
+pub fn save_velocities(path: &str, water: &Water) -> Result<(), String> {
+	write_velocities(path, water).map_err(|err| format!("Failed to write file: {}", err))
+}
+
+fn write_velocities(path: &str, water: &Water) -> std::io::Result<()> {
+	let mut writer = BufWriter::new(File::create(path)?);
+	let (dim_x, dim_y) = water.dims();
+	writeln!(writer, "{} {}", dim_x, dim_y)?;
+	for (index, velocity) in water.velocities().iter().enumerate() {
+		let (x, y) = (index % dim_x, index / dim_x);
+		writeln!(
+			writer,
+			"{} {} {:.4} {:.4} {:.4}",
+			x,
+			y,
+			velocity.x,
+			velocity.y,
+			water.froude(x, y)
+		)?;
+	}
+	writer.flush()
+}
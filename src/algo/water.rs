@@ -1,194 +1,458 @@
-use super::{HeightMap, Map, DIM, G};
-use crate::engine::{Entity, EntityStore, Inputs, KeyCode, Mesh};
+use super::rows::rows_mut;
+use super::{
+	Boundaries, Boundary, Edge, HeightMap, Map, Sea, Source, SourceKind, VolumeLedger, WaterParams,
+	WaterSolver,
+};
 
-use glam::Vec3;
-use noise::{NoiseFn, Perlin, Seedable, Worley};
-use std::any::Any;
+use glam::Vec2;
 
-const ZERO_DEPTH: f32 = 0.01;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
-const D_MAP_SIZE: usize = DIM * DIM;
-const P_MAP_SIZE: usize = (DIM - 1) * DIM;
+mod pipes;
+mod shallow;
 
-const GRID_STEP: f32 = 1.0;
+pub use pipes::PipeSolver;
+pub use shallow::ShallowWater;
+
+pub const ZERO_DEPTH: f32 = 0.01;
+
+/// Fraction of the time a wave needs to cross a cell used as a substep.
+const COURANT: f32 = 0.5;
+const MAX_SUBSTEPS: u32 = 32;
+const MAX_LIMIT_PASSES: usize = 8;
+
+/// What happened during a call to [`Water::update`].
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StepReport {
+	/// Number of substeps the update was split into.
+	pub substeps: u32,
+	/// Shortest stable substep duration computed during the update.
+	pub stable_dt: f32,
+	/// The update needed more than `MAX_SUBSTEPS` substeps, so the last one
+	/// was longer than the stable duration.
+	pub clamped: bool,
+	/// Number of times the outflow of a cell had to be scaled down to keep
+	/// its depth positive.
+	pub limited_flows: usize,
+}
 
 #[derive(Debug)]
 pub struct Water {
-	mesh_id: u128,
-	terrain_id: u128,
-	border_id: u128,
-	depths: Map<D_MAP_SIZE>,
-	pipes_y: Map<P_MAP_SIZE>,
-	pipes_x: Map<P_MAP_SIZE>,
+	width: usize,
+	height: usize,
+	depths: Map,
+	pipes_y: Map,
+	pipes_x: Map,
+	/// Pipes through each edge, indexed by [`Edge`], a positive flow leaves
+	/// the grid. The east and north pipes of periodic edges lead to the
+	/// opposite cell, the west and south ones stay empty.
+	pipes_edge: [Map; 4],
+	boundaries: Boundaries,
 	avg_depth: f32,
-	input_str: u8,
+	ledger: VolumeLedger,
+	sources: Vec<Source>,
+	/// Time elapsed since the sources were set, to follow their schedules.
+	time: f32,
+	sea: Sea,
+	params: WaterParams,
+	/// Manning coefficient of every cell, [`WaterParams::manning`] everywhere
+	/// when `None`.
+	roughness: Option<Map>,
+	solver: Box<dyn WaterSolver>,
+	/// Limit the flows with the old worklist instead of the rows, only to
+	/// benchmark one against the other.
+	worklist_limiter: bool,
 }
 
 impl Water {
 	pub const MAX_HEIGHT: f32 = 50.0;
+	pub const MAX_STRENGTH: u8 = 2;
 
-	pub fn new(store: &EntityStore, terrain_id: u128) -> Self {
-		let noise = Water::foam_noise();
-		let water_vert = Mesh::heights_gen_vertices(DIM, &vec![-0.1; DIM * DIM]);
-		let water_mesh = Box::new(Mesh::new(
-			"water",
-			&water_vert,
-			DIM,
-			false,
-			false,
-			Some(&noise),
-		));
-		let mesh_id = store.to_new_queue(water_mesh);
-		let border_vert = Mesh::wall_gen_vertices(&vec![Vec3::ZERO; 800]);
-		let border_mesh = Box::new(Mesh::new("water", &border_vert, DIM, false, false, None));
-		let border_id = store.to_new_queue(border_mesh);
+	/// Create a dry water grid of `dims` cells, matching the terrain it will
+	/// flow on.
+	pub fn new(dims: (usize, usize)) -> Self {
+		let (width, height) = dims;
 		Water {
-			mesh_id,
-			terrain_id,
-			border_id,
-			depths: [0.0; D_MAP_SIZE],
-			pipes_y: [0.0; P_MAP_SIZE],
-			pipes_x: [0.0; P_MAP_SIZE],
+			width,
+			height,
+			depths: vec![0.0; width * height],
+			pipes_y: vec![0.0; width * (height - 1)],
+			pipes_x: vec![0.0; (width - 1) * height],
+			pipes_edge: Water::empty_edge_pipes(dims),
+			boundaries: Boundaries::default(),
 			avg_depth: 0.0,
-			input_str: 0,
+			ledger: VolumeLedger::default(),
+			sources: Vec::new(),
+			time: 0.0,
+			sea: Sea::default(),
+			params: WaterParams::default(),
+			roughness: None,
+			solver: Box::new(PipeSolver),
+			worklist_limiter: false,
 		}
 	}
 
-	fn interpolate_noise(x: i32, y: i32, scale: f64, noise: &Perlin) -> u8 {
-		if x < 974 && y < 974 {
-			(((noise.get([x as f64 / scale * 10.0, y as f64 / scale * 10.0]) + 1.0) / 2.0) * 255.0)
-				as u8
-		} else {
-			if y < 974 {
-				let last =
-					(noise.get([974 as f64 / scale * 10.0, y as f64 / scale * 10.0]) + 1.0) / 2.0;
-				let first =
-					(noise.get([0 as f64 / scale * 10.0, y as f64 / scale * 10.0]) + 1.0) / 2.0;
-				let k = (x - 974) as f64 / 50.0;
-				((last * (1.0 - k) + first * k) * 255.0) as u8
-			} else if x < 974 {
-				let last =
-					(noise.get([x as f64 / scale * 10.0, 974 as f64 / scale * 10.0]) + 1.0) / 2.0;
-				let first =
-					(noise.get([x as f64 / scale * 10.0, 0 as f64 / scale * 10.0]) + 1.0) / 2.0;
-				let k = (y - 974) as f64 / 50.0;
-				((last * (1.0 - k) + first * k) * 255.0) as u8
+	pub(crate) fn from_state(
+		dims: (usize, usize),
+		depths: Map,
+		pipes_x: Map,
+		pipes_y: Map,
+		pipes_edge: Option<[Map; 4]>,
+	) -> Self {
+		let avg_depth = depths.iter().sum::<f32>() / depths.len() as f32;
+		let mut water = Water {
+			width: dims.0,
+			height: dims.1,
+			depths,
+			pipes_y,
+			pipes_x,
+			pipes_edge: pipes_edge.unwrap_or_else(|| Water::empty_edge_pipes(dims)),
+			boundaries: Boundaries::default(),
+			avg_depth,
+			ledger: VolumeLedger::default(),
+			sources: Vec::new(),
+			time: 0.0,
+			sea: Sea::default(),
+			params: WaterParams::default(),
+			roughness: None,
+			solver: Box::new(PipeSolver),
+			worklist_limiter: false,
+		};
+		water.ledger = VolumeLedger::new(water.volume());
+		water
+	}
+
+	fn empty_edge_pipes(dims: (usize, usize)) -> [Map; 4] {
+		let (width, height) = dims;
+		[
+			vec![0.0; height],
+			vec![0.0; height],
+			vec![0.0; width],
+			vec![0.0; width],
+		]
+	}
+
+	/// Change what happens to the water reaching each edge of the grid. The
+	/// flow through edges that no longer let water through is dropped.
+	pub fn set_boundaries(&mut self, boundaries: Boundaries) -> Result<(), String> {
+		boundaries.check()?;
+		self.boundaries = boundaries;
+		for edge in Edge::ALL.iter() {
+			if !self.has_edge_pipes(*edge) {
+				self.pipes_edge[*edge as usize]
+					.iter_mut()
+					.for_each(|flow| *flow = 0.0);
+			}
+		}
+		Ok(())
+	}
+
+	pub fn boundaries(&self) -> &Boundaries {
+		&self.boundaries
+	}
+
+	/// Replace the sources and drains applied at every update, their
+	/// schedules start over.
+	pub fn set_sources(&mut self, sources: Vec<Source>) -> Result<(), String> {
+		for source in sources.iter() {
+			let (x, y) = source.cell();
+			if x >= self.width || y >= self.height {
+				return Err(format!(
+					"Source '{}' at {}x{} is out of the {}x{} grid",
+					source.name(),
+					x,
+					y,
+					self.width,
+					self.height
+				));
+			}
+		}
+		self.sources = sources;
+		self.time = 0.0;
+		Ok(())
+	}
+
+	pub fn sources(&self) -> &[Source] {
+		&self.sources
+	}
+
+	/// Change the part of the terrain [`tide`](Water::tide) and
+	/// [`drain`](Water::drain) work on.
+	pub fn set_sea(&mut self, sea: Sea) -> Result<(), String> {
+		if let Some((x, y)) = sea.from() {
+			if x >= self.width || y >= self.height {
+				return Err(format!(
+					"Sea cell {}x{} is out of the {}x{} grid",
+					x, y, self.width, self.height
+				));
+			}
+		}
+		self.sea = sea;
+		Ok(())
+	}
+
+	pub fn sea(&self) -> &Sea {
+		&self.sea
+	}
+
+	/// Change the physical parameters of the solver, after checking them.
+	pub fn set_params(&mut self, params: WaterParams) -> Result<(), String> {
+		params.check()?;
+		self.params = params;
+		Ok(())
+	}
+
+	pub fn params(&self) -> &WaterParams {
+		&self.params
+	}
+
+	/// Give every cell its own Manning coefficient, stored row by row, or go
+	/// back to the coefficient of the parameters with `None`.
+	pub fn set_roughness(&mut self, roughness: Option<Map>) -> Result<(), String> {
+		if let Some(roughness) = &roughness {
+			if roughness.len() != self.depths.len() {
+				return Err(format!(
+					"Invalid roughness count {} for a {}x{} grid",
+					roughness.len(),
+					self.width,
+					self.height
+				));
+			}
+			if let Some(n) = roughness
+				.iter()
+				.find(|n| !(0.0..=WaterParams::MAX_MANNING).contains(*n))
+			{
+				return Err(format!(
+					"Invalid roughness '{}' (not between 0 and {})",
+					n,
+					WaterParams::MAX_MANNING
+				));
+			}
+		}
+		self.roughness = roughness;
+		Ok(())
+	}
+
+	/// Manning coefficient of every cell, if they have their own.
+	pub fn roughness(&self) -> Option<&[f32]> {
+		self.roughness.as_deref()
+	}
+
+	/// Change the way the water moves, the pipe model by default.
+	pub fn set_solver(&mut self, solver: Box<dyn WaterSolver>) {
+		self.solver = solver;
+	}
+
+	pub fn solver(&self) -> &dyn WaterSolver {
+		self.solver.as_ref()
+	}
+
+	/// Limit the flows with the worklist used before the row limiter, which
+	/// checks the neighbours of every limited cell one at a time. Kept as a
+	/// reference for the `limit_flows` benchmark.
+	#[doc(hidden)]
+	pub fn set_worklist_limiter(&mut self, worklist: bool) {
+		self.worklist_limiter = worklist;
+	}
+
+	fn has_edge_pipes(&self, edge: Edge) -> bool {
+		match self.boundaries.get(edge) {
+			Boundary::Closed => false,
+			Boundary::Periodic => edge == Edge::East || edge == Edge::North,
+			Boundary::Open | Boundary::Reservoir(_) => true,
+		}
+	}
+
+	/// Index of the cell at position `k` along `edge`.
+	fn edge_cell(&self, edge: Edge, k: usize) -> usize {
+		let (dim_x, dim_y) = (self.width, self.height);
+		match edge {
+			Edge::West => k * dim_x,
+			Edge::East => dim_x - 1 + k * dim_x,
+			Edge::South => k,
+			Edge::North => k + (dim_y - 1) * dim_x,
+		}
+	}
+
+	/// Position along `edge` of the cell at `(i, j)`, if it lies on it.
+	fn edge_position(&self, edge: Edge, i: usize, j: usize) -> Option<usize> {
+		match edge {
+			Edge::West if i == 0 => Some(j),
+			Edge::East if i == self.width - 1 => Some(j),
+			Edge::South if j == 0 => Some(i),
+			Edge::North if j == self.height - 1 => Some(i),
+			_ => None,
+		}
+	}
+
+	/// Depth and terrain height of what lies past the cell at position `k`
+	/// along `edge`, if water can flow there.
+	fn outside_cell(&self, terrain: &HeightMap, edge: Edge, k: usize) -> Option<(f32, f32)> {
+		if !self.has_edge_pipes(edge) {
+			return None;
+		}
+		let ground = terrain.height_points()[self.edge_cell(edge, k)];
+		match self.boundaries.get(edge) {
+			Boundary::Open => Some((0.0, ground)),
+			Boundary::Reservoir(level) => Some((f32::max(level - ground, 0.0), ground)),
+			_ => {
+				let index = self.edge_cell(edge.opposite(), k);
+				Some((self.depths[index], terrain.height_points()[index]))
+			}
+		}
+	}
+
+	/// Advance the water by `delta_time` over the given terrain with its
+	/// solver, split in as many substeps as the current flow speed needs to
+	/// stay stable.
+	pub fn update(&mut self, terrain: &HeightMap, delta_time: f32) -> StepReport {
+		let mut report = StepReport {
+			stable_dt: delta_time,
+			..StepReport::default()
+		};
+		self.apply_sources(delta_time);
+		// The solver is taken out for the update so it can change the grid.
+		let mut solver = std::mem::replace(&mut self.solver, Box::new(PipeSolver));
+		let mut remaining = delta_time;
+		while remaining > 0.0 {
+			let stable_dt = solver.stable_step(self, terrain);
+			report.stable_dt = f32::min(report.stable_dt, stable_dt);
+			let step = if report.substeps + 1 == MAX_SUBSTEPS {
+				report.clamped = remaining > stable_dt;
+				remaining
 			} else {
-				let last =
-					(noise.get([974 as f64 / scale * 10.0, 974 as f64 / scale * 10.0]) + 1.0) / 2.0;
-				let first =
-					(noise.get([974 as f64 / scale * 10.0, 0 as f64 / scale * 10.0]) + 1.0) / 2.0;
-				let k = (y - 974) as f64 / 50.0;
-				let last_interpol = last * (1.0 - k) + first * k;
-				let last =
-					(noise.get([0 as f64 / scale * 10.0, 974 as f64 / scale * 10.0]) + 1.0) / 2.0;
-				let first =
-					(noise.get([0 as f64 / scale * 10.0, 0 as f64 / scale * 10.0]) + 1.0) / 2.0;
-				let k = (y - 974) as f64 / 50.0;
-				let first_interpol = last * (1.0 - k) + first * k;
-				let k = (x - 974) as f64 / 50.0;
-				((last_interpol * (1.0 - k) + first_interpol * k) * 255.0) as u8
-			}
-		}
-	}
-
-	fn foam_noise() -> [[u8; 3]; 1024 * 1024] {
-		let whorley = Worley::default()
-			.set_frequency(10.0)
-			.set_seed(rand::random::<u32>());
-		let perlin_x = Perlin::default().set_seed(rand::random::<u32>());
-		let perlin_y = Perlin::default().set_seed(rand::random::<u32>());
-
-		let edge_w = 3;
-		let scale = 1024.0;
-		let mut edge = [[0; 3]; 1024 * 1024];
-		for x in 0..1024 {
-			for y in 0..1024 {
-				let x_pr = if x >= edge_w {
-					x - edge_w
-				} else {
-					1023 - x - edge_w
-				};
-				let x_nx = if x <= 1023 - edge_w {
-					x + edge_w
-				} else {
-					x + edge_w - 1023
-				};
-				let y_pr = if y >= edge_w {
-					y - edge_w
-				} else {
-					1023 - y - edge_w
-				};
-				let y_nx = if y <= 1023 - edge_w {
-					y + edge_w
-				} else {
-					y + edge_w - 1023
-				};
-				let val_l = (whorley.get([x_pr as f64 / scale, y as f64 / scale]) + 1.0) / 2.0;
-				let val_r = (whorley.get([x_nx as f64 / scale, y as f64 / scale]) + 1.0) / 2.0;
-				let val_top = (whorley.get([x as f64 / scale, y_pr as f64 / scale]) + 1.0) / 2.0;
-				let val_bot = (whorley.get([x as f64 / scale, y_nx as f64 / scale]) + 1.0) / 2.0;
-				let d_x = (val_l - val_r).abs();
-				let d_y = (val_top - val_bot).abs();
-				let noise_x = Water::interpolate_noise(x, y, scale, &perlin_x);
-				let noise_y = Water::interpolate_noise(x, y, scale, &perlin_y);
-				if d_x > 0.0 || d_y > 0.0 {
-					edge[(x + y * 1024) as usize] = [255, noise_x, noise_y];
-				} else {
-					edge[(x + y * 1024) as usize] = [0, noise_x, noise_y];
+				f32::min(remaining, stable_dt)
+			};
+			solver.update_flows(self, terrain, step);
+			report.limited_flows += if self.worklist_limiter {
+				self.limit_flows_worklist(step)
+			} else {
+				self.limit_flows(step)
+			};
+			self.update_depths(step);
+			self.record_edge_flows(step);
+			report.substeps += 1;
+			remaining -= step;
+		}
+		self.solver = solver;
+		self.ledger.record_step(self.volume());
+		report
+	}
+
+	/// Add and remove the water of every source for `delta_time`, at their
+	/// rate in the middle of the step.
+	fn apply_sources(&mut self, delta_time: f32) {
+		let mut sources = std::mem::take(&mut self.sources);
+		for source in sources.iter_mut() {
+			let (x, y) = source.cell();
+			let amount = source.rate().at(self.time + delta_time / 2.0) * delta_time;
+			if amount <= 0.0 {
+				continue;
+			}
+			if source.kind() == SourceKind::Drain {
+				if self.depth(x, y) > 0.0 {
+					let removed = self.remove_depth(x, y, amount);
+					source.record(self.cell_volume(removed));
 				}
+			} else {
+				self.add_depth(x, y, amount);
+				source.record(self.cell_volume(amount));
 			}
 		}
-		return edge;
-	}
-
-	fn update_pipes_flow(&mut self, delta_time: f32, store: &EntityStore) {
-		if let Some(ent_terrain) = store.get(self.terrain_id) {
-			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<HeightMap>() {
-				let flow_acceleration =
-					|depth: f32, depth_next: f32, terrain: f32, terrain_next: f32| {
-						let height_delta = (depth_next + terrain_next) - (depth + terrain);
-						let upwind_depth = f32::min(f32::max(depth, depth_next), 5.0);
-						return 1.0 * upwind_depth * G * height_delta * delta_time;
-					};
-				let flow_decceleration = f32::min(1.0 - delta_time * 0.05, 1.0);
-
-				for i in 0..(DIM - 1) {
-					for j in 0..DIM {
-						if self.depths[i + 1 + j * DIM] > ZERO_DEPTH
-							|| self.depths[i + j * DIM] > ZERO_DEPTH
-						{
-							self.pipes_x[i + j * (DIM - 1)] += flow_acceleration(
-								self.depths[i + j * DIM],
-								self.depths[i + 1 + j * DIM],
-								terrain.height_points()[i + j * DIM],
-								terrain.height_points()[i + 1 + j * DIM],
-							);
-							self.pipes_x[i + j * (DIM - 1)] *= flow_decceleration;
-						} else {
-							self.pipes_x[i + j * (DIM - 1)] = 0.0;
-						}
+		self.sources = sources;
+		self.time += delta_time;
+	}
+
+	/// Scale down the outflow of the cells that would end the substep with a
+	/// negative depth, and return how many times a cell was limited.
+	///
+	/// Each pass computes a factor for the cells from the current flows, then
+	/// scales each pipe by the factor of the cell it drains. A limited cell
+	/// brings less water to its neighbours, which may need limiting on the
+	/// next pass, so passes after the first only look at the rows around the
+	/// cells just limited. The last pass looks at every cell and ignores the
+	/// inflows, so no cell can go negative whatever its neighbours do.
+	fn limit_flows(&mut self, delta_time: f32) -> usize {
+		let (dim_x, dim_y) = (self.width, self.height);
+		let wraps = self.boundaries.south == Boundary::Periodic;
+		let mut factors = vec![1.0; self.depths.len()];
+		let mut to_check = vec![true; dim_y];
+		let mut limited = 0;
+		for pass in 0..MAX_LIMIT_PASSES {
+			let count_inflow = pass + 1 < MAX_LIMIT_PASSES;
+			let this = &*self;
+			let check = &to_check;
+			// Rows left out kept the factors of 1 written when they were last
+			// checked, none of their cells was limited since.
+			let row_counts = rows_mut(&mut factors, dim_x)
+				.map(|(j, row)| {
+					if !check[j] {
+						return 0;
 					}
-				}
-				for i in 0..DIM {
-					for j in 0..(DIM - 1) {
-						if self.depths[i + (j + 1) * DIM] > ZERO_DEPTH
-							|| self.depths[i + j * DIM] > ZERO_DEPTH
-						{
-							self.pipes_y[i + j * DIM] += flow_acceleration(
-								self.depths[i + j * DIM],
-								self.depths[i + (j + 1) * DIM],
-								terrain.height_points()[i + j * DIM],
-								terrain.height_points()[i + (j + 1) * DIM],
-							);
-							self.pipes_y[i + j * DIM] *= flow_decceleration;
-						} else {
-							self.pipes_y[i + j * DIM] = 0.0;
+					let mut count = 0;
+					for (i, factor) in row.iter_mut().enumerate() {
+						*factor = this.outflow_factor(i, j, delta_time, count_inflow);
+						if *factor < 1.0 {
+							count += 1;
 						}
 					}
+					count
+				})
+				.collect::<Vec<usize>>();
+			let limited_rows: Vec<bool> = row_counts.iter().map(|count| *count > 0).collect();
+			let count: usize = row_counts.iter().sum();
+			if count == 0 {
+				break;
+			}
+			limited += count;
+			self.scale_outflows(&factors, &limited_rows);
+			for (j, check) in to_check.iter_mut().enumerate() {
+				if pass + 2 == MAX_LIMIT_PASSES {
+					// The last pass ignores the inflows, so every outflow it
+					// leaves untouched has to be safe on its own.
+					*check = true;
+					continue;
 				}
+				let prev = if j > 0 {
+					j - 1
+				} else if wraps {
+					dim_y - 1
+				} else {
+					j
+				};
+				let next = if j < dim_y - 1 {
+					j + 1
+				} else if wraps {
+					0
+				} else {
+					j
+				};
+				*check = limited_rows[prev] || limited_rows[j] || limited_rows[next];
 			}
 		}
+		limited
+	}
+
+	/// Reference limiter: cap the outflow of every cell that would go
+	/// negative, then recheck the cells it was flowing to until none is left.
+	fn limit_flows_worklist(&mut self, delta_time: f32) -> usize {
+		let (dim_x, dim_y) = (self.width, self.height);
+		let mut to_check = Vec::new();
+		let mut limited = 0;
+		for i in 0..dim_x {
+			for j in 0..dim_y {
+				if self.cap_flow(i, j, &mut to_check, delta_time) {
+					limited += 1;
+				}
+			}
+		}
+		while let Some((i, j)) = to_check.pop() {
+			if self.cap_flow(i, j, &mut to_check, delta_time) {
+				limited += 1;
+			}
+		}
+		limited
 	}
 
 	fn cap_flow(
@@ -197,57 +461,112 @@ impl Water {
 		j: usize,
 		to_check: &mut Vec<(usize, usize)>,
 		delta_time: f32,
-	) {
+	) -> bool {
+		let (dim_x, dim_y) = (self.width, self.height);
 		let (flow_in, flow_out) = self.compute_flows(i, j);
-		let flow_sum = flow_in - flow_out;
-		let delta_depth = delta_time * flow_sum / (GRID_STEP * GRID_STEP);
-		if self.depths[i + j * DIM] + delta_depth < 0.0 {
-			let capped_out =
-				flow_in + (self.depths[i + j * DIM] * (GRID_STEP * GRID_STEP)) / delta_time;
-			let mut ratio = capped_out / flow_out;
-			if ratio.abs() >= 1.0 {
-				ratio = 0.0;
-			}
-			if i < DIM - 1 && -self.pipes_x[i + j * (DIM - 1)] > 0.0 {
-				self.pipes_x[i + j * (DIM - 1)] *= ratio;
-				if !to_check.contains(&(i + 1, j)) {
-					to_check.push((i + 1, j));
-				}
-			}
-			if i > 0 && self.pipes_x[i - 1 + j * (DIM - 1)] > 0.0 {
-				self.pipes_x[i - 1 + j * (DIM - 1)] *= ratio;
-				if !to_check.contains(&(i - 1, j)) {
-					to_check.push((i - 1, j));
-				}
-			}
-			if j < DIM - 1 && -self.pipes_y[i + j * DIM] > 0.0 {
-				self.pipes_y[i + j * DIM] *= ratio;
-				if !to_check.contains(&(i, j + 1)) {
-					to_check.push((i, j + 1));
-				}
+		let area = self.params.cell_size * self.params.cell_size;
+		let depth = self.depths[i + j * dim_x];
+		if depth + delta_time * (flow_in - flow_out) / area >= 0.0 {
+			return false;
+		}
+		let mut ratio = (flow_in + depth * area / delta_time) / flow_out;
+		if ratio.abs() >= 1.0 {
+			ratio = 0.0;
+		}
+		let mut push = |cell: (usize, usize)| {
+			if !to_check.contains(&cell) {
+				to_check.push(cell);
 			}
-			if j > 0 && self.pipes_y[i + (j - 1) * DIM] > 0.0 {
-				self.pipes_y[i + (j - 1) * DIM] *= ratio;
-				if !to_check.contains(&(i, j - 1)) {
-					to_check.push((i, j - 1));
+		};
+		if i < dim_x - 1 && -self.pipes_x[i + j * (dim_x - 1)] > 0.0 {
+			self.pipes_x[i + j * (dim_x - 1)] *= ratio;
+			push((i + 1, j));
+		}
+		if i > 0 && self.pipes_x[i - 1 + j * (dim_x - 1)] > 0.0 {
+			self.pipes_x[i - 1 + j * (dim_x - 1)] *= ratio;
+			push((i - 1, j));
+		}
+		if j < dim_y - 1 && -self.pipes_y[i + j * dim_x] > 0.0 {
+			self.pipes_y[i + j * dim_x] *= ratio;
+			push((i, j + 1));
+		}
+		if j > 0 && self.pipes_y[i + (j - 1) * dim_x] > 0.0 {
+			self.pipes_y[i + (j - 1) * dim_x] *= ratio;
+			push((i, j - 1));
+		}
+		for edge in Edge::ALL.iter() {
+			if let Some(k) = self.edge_position(*edge, i, j) {
+				let (pipe, sign) = self.edge_pipe(*edge);
+				if sign * self.pipes_edge[pipe][k] > 0.0 {
+					self.pipes_edge[pipe][k] *= ratio;
+					if self.boundaries.get(*edge) == Boundary::Periodic {
+						let index = self.edge_cell(edge.opposite(), k);
+						push((index % dim_x, index / dim_x));
+					}
 				}
 			}
 		}
+		true
 	}
 
-	fn limit_flows(&mut self, delta_time: f32) {
-		let mut to_check = Vec::new();
-		for i in 0..DIM {
-			for j in 0..DIM {
-				self.cap_flow(i, j, &mut to_check, delta_time);
-			}
+	/// Factor to apply to the outflow of the cell at `(i, j)` so its depth
+	/// stays positive, counting on its inflow only if `count_inflow` is set.
+	fn outflow_factor(&self, i: usize, j: usize, delta_time: f32, count_inflow: bool) -> f32 {
+		let (flow_in, flow_out) = self.compute_flows(i, j);
+		let flow_in = if count_inflow { flow_in } else { 0.0 };
+		let depth = self.depths[i + j * self.width];
+		let area = self.params.cell_size * self.params.cell_size;
+		let delta_depth = delta_time * (flow_in - flow_out) / area;
+		if depth + delta_depth < 0.0 {
+			let capped_out = flow_in + (depth * area) / delta_time;
+			(capped_out / flow_out).clamp(0.0, 1.0)
+		} else {
+			1.0
 		}
-		while let Some((i, j)) = to_check.pop() {
-			self.cap_flow(i, j, &mut to_check, delta_time);
+	}
+
+	/// Multiply the flow of every pipe by the factor of the cell it drains,
+	/// skipping the rows where no cell was limited.
+	fn scale_outflows(&mut self, factors: &[f32], limited_rows: &[bool]) {
+		let dim_x = self.width;
+		rows_mut(&mut self.pipes_x, dim_x - 1).for_each(|(j, row)| {
+			if !limited_rows[j] {
+				return;
+			}
+			for (i, flow) in row.iter_mut().enumerate() {
+				let source = if *flow > 0.0 { i + 1 } else { i };
+				*flow *= factors[source + j * dim_x];
+			}
+		});
+		rows_mut(&mut self.pipes_y, dim_x).for_each(|(j, row)| {
+			if !limited_rows[j] && !limited_rows[j + 1] {
+				return;
+			}
+			for (i, flow) in row.iter_mut().enumerate() {
+				let source = if *flow > 0.0 { j + 1 } else { j };
+				*flow *= factors[i + source * dim_x];
+			}
+		});
+		for edge in Edge::ALL.iter() {
+			let periodic = self.boundaries.get(*edge) == Boundary::Periodic;
+			for k in 0..self.pipes_edge[*edge as usize].len() {
+				let flow = self.pipes_edge[*edge as usize][k];
+				let source = if flow > 0.0 {
+					Some(self.edge_cell(*edge, k))
+				} else if periodic {
+					Some(self.edge_cell(edge.opposite(), k))
+				} else {
+					None
+				};
+				if let Some(index) = source {
+					self.pipes_edge[*edge as usize][k] *= factors[index];
+				}
+			}
 		}
 	}
 
 	fn compute_flows(&self, i: usize, j: usize) -> (f32, f32) {
+		let (dim_x, dim_y) = (self.width, self.height);
 		let mut flow_in = 0.0;
 		let mut flow_out = 0.0;
 		let mut split_flow = |flow: f32| {
@@ -258,296 +577,602 @@ impl Water {
 				flow_in -= flow;
 			}
 		};
-		if i < DIM - 1 {
-			split_flow(-self.pipes_x[i + j * (DIM - 1)]);
+		if i < dim_x - 1 {
+			split_flow(-self.pipes_x[i + j * (dim_x - 1)]);
 		}
 		if i > 0 {
-			split_flow(self.pipes_x[i - 1 + j * (DIM - 1)]);
+			split_flow(self.pipes_x[i - 1 + j * (dim_x - 1)]);
 		}
-		if j < DIM - 1 {
-			split_flow(-self.pipes_y[i + j * DIM]);
+		if j < dim_y - 1 {
+			split_flow(-self.pipes_y[i + j * dim_x]);
 		}
 		if j > 0 {
-			split_flow(self.pipes_y[i + (j - 1) * DIM]);
+			split_flow(self.pipes_y[i + (j - 1) * dim_x]);
+		}
+		for edge in Edge::ALL.iter() {
+			if let Some(k) = self.edge_position(*edge, i, j) {
+				let (pipe, sign) = self.edge_pipe(*edge);
+				split_flow(sign * self.pipes_edge[pipe][k]);
+			}
 		}
 		return (flow_in, flow_out);
 	}
 
-	fn update_depths(&mut self, delta_time: f32) {
-		self.avg_depth = 0.0;
-		for i in 0..DIM {
-			for j in 0..DIM {
-				let (flow_in, flow_out) = self.compute_flows(i, j);
-				let flow_sum = flow_in - flow_out;
-
-				let delta_depth = delta_time * flow_sum / (GRID_STEP * GRID_STEP);
-				self.depths[i + j * DIM] += delta_depth;
-				self.avg_depth += self.depths[i + j * DIM];
-			}
-		}
-		self.avg_depth /= (DIM * DIM) as f32;
-	}
-
-	fn update_mesh(&self, store: &EntityStore) {
-		if let Some(ent_terrain) = store.get(self.terrain_id) {
-			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<HeightMap>() {
-				if let Some(ent_mesh) = store.get_mut(self.mesh_id) {
-					if let Some(mesh) = ent_mesh.as_any().downcast_ref::<Mesh>() {
-						mesh.update_vertices(|data| {
-							for i in 0..(data.len() / 6) as usize {
-								let x = data[i * 6] as usize;
-								let y = data[i * 6 + 2] as usize;
-								if self.depths[x + y * DIM] > ZERO_DEPTH {
-									data[i * 6 + 1] = self.depths[x + y * DIM]
-										+ terrain.height_points()[x + y * DIM];
-								} else {
-									let check_neighbor = |n_index: usize, sum: &mut f32| {
-										if self.depths[n_index] > ZERO_DEPTH
-											&& terrain.height_points()[x + y * DIM]
-												> terrain.height_points()[n_index]
-										{
-											*sum += terrain.height_points()[n_index]
-												+ self.depths[n_index];
-											return 1.0;
-										}
-										return 0.0;
-									};
-									let mut n_count = 0.0;
-									let mut n_sum = 0.0;
-									if x > 0 {
-										n_count += check_neighbor(x - 1 + y * DIM, &mut n_sum);
-									}
-
-									if x < DIM - 1 {
-										n_count += check_neighbor(x + 1 + y * DIM, &mut n_sum);
-									}
-
-									if y > 0 {
-										n_count += check_neighbor(x + (y - 1) * DIM, &mut n_sum);
-										if x > 0 {
-											n_count +=
-												check_neighbor(x - 1 + (y - 1) * DIM, &mut n_sum);
-										}
-										if x < DIM - 1 {
-											n_count +=
-												check_neighbor(x + 1 + (y - 1) * DIM, &mut n_sum);
-										}
-									}
-
-									if y < DIM - 1 {
-										n_count += check_neighbor(x + (y + 1) * DIM, &mut n_sum);
-										if x > 0 {
-											n_count +=
-												check_neighbor(x - 1 + (y + 1) * DIM, &mut n_sum);
-										}
-										if x < DIM - 1 {
-											n_count +=
-												check_neighbor(x + 1 + (y + 1) * DIM, &mut n_sum);
-										}
-									}
-
-									if n_count == 0.0 {
-										data[i * 6 + 1] =
-											terrain.height_points()[x + y * DIM] - 1.0;
-									} else {
-										data[i * 6 + 1] = n_sum / n_count;
-									}
-								}
-								let prev_x = if x > 0 && self.depths[x - 1 + y * DIM] > ZERO_DEPTH {
-									terrain.height_points()[x - 1 + y * DIM]
-										+ self.depths[x - 1 + y * DIM]
-								} else {
-									terrain.height_points()[x + y * DIM] + self.depths[x + y * DIM]
-								};
-								let next_x = if x < DIM - 1
-									&& self.depths[x + 1 + y * DIM] > ZERO_DEPTH
-								{
-									terrain.height_points()[x + 1 + y * DIM]
-										+ self.depths[x + 1 + y * DIM]
-								} else {
-									terrain.height_points()[x + y * DIM] + self.depths[x + y * DIM]
-								};
-								let prev_y = if y > 0 && self.depths[x + (y - 1) * DIM] > ZERO_DEPTH
-								{
-									terrain.height_points()[x + (y - 1) * DIM]
-										+ self.depths[x + (y - 1) * DIM]
-								} else {
-									terrain.height_points()[x + y * DIM] + self.depths[x + y * DIM]
-								};
-								let next_y = if y < DIM - 1
-									&& self.depths[x + (y + 1) * DIM] > ZERO_DEPTH
-								{
-									terrain.height_points()[x + (y + 1) * DIM]
-										+ self.depths[x + (y + 1) * DIM]
-								} else {
-									terrain.height_points()[x + y * DIM] + self.depths[x + y * DIM]
-								};
-								let normal = Mesh::normal(prev_x, next_x, prev_y, next_y);
-								data[i * 6 + 3] = normal.x;
-								data[i * 6 + 4] = normal.z;
-								data[i * 6 + 5] = normal.y;
-							}
-						});
-					}
-				}
+	/// Pipe carrying the flow through `edge`, and the sign that makes its
+	/// flow positive when leaving the grid through that edge.
+	fn edge_pipe(&self, edge: Edge) -> (usize, f32) {
+		match (self.boundaries.get(edge), edge) {
+			(Boundary::Periodic, Edge::West) | (Boundary::Periodic, Edge::South) => {
+				(edge.opposite() as usize, -1.0)
 			}
+			_ => (edge as usize, 1.0),
 		}
 	}
 
-	fn update_border_mesh(&self, store: &EntityStore) {
-		if let Some(ent_terrain) = store.get(self.terrain_id) {
-			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<HeightMap>() {
-				let mut bord_points = Vec::new();
-				for x in 0..(DIM - 1) {
-					bord_points.push(Vec3::new(x as f32, 0.0, 0.0));
-					bord_points.push(Vec3::new(
-						x as f32,
-						0.0,
-						self.depths[x] + terrain.height_points()[x],
-					));
-				}
-				for y in 0..(DIM - 1) {
-					bord_points.push(Vec3::new((DIM - 1) as f32, y as f32, 0.0));
-					bord_points.push(Vec3::new(
-						(DIM - 1) as f32,
-						y as f32,
-						self.depths[DIM - 1 + y * DIM] + terrain.height_points()[DIM - 1 + y * DIM],
-					));
+	/// Call `visit` with the source cell, the destination cell and the flow of
+	/// every pipe carrying water out of a cell. The destination is `None` when
+	/// the water leaves the grid through an edge.
+	pub(crate) fn visit_outflows(&self, mut visit: impl FnMut(usize, Option<usize>, f32)) {
+		let dim_x = self.width;
+		for (index, flow) in self.pipes_x.iter().enumerate() {
+			let cell = index % (dim_x - 1) + index / (dim_x - 1) * dim_x;
+			if *flow > 0.0 {
+				visit(cell + 1, Some(cell), *flow);
+			} else if *flow < 0.0 {
+				visit(cell, Some(cell + 1), -*flow);
+			}
+		}
+		for (cell, flow) in self.pipes_y.iter().enumerate() {
+			if *flow > 0.0 {
+				visit(cell + dim_x, Some(cell), *flow);
+			} else if *flow < 0.0 {
+				visit(cell, Some(cell + dim_x), -*flow);
+			}
+		}
+		for edge in Edge::ALL.iter() {
+			let periodic = self.boundaries.get(*edge) == Boundary::Periodic;
+			for (k, flow) in self.pipes_edge[*edge as usize].iter().enumerate() {
+				let (cell, opposite) =
+					(self.edge_cell(*edge, k), self.edge_cell(edge.opposite(), k));
+				if *flow > 0.0 {
+					visit(cell, if periodic { Some(opposite) } else { None }, *flow);
+				} else if *flow < 0.0 && periodic {
+					visit(opposite, Some(cell), -*flow);
 				}
+			}
+		}
+	}
 
-				for x in (0..(DIM)).rev() {
-					bord_points.push(Vec3::new(x as f32, (DIM - 1) as f32, 0.0));
-					bord_points.push(Vec3::new(
-						x as f32,
-						(DIM - 1) as f32,
-						self.depths[x + (DIM - 1) * DIM]
-							+ terrain.height_points()[x + (DIM - 1) * DIM],
-					));
-				}
-				for y in (0..(DIM)).rev() {
-					bord_points.push(Vec3::new(0.0, y as f32, 0.0));
-					bord_points.push(Vec3::new(
-						0.0,
-						y as f32,
-						self.depths[0 + y * DIM] + terrain.height_points()[0 + y * DIM],
-					));
+	/// Count the water exchanged with open and reservoir edges in the ledger.
+	fn record_edge_flows(&mut self, delta_time: f32) {
+		for edge in Edge::ALL.iter() {
+			if let Boundary::Open | Boundary::Reservoir(_) = self.boundaries.get(*edge) {
+				for k in 0..self.pipes_edge[*edge as usize].len() {
+					let volume = (self.pipes_edge[*edge as usize][k] * delta_time) as f64;
+					if volume > 0.0 {
+						self.ledger.record_outflow(volume);
+					} else {
+						self.ledger.record_inflow(-volume);
+					}
 				}
+			}
+		}
+	}
+
+	fn update_depths(&mut self, delta_time: f32) {
+		let (dim_x, dim_y) = (self.width, self.height);
+		let mut depths = std::mem::take(&mut self.depths);
+		let this = &*self;
+		let area = self.params.cell_size * self.params.cell_size;
+		let row_sums = rows_mut(&mut depths, dim_x)
+			.map(|(j, row)| {
+				for (i, depth) in row.iter_mut().enumerate() {
+					let (flow_in, flow_out) = this.compute_flows(i, j);
+					let flow_sum = flow_in - flow_out;
 
-				if let Some(ent_mesh) = store.get_mut(self.border_id) {
-					if let Some(mesh) = ent_mesh.as_any().downcast_ref::<Mesh>() {
-						mesh.update_vertices(|data| {
-							let bord_vert = Mesh::wall_gen_vertices(&bord_points);
-							for (i, val) in bord_vert.into_iter().enumerate() {
-								data[i] = val;
-							}
-						});
+					*depth += delta_time * flow_sum / area;
+					// The limited flows can leave a drained cell a rounding
+					// error below zero.
+					if *depth < 0.0 {
+						*depth = 0.0;
 					}
 				}
+				row.iter().sum::<f32>()
+			})
+			.collect::<Vec<f32>>();
+		self.depths = depths;
+		self.avg_depth = row_sums.iter().sum::<f32>() / (dim_x * dim_y) as f32;
+	}
+
+	/// Add water along the `y = 0` side of the map.
+	pub fn wave(&mut self, strength: u8) {
+		if self.avg_depth < self.params.max_height {
+			let inc = 0.5 + strength as f32 * 0.5;
+			for i in 0..self.width {
+				self.depths[i] += inc;
 			}
+			self.ledger
+				.record_inflow(self.cell_volume(inc) * self.width as f64);
 		}
 	}
 
-	fn handle_inputs(&mut self, inputs: &Inputs, store: &EntityStore) {
-		if self.input_str > 0
-			&& (inputs.is_just_pressed(KeyCode::Minus) || inputs.is_just_pressed(KeyCode::M))
-		{
-			self.input_str = self.input_str - 1;
-		}
-		if self.input_str < 2
-			&& (inputs.is_just_pressed(KeyCode::Plus)
-				|| inputs.is_just_pressed(KeyCode::Equals)
-				|| inputs.is_just_pressed(KeyCode::P))
-		{
-			self.input_str = self.input_str + 1;
+	/// Add water on every cell of the sea.
+	pub fn tide(&mut self, terrain: &HeightMap, strength: u8) {
+		if self.avg_depth < self.params.max_height {
+			let inc = 0.1 + 0.1 * strength as f32;
+			for index in self.sea.cells(terrain) {
+				self.depths[index] += inc;
+				self.ledger.record_inflow(self.cell_volume(inc));
+			}
 		}
+	}
 
-		if self.avg_depth < Water::MAX_HEIGHT && inputs.is_pressed(KeyCode::W) {
-			for i in 0..DIM {
-				self.depths[i] += 0.5 + self.input_str as f32 * 0.5;
-			}
+	/// Remove water from every cell of the sea.
+	pub fn drain(&mut self, terrain: &HeightMap, strength: u8) {
+		for index in self.sea.cells(terrain) {
+			let (i, j) = (index % self.width, index / self.width);
+			self.remove_depth(i, j, 0.1 + 0.1 * strength as f32);
 		}
+	}
 
-		if let Some(ent_terrain) = store.get(self.terrain_id) {
-			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<HeightMap>() {
-				if self.avg_depth < Water::MAX_HEIGHT && inputs.is_pressed(KeyCode::T) {
-					for i in 0..DIM {
-						for j in 0..DIM {
-							if terrain.height_points()[i + j * DIM] <= ZERO_DEPTH
-								&& terrain.height_points()[i + j * DIM] >= -ZERO_DEPTH
-							{
-								self.depths[i + j * DIM] += 0.1 + 0.1 * self.input_str as f32;
-							}
-						}
-					}
-				}
+	/// Water surface height of the cell at `(x, y)`.
+	pub fn get_height(&self, terrain: &HeightMap, x: usize, y: usize) -> f32 {
+		let index = x + y * self.width;
+		self.depths[index] + terrain.height_points()[index]
+	}
 
-				if inputs.is_pressed(KeyCode::D) {
-					for i in 0..DIM {
-						for j in 0..DIM {
-							if terrain.height_points()[i + j * DIM] <= ZERO_DEPTH
-								&& terrain.height_points()[i + j * DIM] >= -ZERO_DEPTH
-							{
-								self.depths[i + j * DIM] -= 0.1 + 0.1 * self.input_str as f32;
-								if self.depths[i + j * DIM] <= ZERO_DEPTH {
-									self.depths[i + j * DIM] = 0.0;
-									if i < DIM - 1 && self.pipes_x[i + j * (DIM - 1)] < 0.0 {
-										self.pipes_x[i + j * (DIM - 1)] = 0.0;
-									}
-									if i > 0 && self.pipes_x[i - 1 + j * (DIM - 1)] > 0.0 {
-										self.pipes_x[i - 1 + j * (DIM - 1)] = 0.0;
-									}
-									if j < DIM - 1 && self.pipes_y[i + j * DIM] < 0.0 {
-										self.pipes_y[i + j * DIM] = 0.0;
-									}
-									if j > 0 && self.pipes_y[i + (j - 1) * DIM] > 0.0 {
-										self.pipes_y[i + (j - 1) * DIM] = 0.0;
-									}
-								}
-							}
-						}
+	/// Inject `inc` units of water depth in the cell at `(x, y)`.
+	pub fn add_depth(&mut self, x: usize, y: usize, inc: f32) {
+		self.depths[x + y * self.width] += inc;
+		self.ledger.record_inflow(self.cell_volume(inc));
+	}
+
+	/// Take up to `dec` units of water depth from the cell at `(x, y)` and
+	/// return the amount actually removed. A cell left dry stops flowing out.
+	pub fn remove_depth(&mut self, x: usize, y: usize, dec: f32) -> f32 {
+		let (dim_x, dim_y) = (self.width, self.height);
+		let (i, j) = (x, y);
+		let before = self.depths[i + j * dim_x];
+		self.depths[i + j * dim_x] -= dec;
+		if self.depths[i + j * dim_x] <= self.params.zero_depth {
+			self.depths[i + j * dim_x] = 0.0;
+			if i < dim_x - 1 && self.pipes_x[i + j * (dim_x - 1)] < 0.0 {
+				self.pipes_x[i + j * (dim_x - 1)] = 0.0;
+			}
+			if i > 0 && self.pipes_x[i - 1 + j * (dim_x - 1)] > 0.0 {
+				self.pipes_x[i - 1 + j * (dim_x - 1)] = 0.0;
+			}
+			if j < dim_y - 1 && self.pipes_y[i + j * dim_x] < 0.0 {
+				self.pipes_y[i + j * dim_x] = 0.0;
+			}
+			if j > 0 && self.pipes_y[i + (j - 1) * dim_x] > 0.0 {
+				self.pipes_y[i + (j - 1) * dim_x] = 0.0;
+			}
+			for edge in Edge::ALL.iter() {
+				if let Some(k) = self.edge_position(*edge, i, j) {
+					let (pipe, sign) = self.edge_pipe(*edge);
+					if sign * self.pipes_edge[pipe][k] > 0.0 {
+						self.pipes_edge[pipe][k] = 0.0;
 					}
 				}
 			}
 		}
+		let removed = before - self.depths[i + j * dim_x];
+		self.ledger.record_outflow(self.cell_volume(removed));
+		removed
 	}
 
-	pub fn get_height(&self, store: &EntityStore, x: usize, y: usize) -> Option<f32> {
-		if let Some(ent_terrain) = store.get(self.terrain_id) {
-			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<HeightMap>() {
-				return Some(self.depths[x + y * DIM] + terrain.height_points()[x + y * DIM]);
-			}
+	pub fn dims(&self) -> (usize, usize) {
+		(self.width, self.height)
+	}
+
+	/// Water depth of the cell at `(x, y)`.
+	pub fn depth(&self, x: usize, y: usize) -> f32 {
+		self.depths[x + y * self.width]
+	}
+
+	/// Mean speed of the water in the cell at `(x, y)`, from the flow through
+	/// the pipes on each of its sides, or zero if the cell is dry.
+	///
+	/// Like the pipes, the flow through a side is carried by the deeper of
+	/// the two cells it links, so a thin film next to a lake does not divide
+	/// the flow of the lake by its own vanishing depth. Cells at or under
+	/// [`WaterParams::zero_depth`] count as dry.
+	pub fn velocity(&self, x: usize, y: usize) -> Vec2 {
+		let (dim_x, dim_y) = (self.width, self.height);
+		let depth = self.depths[x + y * dim_x];
+		if depth <= self.params.zero_depth {
+			return Vec2::ZERO;
 		}
-		return None;
+		let speed = |flow: f32, index_next: usize| {
+			flow / (self.params.cell_size * f32::max(depth, self.depths[index_next]))
+		};
+		let leaving = |edge: Edge, k: usize| {
+			let (pipe, sign) = self.edge_pipe(edge);
+			sign * self.pipes_edge[pipe][k] / (self.params.cell_size * depth)
+		};
+		let left = if x > 0 {
+			-speed(self.pipes_x[x - 1 + y * (dim_x - 1)], x - 1 + y * dim_x)
+		} else {
+			-leaving(Edge::West, y)
+		};
+		let right = if x < dim_x - 1 {
+			-speed(self.pipes_x[x + y * (dim_x - 1)], x + 1 + y * dim_x)
+		} else {
+			leaving(Edge::East, y)
+		};
+		let bottom = if y > 0 {
+			-speed(self.pipes_y[x + (y - 1) * dim_x], x + (y - 1) * dim_x)
+		} else {
+			-leaving(Edge::South, x)
+		};
+		let top = if y < dim_y - 1 {
+			-speed(self.pipes_y[x + y * dim_x], x + (y + 1) * dim_x)
+		} else {
+			leaving(Edge::North, x)
+		};
+		Vec2::new(left + right, bottom + top) / 2.0
 	}
 
-	pub fn add_depth(&mut self, x: usize, y: usize, inc: f32) {
-		self.depths[x + y * DIM] += inc;
+	/// Velocity of the water at `point`, in cells, interpolated between the
+	/// velocities of the four nearest cells. Points off the grid take the
+	/// velocity of the nearest edge.
+	pub fn velocity_at(&self, point: Vec2) -> Vec2 {
+		let max = Vec2::new((self.width - 1) as f32, (self.height - 1) as f32);
+		let point = point.clamp(Vec2::ZERO, max);
+		let (x, y) = (point.x as usize, point.y as usize);
+		let (x_next, y_next) = (
+			usize::min(x + 1, self.width - 1),
+			usize::min(y + 1, self.height - 1),
+		);
+		let (tx, ty) = (point.x - x as f32, point.y - y as f32);
+		let bottom = self.velocity(x, y).lerp(self.velocity(x_next, y), tx);
+		let top = self
+			.velocity(x, y_next)
+			.lerp(self.velocity(x_next, y_next), tx);
+		bottom.lerp(top, ty)
+	}
+
+	/// Velocity of every cell, stored row by row, see [`Water::velocity`].
+	pub fn velocities(&self) -> Vec<Vec2> {
+		(0..self.width * self.height)
+			.map(|index| self.velocity(index % self.width, index / self.width))
+			.collect()
+	}
+
+	/// Froude number of the water in the cell at `(x, y)`, its speed over the
+	/// speed of a wave at its depth: the flow is supercritical above 1. Zero
+	/// if the cell is dry.
+	pub fn froude(&self, x: usize, y: usize) -> f32 {
+		let depth = self.depths[x + y * self.width];
+		if depth <= self.params.zero_depth {
+			return 0.0;
+		}
+		self.velocity(x, y).length() / (self.params.gravity * depth).sqrt()
+	}
+
+	/// Water depth of every cell, stored row by row.
+	pub fn depths(&self) -> &[f32] {
+		&self.depths
+	}
+
+	/// Flow through the pipes between each cell and its `x + 1` neighbour,
+	/// `(width - 1) * height` values stored row by row. A positive flow goes
+	/// towards decreasing `x`.
+	pub fn flows_x(&self) -> &[f32] {
+		&self.pipes_x
+	}
+
+	/// Flow through the pipes between each cell and its `y + 1` neighbour,
+	/// `width * (height - 1)` values stored row by row. A positive flow goes
+	/// towards decreasing `y`.
+	pub fn flows_y(&self) -> &[f32] {
+		&self.pipes_y
+	}
+
+	/// Flow through the pipes of `edge`, one value per cell along it. A
+	/// positive flow leaves the grid, or goes to the opposite edge when
+	/// periodic.
+	pub fn flows_edge(&self, edge: Edge) -> &[f32] {
+		&self.pipes_edge[edge as usize]
 	}
 
 	pub fn get_avg_depth(&self) -> f32 {
 		self.avg_depth
 	}
 
-	pub fn input_str(&self) -> u8 {
-		self.input_str
+	/// Account of the volume added, removed and numerically lost since the
+	/// grid was created or loaded.
+	pub fn ledger(&self) -> &VolumeLedger {
+		&self.ledger
+	}
+
+	/// Total water volume on the grid.
+	pub fn volume(&self) -> f64 {
+		self.depths
+			.iter()
+			.map(|depth| self.cell_volume(*depth))
+			.sum()
+	}
+
+	fn cell_volume(&self, depth: f32) -> f64 {
+		depth as f64 * (self.params.cell_size * self.params.cell_size) as f64
 	}
 }
 
-impl Entity for Water {
-	fn as_any(&self) -> &dyn Any {
-		self
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::algo::Rate;
+
+	/// Every other cell one unit higher, under a sheet too thin to cover the
+	/// bumps, so half the cells dry out in the first step.
+	fn bumps(side: usize) -> (HeightMap, Water) {
+		let heights = (0..side * side)
+			.map(|index| ((index % side + index / side) % 2) as f32)
+			.collect();
+		let terrain = HeightMap::from_heights((side, side), heights).unwrap();
+		let mut water = Water::new((side, side));
+		for y in 0..side {
+			for x in 0..side {
+				water.add_depth(x, y, 0.05);
+			}
+		}
+		(terrain, water)
+	}
+
+	/// Lowest depth every cell would reach with the current flows, before
+	/// `update_depths` clamps it to zero.
+	fn lowest_depth(water: &Water, delta_time: f32) -> f32 {
+		let area = water.params.cell_size * water.params.cell_size;
+		let mut lowest = f32::INFINITY;
+		for j in 0..water.height {
+			for i in 0..water.width {
+				let (flow_in, flow_out) = water.compute_flows(i, j);
+				let depth = water.depths[i + j * water.width];
+				lowest = lowest.min(depth + delta_time * (flow_in - flow_out) / area);
+			}
+		}
+		lowest
+	}
+
+	#[test]
+	fn limited_flows_keep_depths_positive() {
+		for boundary in [Boundary::Closed, Boundary::Open, Boundary::Periodic].iter() {
+			let (terrain, mut water) = bumps(16);
+			let mut boundaries = Boundaries::default();
+			for edge in Edge::ALL.iter() {
+				boundaries.set(*edge, *boundary);
+			}
+			water.set_boundaries(boundaries).unwrap();
+			let mut solver: Box<dyn WaterSolver> = Box::new(PipeSolver);
+			let mut limited = 0;
+			for _ in 0..10 {
+				let step = solver.stable_step(&water, &terrain);
+				solver.update_flows(&mut water, &terrain, step);
+				limited += water.limit_flows(step);
+				assert!(lowest_depth(&water, step) > -1e-6, "{:?} edges", boundary);
+				water.update_depths(step);
+			}
+			assert!(limited > 0);
+		}
+	}
+
+	#[test]
+	fn edges_let_water_in_and_out() {
+		let terrain = HeightMap::from_heights((8, 4), vec![0.0; 32]).unwrap();
+		let pond = |west: Boundary, east: Boundary| {
+			let mut water = Water::new((8, 4));
+			for y in 0..4 {
+				for x in 0..8 {
+					water.add_depth(x, y, 1.0);
+				}
+			}
+			let mut boundaries = Boundaries::default();
+			boundaries.set(Edge::West, west);
+			boundaries.set(Edge::East, east);
+			water.set_boundaries(boundaries).unwrap();
+			for _ in 0..20 {
+				water.update(&terrain, 0.1);
+			}
+			water
+		};
+		let flows = |water: &Water, edge: Edge| water.flows_edge(edge).iter().sum::<f32>();
+
+		// Water only leaves through an open edge.
+		let water = pond(Boundary::Closed, Boundary::Open);
+		assert!(flows(&water, Edge::East) > 0.0 && water.volume() < 32.0);
+		assert_eq!(flows(&water, Edge::West), 0.0);
+		assert!(water.ledger().outflow > 0.0);
+
+		// A higher reservoir fills the grid, a lower one drains it.
+		let water = pond(Boundary::Reservoir(1.5), Boundary::Closed);
+		assert!(flows(&water, Edge::West) < 0.0 && water.volume() > 32.0);
+		let water = pond(Boundary::Reservoir(0.5), Boundary::Closed);
+		assert!(flows(&water, Edge::West) > 0.0 && water.volume() < 32.0);
+
+		// A reservoir at the level of the grid leaves it still.
+		let water = pond(Boundary::Reservoir(1.0), Boundary::Reservoir(1.0));
+		assert!((water.volume() - 32.0).abs() < 1e-4);
+		assert_eq!(water.ledger().inflow, 32.0);
 	}
 
-	fn as_any_mut(&mut self) -> &mut dyn Any {
-		self
+	#[test]
+	fn manning_friction_slows_the_flow_down() {
+		// Water running down a slope towards an open edge.
+		let heights = (0..16 * 4)
+			.map(|index| (15 - index % 16) as f32 * 0.1)
+			.collect();
+		let terrain = HeightMap::from_heights((16, 4), heights).unwrap();
+		let run = |solver: fn() -> Box<dyn WaterSolver>, manning: f32, roughness: bool| {
+			let mut water = Water::new((16, 4));
+			water.set_solver(solver());
+			let mut boundaries = Boundaries::default();
+			boundaries.set(Edge::East, Boundary::Open);
+			water.set_boundaries(boundaries).unwrap();
+			if roughness {
+				water.set_roughness(Some(vec![manning; 64])).unwrap();
+			} else {
+				let mut params = *water.params();
+				params.set("manning", manning).unwrap();
+				water.set_params(params).unwrap();
+			}
+			for step in 0..30 {
+				water.add_depth(0, step % 4, 1.0);
+				water.update(&terrain, 0.1);
+			}
+			water
+		};
+		let solvers: [fn() -> Box<dyn WaterSolver>; 2] = [
+			|| Box::new(PipeSolver),
+			|| Box::new(ShallowWater::default()),
+		];
+		for solver in solvers.iter() {
+			let name = solver().name();
+			let speeds: Vec<f32> = [0.0, 0.03, 0.1]
+				.iter()
+				.map(|manning| {
+					let water = run(*solver, *manning, false);
+					// Friction slows the flow, but never turns it uphill.
+					assert!(water.flows_x().iter().all(|flow| *flow <= 1e-6), "{}", name);
+					water.velocity(8, 1).x
+				})
+				.collect();
+			assert!(speeds[0] > speeds[1] && speeds[1] > speeds[2], "{}", name);
+			assert!(speeds[2] > 0.0, "{}", name);
+			// Cells of the same roughness flow like the coefficient of the
+			// parameters.
+			assert_eq!(
+				run(*solver, 0.1, true).flows_x(),
+				run(*solver, 0.1, false).flows_x(),
+				"{}",
+				name
+			);
+		}
+		let mut water = Water::new((16, 4));
+		assert!(water.set_roughness(Some(vec![0.1; 63])).is_err());
+		assert!(water.set_roughness(Some(vec![2.0; 64])).is_err());
 	}
 
-	fn update(&mut self, delta: f32, inputs: &Inputs, store: &EntityStore) {
-		self.update_pipes_flow(delta, store);
-		self.limit_flows(delta);
-		self.update_depths(delta);
-		self.handle_inputs(inputs, store);
-		self.update_mesh(store);
-		self.update_border_mesh(store);
+	#[test]
+	fn velocity_and_froude_follow_the_flow() {
+		let mut water = Water::new((3, 3));
+		for y in 0..3 {
+			for x in 0..3 {
+				water.add_depth(x, y, 2.0);
+			}
+		}
+		// A flow of 4 towards increasing x through every pipe, over cells 2
+		// deep: 2 units per second.
+		water.pipes_x = vec![-4.0; 6];
+		assert_eq!(water.velocity(1, 1), Vec2::new(2.0, 0.0));
+		let froude = 2.0 / (water.params.gravity * 2.0).sqrt();
+		assert!((water.froude(1, 1) - froude).abs() < 1e-6);
+		// The closed west edge carries nothing.
+		assert_eq!(water.velocity(0, 1), Vec2::new(1.0, 0.0));
+		assert_eq!(water.velocity_at(Vec2::new(0.5, 1.0)), Vec2::new(1.5, 0.0));
+
+		// A side carries the flow over the deeper of its cells.
+		water.remove_depth(2, 1, 1.5);
+		water.pipes_x = vec![-4.0; 6];
+		assert_eq!(water.velocity(1, 1), Vec2::new(2.0, 0.0));
+		assert_eq!(water.velocity(2, 1), Vec2::new(1.0, 0.0));
+
+		// Fast shallow water is supercritical, dry cells stand still.
+		water.pipes_y = vec![-20.0; 6];
+		assert!(water.froude(1, 1) > 1.0);
+		water.remove_depth(1, 1, 2.0);
+		assert_eq!(
+			(water.velocity(1, 1), water.froude(1, 1)),
+			(Vec2::ZERO, 0.0)
+		);
+	}
+
+	#[test]
+	fn substeps_follow_the_stable_step() {
+		let terrain = HeightMap::from_heights((8, 8), vec![0.0; 64]).unwrap();
+		let mut water = Water::new((8, 8));
+		let report = water.update(&terrain, 0.5);
+		assert_eq!(
+			(report.substeps, report.stable_dt, report.clamped),
+			(1, 0.5, false)
+		);
+		for y in 0..8 {
+			for x in 0..8 {
+				water.add_depth(x, y, 1.0);
+			}
+		}
+		// Still water keeps the gravity wave speed as its only limit.
+		let params = water.params().clone();
+		let stable_dt = COURANT * params.cell_size
+			/ (params.gravity * 1.0f32.min(params.max_upwind_depth)).sqrt();
+		let report = water.update(&terrain, 0.5);
+		assert!((report.stable_dt - stable_dt).abs() < 1e-6);
+		assert_eq!(report.substeps, (0.5 / stable_dt).ceil() as u32);
+		assert!(!report.clamped);
+		let report = water.update(&terrain, 100.0);
+		assert_eq!((report.substeps, report.clamped), (MAX_SUBSTEPS, true));
+	}
+
+	#[test]
+	fn ledger_accounts_for_sources_and_edges() {
+		let (terrain, mut water) = bumps(16);
+		let mut boundaries = Boundaries::default();
+		boundaries.set(Edge::West, Boundary::Reservoir(1.5));
+		boundaries.set(Edge::East, Boundary::Open);
+		water.set_boundaries(boundaries).unwrap();
+		water
+			.set_sources(vec![
+				Source::new("well", SourceKind::Spring, (4, 8), Rate::Constant(2.0)),
+				Source::new("sink", SourceKind::Drain, (10, 8), Rate::Constant(1.0)),
+			])
+			.unwrap();
+		let before = *water.ledger();
+		for _ in 0..50 {
+			water.update(&terrain, 0.1);
+		}
+		let ledger = water.ledger();
+		// The reservoir fills the grid and the open edge and the drain empty it.
+		assert!(ledger.inflow - before.inflow > water.sources()[0].total());
+		assert!(ledger.outflow - before.outflow > water.sources()[1].total());
+		assert!(water.sources()[1].total() > 0.0);
+		let error = (ledger.expected() - water.volume()).abs();
+		assert!(error < 1e-4 * water.volume(), "{}", ledger);
+	}
+
+	#[cfg(feature = "parallel")]
+	#[test]
+	fn parallel_rows_match_serial_rows() {
+		let run = |solver: fn() -> Box<dyn WaterSolver>| {
+			let (terrain, mut water) = bumps(24);
+			water.set_solver(solver());
+			let mut boundaries = Boundaries::default();
+			boundaries.set(Edge::East, Boundary::Open);
+			water.set_boundaries(boundaries).unwrap();
+			for step in 0..40 {
+				water.wave(1 + step % 3);
+				water.update(&terrain, 0.1);
+			}
+			let bits = |values: &[f32]| values.iter().map(|value| value.to_bits()).collect();
+			let state: [Vec<u32>; 3] = [
+				bits(water.depths()),
+				bits(water.flows_x()),
+				bits(water.flows_y()),
+			];
+			state
+		};
+		let pool = rayon::ThreadPoolBuilder::new()
+			.num_threads(4)
+			.build()
+			.unwrap();
+		let solvers: [fn() -> Box<dyn WaterSolver>; 2] = [
+			|| Box::new(PipeSolver),
+			|| Box::new(ShallowWater::default()),
+		];
+		for solver in solvers.iter() {
+			let parallel = pool.install(|| run(*solver));
+			let serial = crate::algo::rows::serial(|| run(*solver));
+			assert!(parallel == serial, "{} solver", solver().name());
+		}
 	}
 }
@@ -0,0 +1,161 @@
+use super::{Water, COURANT};
+use crate::algo::rows::{rows, rows_mut};
+use crate::algo::{Boundary, Edge, HeightMap, WaterSolver};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// The virtual pipe model: a pipe between each pair of neighbour cells, its
+/// flow pushed by the difference of water surface height on either side.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PipeSolver;
+
+impl WaterSolver for PipeSolver {
+	fn name(&self) -> &'static str {
+		"pipes"
+	}
+
+	fn stable_step(&self, water: &Water, terrain: &HeightMap) -> f32 {
+		water.stable_step(terrain)
+	}
+
+	fn update_flows(&mut self, water: &mut Water, terrain: &HeightMap, delta_time: f32) {
+		water.update_pipes_flow(terrain, delta_time);
+	}
+}
+
+impl Water {
+	/// Longest time step respecting the CFL condition, from the gravity wave
+	/// speed and the flow speed through the pipes.
+	fn stable_step(&self, terrain: &HeightMap) -> f32 {
+		let dim_x = self.width;
+		let depths = &self.depths;
+		let max_depth = rows(depths, dim_x)
+			.map(|(_, row)| row.iter().fold(0.0, |max: f32, depth| max.max(*depth)))
+			.collect::<Vec<f32>>()
+			.into_iter()
+			.fold(0.0, f32::max);
+		let params = &self.params;
+		let wave_speed = (params.gravity * f32::min(max_depth, params.max_upwind_depth)).sqrt();
+		let flow_speed = |flow: f32, depth: f32, depth_next: f32| {
+			let upwind_depth = f32::max(depth, depth_next);
+			if upwind_depth > params.zero_depth {
+				flow.abs() / (params.cell_size * upwind_depth)
+			} else {
+				0.0
+			}
+		};
+		let speeds_x = rows(&self.pipes_x, dim_x - 1).map(|(j, row)| {
+			row.iter().enumerate().fold(0.0, |max: f32, (i, flow)| {
+				let speed = flow_speed(*flow, depths[i + j * dim_x], depths[i + 1 + j * dim_x]);
+				max.max(speed)
+			})
+		});
+		let speeds_y = rows(&self.pipes_y, dim_x).map(|(j, row)| {
+			row.iter().enumerate().fold(0.0, |max: f32, (i, flow)| {
+				let speed = flow_speed(*flow, depths[i + j * dim_x], depths[i + (j + 1) * dim_x]);
+				max.max(speed)
+			})
+		});
+		let mut max_speed = speeds_x
+			.collect::<Vec<f32>>()
+			.into_iter()
+			.chain(speeds_y.collect::<Vec<f32>>())
+			.fold(0.0, f32::max);
+		for edge in Edge::ALL.iter() {
+			for (k, flow) in self.pipes_edge[*edge as usize].iter().enumerate() {
+				if let Some((depth_out, _)) = self.outside_cell(terrain, *edge, k) {
+					let depth = self.depths[self.edge_cell(*edge, k)];
+					max_speed = max_speed.max(flow_speed(*flow, depth, depth_out));
+				}
+			}
+		}
+		if wave_speed + max_speed > 0.0 {
+			COURANT * params.cell_size / (wave_speed + max_speed)
+		} else {
+			f32::INFINITY
+		}
+	}
+
+	fn update_pipes_flow(&mut self, terrain: &HeightMap, delta_time: f32) {
+		let dim_x = self.width;
+		let params = self.params;
+		let flow_acceleration = |depth: f32, depth_next: f32, terrain: f32, terrain_next: f32| {
+			let height_delta = (depth_next + terrain_next) - (depth + terrain);
+			let upwind_depth = f32::min(f32::max(depth, depth_next), params.max_upwind_depth);
+			upwind_depth * params.gravity * height_delta * delta_time
+		};
+		let flow_decceleration = (1.0 - delta_time * params.damping).clamp(0.0, 1.0);
+		let roughness = &self.roughness;
+		let manning = |index: usize| roughness.as_ref().map_or(params.manning, |n| n[index]);
+		// Manning bottom friction, applied implicitly so it can slow the flow
+		// down to zero but never reverse it.
+		let friction = |flow: f32, depth: f32, depth_next: f32, manning: f32| {
+			if manning == 0.0 {
+				return flow;
+			}
+			let depth = f32::max(f32::max(depth, depth_next), params.zero_depth);
+			let speed = flow.abs() / (params.cell_size * depth);
+			let loss = params.gravity * manning * manning * speed / depth.powf(4.0 / 3.0);
+			flow / (1.0 + delta_time * loss)
+		};
+		let (depths, ground) = (&self.depths, terrain.height_points());
+		let update_pipe = |flow: &mut f32, index: usize, index_next: usize| {
+			if depths[index_next] > params.zero_depth || depths[index] > params.zero_depth {
+				*flow += flow_acceleration(
+					depths[index],
+					depths[index_next],
+					ground[index],
+					ground[index_next],
+				);
+				*flow *= flow_decceleration;
+				*flow = friction(
+					*flow,
+					depths[index],
+					depths[index_next],
+					(manning(index) + manning(index_next)) / 2.0,
+				);
+			} else {
+				*flow = 0.0;
+			}
+		};
+
+		rows_mut(&mut self.pipes_x, dim_x - 1).for_each(|(j, row)| {
+			for (i, flow) in row.iter_mut().enumerate() {
+				update_pipe(flow, i + j * dim_x, i + 1 + j * dim_x);
+			}
+		});
+		rows_mut(&mut self.pipes_y, dim_x).for_each(|(j, row)| {
+			for (i, flow) in row.iter_mut().enumerate() {
+				update_pipe(flow, i + j * dim_x, i + (j + 1) * dim_x);
+			}
+		});
+		for edge in Edge::ALL.iter() {
+			for k in 0..self.pipes_edge[*edge as usize].len() {
+				let index = self.edge_cell(*edge, k);
+				let (depth, ground) = (self.depths[index], terrain.height_points()[index]);
+				let outside = self.outside_cell(terrain, *edge, k);
+				let open = self.boundaries.get(*edge) == Boundary::Open;
+				let flow = &mut self.pipes_edge[*edge as usize][k];
+				match outside {
+					Some((depth_out, ground_out))
+						if depth > params.zero_depth || depth_out > params.zero_depth =>
+					{
+						*flow += flow_acceleration(depth_out, depth, ground_out, ground);
+						*flow *= flow_decceleration;
+						*flow = friction(*flow, depth, depth_out, manning(index));
+						if open {
+							// Water falls off the edge at most at the critical flow
+							// of the cell, there is nothing past it to push back.
+							let upwind_depth = f32::min(depth, params.max_upwind_depth);
+							let critical =
+								params.cell_size * depth * (params.gravity * upwind_depth).sqrt();
+							*flow = f32::min(f32::max(*flow, 0.0), critical);
+						}
+					}
+					_ => *flow = 0.0,
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,441 @@
+use super::{Water, COURANT};
+use crate::algo::rows::{rows, rows_mut};
+use crate::algo::{Boundary, Edge, HeightMap, Map, WaterSolver};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Water on one side of a cell side, with its speed across the side and
+/// along it.
+#[derive(Debug, Clone, Copy, Default)]
+struct SideState {
+	depth: f32,
+	ground: f32,
+	normal: f32,
+	tangent: f32,
+}
+
+/// Flux through a cell side per unit of its length, from the first cell to
+/// the second one. The normal momentum flux differs on each side because of
+/// the slope of the terrain.
+#[derive(Debug, Clone, Copy, Default)]
+struct Flux {
+	mass: f32,
+	normal_first: f32,
+	normal_second: f32,
+	tangent: f32,
+}
+
+/// A finite volume solver of the shallow water equations, with the HLL flux
+/// and the hydrostatic reconstruction of Audusse et al. so that still water
+/// over an uneven terrain stays still.
+///
+/// It tracks the momentum of the water in every cell, which the pipe model
+/// does not have, and sets the flows of the [`Water`] grid to the mass flux
+/// through every cell side.
+#[derive(Debug, Clone, Default)]
+pub struct ShallowWater {
+	momentum_x: Map,
+	momentum_y: Map,
+}
+
+impl WaterSolver for ShallowWater {
+	fn name(&self) -> &'static str {
+		"shallow"
+	}
+
+	fn stable_step(&self, water: &Water, _terrain: &HeightMap) -> f32 {
+		let params = &water.params;
+		let initial;
+		let (momentum_x, momentum_y) = if self.momentum_x.len() == water.depths.len() {
+			(&self.momentum_x, &self.momentum_y)
+		} else {
+			initial = ShallowWater::momentum(water);
+			(&initial.0, &initial.1)
+		};
+		let max_speed = rows(&water.depths, water.width)
+			.map(|(j, row)| {
+				row.iter().enumerate().fold(0.0, |max: f32, (i, depth)| {
+					let index = i + j * water.width;
+					let wave = (params.gravity * depth).sqrt();
+					let speed_x = velocity(*depth, momentum_x[index], params.zero_depth);
+					let speed_y = velocity(*depth, momentum_y[index], params.zero_depth);
+					// Both directions add up on a cell, so their sum is kept
+					// under the Courant limit.
+					max.max(speed_x.abs() + speed_y.abs() + 2.0 * wave)
+				})
+			})
+			.collect::<Vec<f32>>()
+			.into_iter()
+			.fold(0.0, f32::max);
+		if max_speed > 0.0 {
+			COURANT * params.cell_size / max_speed
+		} else {
+			f32::INFINITY
+		}
+	}
+
+	fn update_flows(&mut self, water: &mut Water, terrain: &HeightMap, delta_time: f32) {
+		if self.momentum_x.len() != water.depths.len() {
+			let (momentum_x, momentum_y) = ShallowWater::momentum(water);
+			self.momentum_x = momentum_x;
+			self.momentum_y = momentum_y;
+		}
+		let (dim_x, dim_y) = (water.width, water.height);
+		let params = water.params;
+		let zero_depth = params.zero_depth;
+		let ground = terrain.height_points();
+		let depths = &water.depths;
+		let (momentum_x, momentum_y) = (&self.momentum_x, &self.momentum_y);
+		let speeds = |index: usize| {
+			(
+				velocity(depths[index], momentum_x[index], zero_depth),
+				velocity(depths[index], momentum_y[index], zero_depth),
+			)
+		};
+		let state = |index: usize, normal: f32, tangent: f32| SideState {
+			depth: depths[index],
+			ground: ground[index],
+			normal,
+			tangent,
+		};
+
+		let mut fluxes_x = vec![Flux::default(); (dim_x - 1) * dim_y];
+		rows_mut(&mut fluxes_x, dim_x - 1).for_each(|(j, row)| {
+			for (i, flux) in row.iter_mut().enumerate() {
+				let (first, second) = (i + j * dim_x, i + 1 + j * dim_x);
+				let ((u_first, v_first), (u_second, v_second)) = (speeds(first), speeds(second));
+				*flux = hll(
+					state(first, u_first, v_first),
+					state(second, u_second, v_second),
+					params.gravity,
+				);
+			}
+		});
+		let mut fluxes_y = vec![Flux::default(); dim_x * (dim_y - 1)];
+		rows_mut(&mut fluxes_y, dim_x).for_each(|(j, row)| {
+			for (i, flux) in row.iter_mut().enumerate() {
+				let (first, second) = (i + j * dim_x, i + (j + 1) * dim_x);
+				let ((u_first, v_first), (u_second, v_second)) = (speeds(first), speeds(second));
+				*flux = hll(
+					state(first, v_first, u_first),
+					state(second, v_second, u_second),
+					params.gravity,
+				);
+			}
+		});
+
+		// Sides on the edges, seen from the cell inside the grid with the
+		// normal pointing out of it. Periodic edges are handled once, from
+		// the east and north cells.
+		let mut fluxes_edge: [Vec<Flux>; 4] = Default::default();
+		for edge in Edge::ALL.iter() {
+			let periodic = water.boundaries.get(*edge) == Boundary::Periodic;
+			if periodic && (*edge == Edge::West || *edge == Edge::South) {
+				fluxes_edge[*edge as usize] =
+					vec![Flux::default(); water.pipes_edge[*edge as usize].len()];
+				continue;
+			}
+			let (sign, along_x) = orientation(*edge);
+			let oriented = |index: usize| {
+				let (u, v) = speeds(index);
+				if along_x {
+					(sign * u, sign * v)
+				} else {
+					(sign * v, sign * u)
+				}
+			};
+			fluxes_edge[*edge as usize] = (0..water.pipes_edge[*edge as usize].len())
+				.map(|k| {
+					let index = water.edge_cell(*edge, k);
+					let (normal, tangent) = oriented(index);
+					let inside = state(index, normal, tangent);
+					let outside = match (
+						water.outside_cell(terrain, *edge, k),
+						water.boundaries.get(*edge),
+					) {
+						// A wall reflects the water back.
+						(None, _) => SideState {
+							normal: -normal,
+							..inside
+						},
+						(Some((depth, ground)), Boundary::Periodic) => {
+							let opposite = water.edge_cell(edge.opposite(), k);
+							let (normal, tangent) = oriented(opposite);
+							SideState {
+								depth,
+								ground,
+								normal,
+								tangent,
+							}
+						}
+						(Some((depth, ground)), _) => SideState {
+							depth,
+							ground,
+							normal: 0.0,
+							tangent: 0.0,
+						},
+					};
+					let mut flux = hll(inside, outside, params.gravity);
+					if water.boundaries.get(*edge) == Boundary::Open && flux.mass < 0.0 {
+						// Nothing past an open edge can flow back in.
+						flux.mass = 0.0;
+						flux.tangent = 0.0;
+					}
+					flux
+				})
+				.collect();
+		}
+
+		// Momentum change of every cell from the fluxes through its sides,
+		// then the bottom friction.
+		let ratio = delta_time / params.cell_size;
+		let damping = (1.0 - delta_time * params.damping).clamp(0.0, 1.0);
+		let roughness = &water.roughness;
+		let update_momentum = |j: usize, row_x: &mut [f32], row_y: &mut [f32]| {
+			for i in 0..dim_x {
+				let index = i + j * dim_x;
+				let (mut change_x, mut change_y) = (0.0, 0.0);
+				if i > 0 {
+					let flux = fluxes_x[i - 1 + j * (dim_x - 1)];
+					change_x += flux.normal_second;
+					change_y += flux.tangent;
+				}
+				if i < dim_x - 1 {
+					let flux = fluxes_x[i + j * (dim_x - 1)];
+					change_x -= flux.normal_first;
+					change_y -= flux.tangent;
+				}
+				if j > 0 {
+					let flux = fluxes_y[i + (j - 1) * dim_x];
+					change_y += flux.normal_second;
+					change_x += flux.tangent;
+				}
+				if j < dim_y - 1 {
+					let flux = fluxes_y[i + j * dim_x];
+					change_y -= flux.normal_first;
+					change_x -= flux.tangent;
+				}
+				for edge in Edge::ALL.iter() {
+					let k = match water.edge_position(*edge, i, j) {
+						Some(k) => k,
+						None => continue,
+					};
+					let (sign, along_x) = orientation(*edge);
+					let periodic = water.boundaries.get(*edge) == Boundary::Periodic;
+					let (normal, tangent) = if periodic && sign < 0.0 {
+						// Entering from the opposite edge, as the second cell of
+						// its flux.
+						let flux = fluxes_edge[edge.opposite() as usize][k];
+						(flux.normal_second, flux.tangent)
+					} else {
+						let flux = fluxes_edge[*edge as usize][k];
+						(flux.normal_first, flux.tangent)
+					};
+					if along_x {
+						change_x -= sign * normal;
+						change_y -= sign * tangent;
+					} else {
+						change_y -= sign * normal;
+						change_x -= sign * tangent;
+					}
+				}
+				let depth = depths[index];
+				if depth <= zero_depth {
+					row_x[i] = 0.0;
+					row_y[i] = 0.0;
+					continue;
+				}
+				let mut new_x = (row_x[i] + ratio * change_x) * damping;
+				let mut new_y = (row_y[i] + ratio * change_y) * damping;
+				let manning = roughness.as_ref().map_or(params.manning, |n| n[index]);
+				if manning > 0.0 {
+					let speed = (new_x * new_x + new_y * new_y).sqrt() / depth;
+					let loss = params.gravity * manning * manning * speed / depth.powf(4.0 / 3.0);
+					new_x /= 1.0 + delta_time * loss;
+					new_y /= 1.0 + delta_time * loss;
+				}
+				row_x[i] = new_x;
+				row_y[i] = new_y;
+			}
+		};
+		rows_mut(&mut self.momentum_x, dim_x)
+			.zip(rows_mut(&mut self.momentum_y, dim_x))
+			.for_each(|((j, row_x), (_, row_y))| update_momentum(j, row_x, row_y));
+
+		// The pipes carry the mass flux, positive towards decreasing x or y
+		// inside the grid and leaving the grid on the edges.
+		let side = params.cell_size;
+		rows_mut(&mut water.pipes_x, dim_x - 1).for_each(|(j, row)| {
+			for (i, flow) in row.iter_mut().enumerate() {
+				*flow = -fluxes_x[i + j * (dim_x - 1)].mass * side;
+			}
+		});
+		rows_mut(&mut water.pipes_y, dim_x).for_each(|(j, row)| {
+			for (i, flow) in row.iter_mut().enumerate() {
+				*flow = -fluxes_y[i + j * dim_x].mass * side;
+			}
+		});
+		for (pipes, fluxes) in water.pipes_edge.iter_mut().zip(fluxes_edge.iter()) {
+			for (flow, flux) in pipes.iter_mut().zip(fluxes.iter()) {
+				*flow = flux.mass * side;
+			}
+		}
+	}
+}
+
+impl ShallowWater {
+	/// Momentum of every cell matching the flows of `water`, for a solver
+	/// that has not run on it yet, for instance after loading a state.
+	fn momentum(water: &Water) -> (Map, Map) {
+		let (dim_x, dim_y) = water.dims();
+		let mut momentum_x = vec![0.0; dim_x * dim_y];
+		let mut momentum_y = vec![0.0; dim_x * dim_y];
+		for index in 0..dim_x * dim_y {
+			let (x, y) = (index % dim_x, index / dim_x);
+			let speed = water.velocity(x, y);
+			momentum_x[index] = speed.x * water.depths[index];
+			momentum_y[index] = speed.y * water.depths[index];
+		}
+		(momentum_x, momentum_y)
+	}
+}
+
+/// Sign of the normal pointing out of the grid through `edge`, and whether it
+/// goes along `x`.
+fn orientation(edge: Edge) -> (f32, bool) {
+	match edge {
+		Edge::West => (-1.0, true),
+		Edge::East => (1.0, true),
+		Edge::South => (-1.0, false),
+		Edge::North => (1.0, false),
+	}
+}
+
+/// Speed of water `depth` deep carrying `momentum`, smoothed down to zero
+/// under `zero_depth` so nearly dry cells do not get absurd speeds.
+fn velocity(depth: f32, momentum: f32, zero_depth: f32) -> f32 {
+	let depth_4 = depth.powi(4);
+	std::f32::consts::SQRT_2 * depth * momentum / (depth_4 + depth_4.max(zero_depth.powi(4))).sqrt()
+}
+
+/// HLL flux between two sides, after lowering both water surfaces to the
+/// highest ground of the two so that water can not climb a step.
+fn hll(first: SideState, second: SideState, gravity: f32) -> Flux {
+	let ground = first.ground.max(second.ground);
+	let depth_first = (first.depth + first.ground - ground).max(0.0);
+	let depth_second = (second.depth + second.ground - ground).max(0.0);
+	// The part of the hydrostatic pressure the reconstruction removed pushes
+	// each cell against the step.
+	let step_first = gravity / 2.0 * (first.depth * first.depth - depth_first * depth_first);
+	let step_second = gravity / 2.0 * (second.depth * second.depth - depth_second * depth_second);
+	if depth_first <= 0.0 && depth_second <= 0.0 {
+		return Flux {
+			mass: 0.0,
+			normal_first: step_first,
+			normal_second: step_second,
+			tangent: 0.0,
+		};
+	}
+	let flux = |depth: f32, speed: f32| {
+		(
+			depth * speed,
+			depth * speed * speed + gravity / 2.0 * depth * depth,
+		)
+	};
+	let (wave_first, wave_second) = (
+		(gravity * depth_first).sqrt(),
+		(gravity * depth_second).sqrt(),
+	);
+	let slowest = f32::min(first.normal - wave_first, second.normal - wave_second);
+	let fastest = f32::max(first.normal + wave_first, second.normal + wave_second);
+	let (mass_first, normal_first) = flux(depth_first, first.normal);
+	let (mass_second, normal_second) = flux(depth_second, second.normal);
+	let (mass, normal) = if slowest >= 0.0 {
+		(mass_first, normal_first)
+	} else if fastest <= 0.0 {
+		(mass_second, normal_second)
+	} else {
+		let spread = fastest - slowest;
+		(
+			(fastest * mass_first - slowest * mass_second
+				+ slowest * fastest * (depth_second - depth_first))
+				/ spread,
+			(fastest * normal_first - slowest * normal_second
+				+ slowest * fastest * (depth_second * second.normal - depth_first * first.normal))
+				/ spread,
+		)
+	};
+	let tangent = if mass > 0.0 {
+		mass * first.tangent
+	} else {
+		mass * second.tangent
+	};
+	Flux {
+		mass,
+		normal_first: normal + step_first,
+		normal_second: normal + step_second,
+		tangent,
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn shallow_water(terrain: &HeightMap, surface: impl Fn(usize) -> f32) -> Water {
+		let (dim_x, dim_y) = terrain.dims();
+		let mut water = Water::new((dim_x, dim_y));
+		water.set_solver(Box::new(ShallowWater::default()));
+		for (index, ground) in terrain.height_points().iter().enumerate() {
+			let depth = surface(index % dim_x) - ground;
+			if depth > 0.0 {
+				water.add_depth(index % dim_x, index / dim_x, depth);
+			}
+		}
+		water
+	}
+
+	#[test]
+	fn lake_at_rest_stays_still() {
+		// Uneven ground under a flat surface, with an island above it.
+		let heights = (0..16 * 12)
+			.map(|index| ((index * 7919) % 13) as f32 * 0.15)
+			.enumerate()
+			.map(|(index, height)| if index == 5 + 6 * 16 { 4.0 } else { height })
+			.collect();
+		let terrain = HeightMap::from_heights((16, 12), heights).unwrap();
+		let mut water = shallow_water(&terrain, |_| 3.0);
+		let depths = water.depths().to_vec();
+		for _ in 0..50 {
+			water.update(&terrain, 0.1);
+		}
+		for (index, depth) in water.depths().iter().enumerate() {
+			assert!((depth - depths[index]).abs() < 1e-4, "cell {}", index);
+		}
+		let fastest = water
+			.velocities()
+			.iter()
+			.fold(0.0, |max: f32, speed| max.max(speed.length()));
+		assert!(fastest < 1e-4, "{}", fastest);
+	}
+
+	#[test]
+	fn dam_break_moves_downstream_and_keeps_the_mass() {
+		let terrain = HeightMap::from_heights((40, 3), vec![0.0; 120]).unwrap();
+		let mut water = shallow_water(&terrain, |x| if x < 20 { 2.0 } else { 0.5 });
+		let volume = water.volume();
+		for _ in 0..10 {
+			water.update(&terrain, 0.1);
+		}
+		assert!((water.volume() - volume).abs() < 1e-5 * volume);
+		// The water runs towards increasing x, with negative flows.
+		for j in 0..3 {
+			assert!(water.flows_x()[19 + j * 39] < 0.0);
+			assert!(water.depth(15, j) < 2.0 && water.depth(25, j) > 0.5);
+			assert!(water.velocity(20, j).x > 0.0);
+		}
+		// The front moves no faster than the waves, and has not hit the wall.
+		assert!((water.depth(39, 1) - 0.5).abs() < 1e-3);
+	}
+}
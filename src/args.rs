@@ -0,0 +1,284 @@
+use crate::headless::Hold;
+use mod1::{Boundaries, HeightMap, MapError, Soil, Water, WaterParams};
+
+const USAGE: &str = "usage: mod1 <mapfile> [--size <width>x<height>] [--load-state <file>]
+                      [--save-state <file>] [--seed <number>] [--boundary <edge>=<boundary>]...
+                      [--erosion] [--soil <file>] [--evaporation <rate>] [--infiltration <depth>]
+                      [--sea-level <height>] [--sea-from <x>x<y>]
+                      [--params <file>] [--param <name>=<value>]... [--roughness <file>]
+                      [--solver <pipes|shallow>] [--velocity-output <file>]
+                      [--height-scale <height>] [--height-offset <height>]
+       mod1 <mapfile> --headless [--size <width>x<height>] [--steps <count>] [--dt <seconds>]
+                      [--hold <wave|tide|rain|drain>[:<steps>]]... [--strength <0-2>]
+                      [--output <file>] [--load-state <file>] [--save-state <file>]
+                      [--seed <number>] [--boundary <edge>=<boundary>]... [--erosion]
+                      [--soil <file>] [--evaporation <rate>] [--infiltration <depth>]
+                      [--sea-level <height>] [--sea-from <x>x<y>]
+                      [--params <file>] [--param <name>=<value>]... [--roughness <file>]
+                      [--solver <pipes|shallow>] [--velocity-output <file>]
+                      [--height-scale <height>] [--height-offset <height>]
+       mod1 check <mapfile>
+mapfiles: .mod1 point files, .png and .pgm grayscale heightmaps,
+          .asc ESRI ASCII grids or .xyz point clouds
+edges: west, east, south, north or all
+boundaries: closed, open, periodic or reservoir:<level>
+params: gravity, zero_depth, cell_size, max_upwind_depth, damping, manning or max_height";
+
+const DEFAULT_STEPS: usize = 1000;
+const DEFAULT_DT: f32 = 0.08333;
+const DEFAULT_OUTPUT: &str = "mod1.out";
+pub const DEFAULT_STATE: &str = "mod1.state";
+pub const DEFAULT_VELOCITY: &str = "mod1.velocity";
+const DEFAULT_HEIGHT_SCALE: f32 = 20.0;
+
+#[derive(Debug)]
+pub struct Args {
+	pub map_file: String,
+	pub dims: Option<(usize, usize)>,
+	pub headless: bool,
+	/// Only validate the map file, see [`HeightMap::check`](mod1::HeightMap::check).
+	pub check: bool,
+	pub steps: usize,
+	pub dt: f32,
+	pub holds: Vec<Hold>,
+	pub strength: u8,
+	pub output: String,
+	pub load_state: Option<String>,
+	pub save_state: Option<String>,
+	pub seed: u64,
+	/// `(edge, boundary)` pairs applied over the boundaries of the map file.
+	pub boundaries: Vec<(String, String)>,
+	pub erosion: bool,
+	pub soil_file: Option<String>,
+	pub evaporation: f32,
+	pub infiltration: f32,
+	pub sea_level: Option<f32>,
+	/// Cell the sea spreads from, over the one declared by the map file.
+	pub sea_from: Option<(usize, usize)>,
+	params_file: Option<String>,
+	/// `<name>=<value>` values of the `--param` options.
+	params: Vec<String>,
+	pub roughness_file: Option<String>,
+	pub solver: String,
+	/// File the velocity field is written to.
+	pub velocity_output: Option<String>,
+	/// Height of the white pixels of an image map over the black ones, or of
+	/// the highest point of an elevation model over the lowest.
+	pub height_scale: Option<f32>,
+	/// Height of the black pixels of an image map, or of the lowest point of
+	/// an elevation model.
+	pub height_offset: f32,
+}
+
+impl Args {
+	pub fn parse() -> Result<Self, String> {
+		let mut map_file = None;
+		let mut parsed = Args {
+			map_file: String::new(),
+			dims: None,
+			headless: false,
+			check: false,
+			steps: DEFAULT_STEPS,
+			dt: DEFAULT_DT,
+			holds: Vec::new(),
+			strength: 0,
+			output: String::from(DEFAULT_OUTPUT),
+			load_state: None,
+			save_state: None,
+			seed: rand::random(),
+			boundaries: Vec::new(),
+			erosion: false,
+			soil_file: None,
+			evaporation: 0.0,
+			infiltration: 0.0,
+			sea_level: None,
+			sea_from: None,
+			params_file: None,
+			params: Vec::new(),
+			roughness_file: None,
+			solver: String::from("pipes"),
+			velocity_output: None,
+			height_scale: None,
+			height_offset: 0.0,
+		};
+		let mut args = std::env::args().skip(1);
+		while let Some(arg) = args.next() {
+			let mut value = || {
+				args.next()
+					.ok_or(format!("Missing value for '{}'\n{}", arg, USAGE))
+			};
+			match arg.as_str() {
+				"--size" => parsed.dims = Some(Args::parse_dims(&value()?)?),
+				"--headless" => parsed.headless = true,
+				"--steps" => parsed.steps = Args::parse_number(&arg, &value()?)?,
+				"--dt" => parsed.dt = Args::parse_number(&arg, &value()?)?,
+				"--hold" => parsed.holds.push(Hold::parse(&value()?)?),
+				"--strength" => parsed.strength = Args::parse_number(&arg, &value()?)?,
+				"--output" => parsed.output = value()?,
+				"--load-state" => parsed.load_state = Some(value()?),
+				"--save-state" => parsed.save_state = Some(value()?),
+				"--seed" => parsed.seed = Args::parse_number(&arg, &value()?)?,
+				"--erosion" => parsed.erosion = true,
+				"--soil" => parsed.soil_file = Some(value()?),
+				"--evaporation" => parsed.evaporation = Args::parse_number(&arg, &value()?)?,
+				"--infiltration" => parsed.infiltration = Args::parse_number(&arg, &value()?)?,
+				"--sea-level" => parsed.sea_level = Some(Args::parse_number(&arg, &value()?)?),
+				"--sea-from" => parsed.sea_from = Some(Args::parse_cell(&value()?)?),
+				"--boundary" => parsed.boundaries.push(Args::parse_boundary(&value()?)?),
+				"--params" => parsed.params_file = Some(value()?),
+				"--param" => parsed.params.push(value()?),
+				"--roughness" => parsed.roughness_file = Some(value()?),
+				"--solver" => {
+					parsed.solver = value()?;
+					mod1::new_solver(&parsed.solver)?;
+				}
+				"--velocity-output" => parsed.velocity_output = Some(value()?),
+				"--height-scale" => {
+					parsed.height_scale = Some(Args::parse_number(&arg, &value()?)?)
+				}
+				"--height-offset" => parsed.height_offset = Args::parse_number(&arg, &value()?)?,
+				opt if opt.starts_with("--") => {
+					return Err(format!("Unknown option '{}'\n{}", opt, USAGE));
+				}
+				"check" if map_file.is_none() && !parsed.check => parsed.check = true,
+				_ if map_file.is_none() => map_file = Some(arg),
+				_ => return Err(format!("Too many arguments\n{}", USAGE)),
+			}
+		}
+		parsed.map_file =
+			map_file.ok_or(format!("Not enough argument, need 1 file path.\n{}", USAGE))?;
+		parsed.params(WaterParams::default())?;
+		if parsed.strength > Water::MAX_STRENGTH {
+			return Err(format!(
+				"Invalid strength '{}' (not between 0 and {})",
+				parsed.strength,
+				Water::MAX_STRENGTH
+			));
+		}
+		if parsed.dt.is_nan() || parsed.dt <= 0.0 {
+			return Err(format!(
+				"Invalid time step '{}' (must be positive)",
+				parsed.dt
+			));
+		}
+		if parsed.evaporation.is_nan() || parsed.evaporation < 0.0 {
+			return Err(format!(
+				"Invalid evaporation rate '{}' (must not be negative)",
+				parsed.evaporation
+			));
+		}
+		if !(0.0..=Soil::MAX_CAPACITY).contains(&parsed.infiltration) {
+			return Err(format!(
+				"Invalid infiltration capacity '{}' (not between 0 and {})",
+				parsed.infiltration,
+				Soil::MAX_CAPACITY
+			));
+		}
+		if let Some(level) = parsed.sea_level {
+			let limit = MapError::MAX_HEIGHT;
+			if level.is_nan() || level.abs() >= limit {
+				return Err(format!(
+					"Invalid sea level '{}' (not between -{} and {})",
+					level, limit, limit
+				));
+			}
+		}
+		Ok(parsed)
+	}
+
+	/// Load the terrain of the map file, a point file, a heightmap image or
+	/// an elevation model depending on its extension.
+	pub fn terrain(&self) -> Result<HeightMap, String> {
+		let (path, dims, offset) = (self.map_file.as_str(), self.dims, self.height_offset);
+		let terrain = match self.map_format().as_str() {
+			"png" | "pgm" => HeightMap::from_image(
+				path,
+				dims,
+				self.height_scale.unwrap_or(DEFAULT_HEIGHT_SCALE),
+				offset,
+			),
+			"asc" => HeightMap::from_esri_ascii(path, dims, self.height_scale, offset),
+			"xyz" => HeightMap::from_xyz(path, dims, self.height_scale, offset),
+			_ => return HeightMap::new(path, dims).map_err(|err| err.diagnostic()),
+		};
+		terrain.map_err(|err| format!("{}: {}", path, err))
+	}
+
+	/// Lowercase extension of the map file.
+	pub fn map_format(&self) -> String {
+		let extension = self.map_file.rsplit('.').next().unwrap_or_default();
+		extension.to_ascii_lowercase()
+	}
+
+	/// Water parameters of `base` overridden by the parameter file, then by
+	/// the `--param` options.
+	pub fn params(&self, base: WaterParams) -> Result<WaterParams, String> {
+		let mut params = base;
+		if let Some(path) = &self.params_file {
+			params
+				.read_file(path)
+				.map_err(|err| format!("Invalid parameter file '{}': {}", path, err))?;
+		}
+		for param in self.params.iter() {
+			let (name, value) = param.split_once('=').ok_or(format!(
+				"Invalid parameter '{}' (expected <name>=<value>)",
+				param
+			))?;
+			params.parse(name, value)?;
+		}
+		Ok(params)
+	}
+
+	/// Soil described by the options, if any of them was given.
+	pub fn soil(&self, dims: (usize, usize)) -> Result<Option<Soil>, String> {
+		match &self.soil_file {
+			Some(path) => Soil::from_file(path, dims, self.evaporation).map(Some),
+			None if self.evaporation > 0.0 || self.infiltration > 0.0 => {
+				Ok(Some(Soil::new(dims, self.infiltration, self.evaporation)))
+			}
+			None => Ok(None),
+		}
+	}
+
+	fn parse_number<T>(option: &str, value: &str) -> Result<T, String>
+	where
+		T: std::str::FromStr,
+		T::Err: std::fmt::Display,
+	{
+		value
+			.parse()
+			.map_err(|err| format!("Invalid value '{}' for '{}' ({})", value, option, err))
+	}
+
+	fn parse_boundary(value: &str) -> Result<(String, String), String> {
+		let (edge, boundary) = value.split_once('=').ok_or(format!(
+			"Invalid boundary '{}' (expected <edge>=<boundary>)",
+			value
+		))?;
+		Boundaries::default().apply(edge, boundary)?;
+		Ok((String::from(edge), String::from(boundary)))
+	}
+
+	fn parse_dims(value: &str) -> Result<(usize, usize), String> {
+		let parse_side = |side: &str| {
+			side.parse::<usize>()
+				.map_err(|err| format!("Invalid grid size '{}' ({})", value, err))
+		};
+		match value.split_once('x') {
+			Some((width, height)) => Ok((parse_side(width)?, parse_side(height)?)),
+			None => {
+				let side = parse_side(value)?;
+				Ok((side, side))
+			}
+		}
+	}
+
+	fn parse_cell(value: &str) -> Result<(usize, usize), String> {
+		let invalid = || format!("Invalid cell '{}' (expected <x>x<y>)", value);
+		let (x, y) = value.split_once('x').ok_or_else(invalid)?;
+		Ok((
+			x.parse().map_err(|_| invalid())?,
+			y.parse().map_err(|_| invalid())?,
+		))
+	}
+}
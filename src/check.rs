@@ -0,0 +1,22 @@
+use crate::args::Args;
+use mod1::HeightMap;
+
+/// Print every error and warning of the map file, failing if it does not
+/// load. Heightmap images and elevation models are only loaded.
+pub fn run(args: &Args) -> Result<(), String> {
+	if ["png", "pgm", "asc", "xyz"].contains(&args.map_format().as_str()) {
+		let terrain = args.terrain()?;
+		println!("{}x{} heightmap loaded", terrain.dims().0, terrain.dims().1);
+		if let Some(scale) = terrain.info().scale {
+			println!("{}", scale);
+		}
+		return Ok(());
+	}
+	let report = HeightMap::check(&args.map_file);
+	if report.is_ok() {
+		println!("{}", report.diagnostics());
+		Ok(())
+	} else {
+		Err(report.diagnostics())
+	}
+}
@@ -13,7 +13,7 @@ pub use error::EngineError;
 pub use input::{ClickButton, Inputs, KeyCode};
 pub use mesh::Mesh;
 pub use mesh_points::MeshPoints;
-pub use render::{Renderable, Renderer, RendererBuilder};
+pub use render::{grid_placement, Renderable, Renderer, RendererBuilder};
 pub use shader_program::ShaderProgram;
 
 use glutin::{
@@ -22,14 +22,23 @@ use glutin::{
 };
 use std::time;
 
+/// Duration of an update tick, in simulation time units.
+pub const UPDATE_STEP: f32 = 0.08333;
+const MAX_STEPS_PER_FRAME: u32 = 5;
+
+/// Build the event loop handler. Every update tick lasts exactly
+/// `UPDATE_STEP` and the elapsed time is accumulated to decide how many ticks
+/// to run, so the simulation does not depend on the frame timings.
 pub fn core_loop(
 	mut renderer: Renderer,
 	mut entities: EntityStore,
 	_event_proxy: EventLoopProxy<()>,
 ) -> Box<dyn FnMut(Event<'_, ()>, &EventLoopWindowTarget<()>, &mut ControlFlow)> {
 	let mut inputs = Inputs::new();
+	inputs.store_resize(renderer.viewport_res());
 	let mut last_draw = time::Instant::now();
 	let mut last_update = time::Instant::now();
+	let mut accumulator = 0.0;
 	Box::new(move |event, _target, flow: &mut ControlFlow| {
 		*flow = ControlFlow::Poll;
 
@@ -37,7 +46,10 @@ pub fn core_loop(
 			Event::LoopDestroyed => return,
 			Event::WindowEvent { event, .. } => match event {
 				WindowEvent::CloseRequested => *flow = ControlFlow::Exit,
-				WindowEvent::Resized(size) => renderer.resize(size),
+				WindowEvent::Resized(size) => {
+					renderer.resize(size);
+					inputs.store_resize(renderer.viewport_res());
+				}
 				WindowEvent::KeyboardInput { input, .. } => {
 					if let Some(VirtualKeyCode::Escape) = input.virtual_keycode {
 						*flow = ControlFlow::Exit;
@@ -65,14 +77,18 @@ pub fn core_loop(
 			}
 			Event::UserEvent(_) => {}
 			Event::MainEventsCleared => {
-				let delta = f32::min(
-					8333.0,
-					time::Instant::now().duration_since(last_update).as_micros() as f32,
-				) / 100000.0;
-				if delta >= 0.08333 {
-					entities.update(delta, &inputs);
+				let now = time::Instant::now();
+				accumulator += now.duration_since(last_update).as_micros() as f32 / 100000.0;
+				last_update = now;
+				let mut steps = 0;
+				while accumulator >= UPDATE_STEP && steps < MAX_STEPS_PER_FRAME {
+					entities.update(UPDATE_STEP, &inputs);
 					inputs.update();
-					last_update = time::Instant::now();
+					accumulator -= UPDATE_STEP;
+					steps += 1;
+				}
+				if steps == MAX_STEPS_PER_FRAME {
+					accumulator = 0.0;
 				}
 				if time::Instant::now().duration_since(last_draw).as_micros() >= 8333 {
 					renderer.window().request_redraw();
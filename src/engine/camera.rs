@@ -1,5 +1,5 @@
 use super::{ClickButton, Inputs, KeyCode};
-use glam::{Mat4, Vec3};
+use glam::{Mat4, Vec2, Vec3};
 use std::any::Any;
 
 const NEAR: f32 = 0.1;
@@ -74,6 +74,16 @@ impl Camera {
 		.normalize() * self.dist
 	}
 
+	/// Origin and direction of the ray going from the camera through the
+	/// point of the screen at `ndc`, in normalized device coordinates.
+	#[allow(dead_code)]
+	pub fn ray(&self, ndc: Vec2) -> (Vec3, Vec3) {
+		let inverse = (self.perspective() * self.view()).inverse();
+		let near = inverse.project_point3(Vec3::new(ndc.x, ndc.y, -1.0));
+		let far = inverse.project_point3(Vec3::new(ndc.x, ndc.y, 1.0));
+		(near, (far - near).normalize())
+	}
+
 	fn change_render_mode(&mut self) {
 		match self.mode {
 			PolygonMode::Point => self.mode = PolygonMode::Line,
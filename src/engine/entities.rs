@@ -2,7 +2,7 @@ use super::{render::RenderableStore, Inputs, Renderable};
 use std::any::Any;
 use std::cell::{Ref, RefCell, RefMut};
 use std::collections::hash_set;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::Debug;
 
 pub trait Entity: Debug + Any {
@@ -16,7 +16,7 @@ pub trait Entity: Debug + Any {
 }
 
 pub struct EntityStore {
-	entities: HashMap<u128, RefCell<Box<dyn Entity>>>,
+	entities: BTreeMap<u128, RefCell<Box<dyn Entity>>>,
 	reserved_keys: RefCell<HashSet<u128>>,
 	opaques_renderables: HashSet<u128>,
 	transparent_renderables: HashSet<u128>,
@@ -27,7 +27,7 @@ pub struct EntityStore {
 impl EntityStore {
 	pub fn new() -> Self {
 		EntityStore {
-			entities: HashMap::new(),
+			entities: BTreeMap::new(),
 			reserved_keys: RefCell::new(HashSet::new()),
 			opaques_renderables: HashSet::new(),
 			transparent_renderables: HashSet::new(),
@@ -37,7 +37,7 @@ impl EntityStore {
 	}
 
 	pub fn update(&mut self, delta: f32, inputs: &Inputs) {
-		// run entities logic
+		// run entities logic, in key order so runs are reproducible
 		for (_key, entity) in self.entities.iter() {
 			entity.borrow_mut().update(delta, inputs, self);
 		}
@@ -41,6 +41,7 @@ pub struct Inputs {
 	keys_states: HashMap<KeyCode, KeyState>,
 	mouse_state: MouseState,
 	wheel_delta: f32,
+	window_size: Vec2,
 }
 
 #[allow(dead_code)]
@@ -58,6 +59,7 @@ impl Inputs {
 				right_click: KeyState::new(false),
 			},
 			wheel_delta: 0.0,
+			window_size: Vec2::ONE,
 		}
 	}
 
@@ -142,6 +144,15 @@ impl Inputs {
 	pub fn wheel_delta(&self) -> f32 {
 		self.wheel_delta
 	}
+
+	/// Position of the cursor in normalized device coordinates, from -1 to 1
+	/// with `y` going up.
+	#[allow(dead_code)]
+	pub fn mouse_ndc(&self) -> Vec2 {
+		let pos = self.mouse_state.pos / self.window_size;
+		Vec2::new(pos.x * 2.0 - 1.0, 1.0 - pos.y * 2.0)
+	}
+
 	pub fn update(&mut self) {
 		if self.active {
 			for (_key, state) in self.keys_states.iter_mut() {
@@ -187,6 +198,10 @@ impl Inputs {
 		self.mouse_state.pos = new_pos;
 	}
 
+	pub fn store_resize(&mut self, size: Vec2) {
+		self.window_size = size;
+	}
+
 	pub fn store_mouse_wheel(&mut self, delta: (f32, f32)) {
 		self.active = true;
 		self.wheel_delta += delta.1;
@@ -1,4 +1,4 @@
-use super::{Camera, EngineError, Entity, Renderable, Renderer};
+use super::{render, Camera, EngineError, Entity, Renderable, Renderer};
 use crate::engine_error;
 use gl::types::*;
 use glam::Vec3;
@@ -14,7 +14,8 @@ pub struct Mesh {
 	shader_name: String,
 	noise_texture: Option<u32>,
 	count: i32,
-	offset: f32,
+	offset: Vec3,
+	scale: Vec3,
 	opaque: bool,
 }
 
@@ -22,12 +23,12 @@ impl Mesh {
 	pub fn new(
 		shader_name: &str,
 		vertices_flat: &Vec<f32>,
-		dim: usize,
+		dims: (usize, usize),
 		opaque: bool,
 		static_data: bool,
 		noise: Option<&[[u8; 3]; 1024 * 1024]>,
 	) -> Self {
-		let offset = (dim - 1) as f32 * 0.5;
+		let (offset, scale) = render::grid_placement(dims);
 		let count = vertices_flat.len() as i32;
 
 		let (mut vbo, mut vao) = (0, 0);
@@ -103,19 +104,20 @@ impl Mesh {
 			noise_texture,
 			count,
 			offset,
+			scale,
 			opaque,
 		}
 	}
 
-	pub fn heights_gen_vertices(dim: usize, height_pts: &Vec<f32>) -> Vec<f32> {
+	pub fn heights_gen_vertices(dims: (usize, usize), height_pts: &[f32]) -> Vec<f32> {
 		let mut vertices = Vec::new();
-		for i in 0..dim {
-			for j in 0..dim {
-				if i + 1 < dim && j + 1 < dim {
-					let top_left = Mesh::compute_vertice(i, j, dim, height_pts);
-					let top_right = Mesh::compute_vertice(i + 1, j, dim, height_pts);
-					let bot_left = Mesh::compute_vertice(i, j + 1, dim, height_pts);
-					let bot_right = Mesh::compute_vertice(i + 1, j + 1, dim, height_pts);
+		for i in 0..dims.0 {
+			for j in 0..dims.1 {
+				if i + 1 < dims.0 && j + 1 < dims.1 {
+					let top_left = Mesh::compute_vertice(i, j, dims, height_pts);
+					let top_right = Mesh::compute_vertice(i + 1, j, dims, height_pts);
+					let bot_left = Mesh::compute_vertice(i, j + 1, dims, height_pts);
+					let bot_right = Mesh::compute_vertice(i + 1, j + 1, dims, height_pts);
 
 					// first triangle
 					vertices.extend_from_slice(&top_left);
@@ -142,8 +144,9 @@ impl Mesh {
 		}
 	}
 
-	fn compute_vertice(i: usize, j: usize, dim: usize, height_pts: &Vec<f32>) -> [f32; 6] {
+	fn compute_vertice(i: usize, j: usize, dims: (usize, usize), height_pts: &[f32]) -> [f32; 6] {
 		let mut vertice = [0.0; 6];
+		let dim = dims.0;
 		let (x, y) = (i as f32, j as f32);
 		// coords
 		vertice[0] = x;
@@ -165,7 +168,7 @@ impl Mesh {
 		} else {
 			height_pts[i + j * dim]
 		};
-		let next_y = if j < dim - 1 {
+		let next_y = if j < dims.1 - 1 {
 			height_pts[i + (j + 1) * dim]
 		} else {
 			height_pts[i + j * dim]
@@ -253,11 +256,10 @@ impl Renderable for Mesh {
 			ShaderFail,
 			format!("Shader '{}' is missing", self.shader_name)
 		))?;
-		let pos = glam::Vec3::new(-self.offset, 0.0, -self.offset);
 		let model = glam::Mat4::from_scale_rotation_translation(
-			glam::Vec3::new(1.0, 1.0, 1.0),
+			self.scale,
 			glam::Quat::from_axis_angle(glam::Vec3::Y, 0.0),
-			pos,
+			self.offset,
 		);
 		let view = camera.view();
 		let projection = camera.perspective();
@@ -1,4 +1,4 @@
-use super::{Camera, EngineError, Entity, Renderable, Renderer};
+use super::{render, Camera, EngineError, Entity, Renderable, Renderer};
 use crate::{engine_error, map_engine_error};
 use gl::types::*;
 use glam::Vec3;
@@ -14,7 +14,8 @@ pub struct MeshPoints {
 	vbo: u32,
 	shader_name: String,
 	count: i32,
-	offset: f32,
+	offset: Vec3,
+	scale: Vec3,
 	opaque: bool,
 }
 
@@ -22,11 +23,11 @@ impl MeshPoints {
 	pub fn new(
 		shader_name: &str,
 		vertices_flat: &Vec<f32>,
-		dim: usize,
+		dims: (usize, usize),
 		opaque: bool,
 		static_data: bool,
 	) -> Self {
-		let offset = (dim - 1) as f32 * 0.5;
+		let (offset, scale) = render::grid_placement(dims);
 		let count = vertices_flat.len() as i32;
 
 		let (mut vbo, mut vao) = (0, 0);
@@ -65,6 +66,7 @@ impl MeshPoints {
 			shader_name: String::from(shader_name),
 			count,
 			offset,
+			scale,
 			opaque,
 		}
 	}
@@ -100,11 +102,10 @@ impl Renderable for MeshPoints {
 			ShaderFail,
 			format!("Shader '{}' is missing", self.shader_name)
 		))?;
-		let pos = glam::Vec3::new(-self.offset, 0.0, -self.offset);
 		let model = glam::Mat4::from_scale_rotation_translation(
-			glam::Vec3::new(1.0, 1.0, 1.0),
+			self.scale,
 			glam::Quat::from_axis_angle(glam::Vec3::Y, 0.0),
-			pos,
+			self.offset,
 		);
 		let view = camera.view();
 		let projection = camera.perspective();
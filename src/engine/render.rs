@@ -16,6 +16,20 @@ use std::ptr;
 const DEFAULT_WIDTH: u32 = 800;
 const DEFAULT_HEIGHT: u32 = 600;
 const DEFAULT_TITLE: String = String::new();
+const VIEW_SIZE: f32 = 99.0;
+const SUN_DIMS: (usize, usize) = (100, 100);
+
+/// Translation and scale centering a grid of `dims` cells on the origin, its
+/// longest side stretched to the size the camera is tuned for.
+pub fn grid_placement(dims: (usize, usize)) -> (Vec3, Vec3) {
+	let scale = VIEW_SIZE / (usize::max(dims.0, dims.1) - 1) as f32;
+	let offset = Vec3::new(
+		-((dims.0 - 1) as f32 * 0.5 * scale),
+		0.0,
+		-((dims.1 - 1) as f32 * 0.5 * scale),
+	);
+	(offset, Vec3::new(scale, 1.0, scale))
+}
 
 pub trait RenderableStore {
 	fn opaques_renderables_keys(&self) -> hash_set::Iter<u128>;
@@ -283,11 +297,11 @@ impl Renderer {
 		Vec3::new(75.0, 60.0, 0.0)
 	}
 
-	pub fn light_source(&self, dim: usize) -> MeshPoints {
+	pub fn light_source(&self) -> MeshPoints {
 		MeshPoints::new(
 			"sun",
 			&MeshPoints::points_vertices(&vec![glam::Vec3::new(125.0, 60.0, 50.0)]),
-			dim,
+			SUN_DIMS,
 			true,
 			true,
 		)
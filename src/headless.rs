@@ -0,0 +1,168 @@
+use crate::args::Args;
+use mod1::{Erosion, SimRng, SourceKind, Water};
+
+use rand::SeedableRng;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Action {
+	Wave,
+	Tide,
+	Rain,
+	Drain,
+}
+
+/// An action repeated every step, like a key held down in the window, for the
+/// first `steps` steps or for the whole run.
+#[derive(Debug)]
+pub struct Hold {
+	action: Action,
+	steps: Option<usize>,
+}
+
+impl Hold {
+	pub fn parse(value: &str) -> Result<Self, String> {
+		let (name, steps) = match value.split_once(':') {
+			Some((name, steps)) => {
+				let steps = steps
+					.parse()
+					.map_err(|err| format!("Invalid step count in '{}' ({})", value, err))?;
+				(name, Some(steps))
+			}
+			None => (value, None),
+		};
+		let action = match name {
+			"wave" => Action::Wave,
+			"tide" => Action::Tide,
+			"rain" => Action::Rain,
+			"drain" => Action::Drain,
+			_ => return Err(format!("Unknown action '{}'", name)),
+		};
+		Ok(Hold { action, steps })
+	}
+
+	fn is_active(&self, step: usize) -> bool {
+		self.steps.is_none_or(|steps| step < steps)
+	}
+}
+
+/// Run the simulation without any window and write the final water depths to
+/// the output file, and the final state and velocity field to their files if
+/// they were given.
+pub fn run(args: &Args) -> Result<(), String> {
+	let mut terrain = args.terrain()?;
+	if let Some(scale) = terrain.info().scale {
+		println!("Elevation model rescaled: {}", scale);
+	}
+	let (mut water, mut rain) = crate::initial_state(args, &terrain)?;
+	let mut soil = args.soil(terrain.dims())?;
+	let mut erosion = if args.erosion {
+		Some(Erosion::new(terrain.dims()))
+	} else {
+		None
+	};
+	let mut rng = SimRng::seed_from_u64(args.seed);
+	let mut max_substeps = 0;
+	let mut clamped_steps = 0;
+
+	for step in 0..args.steps {
+		let report = water.update(&terrain, args.dt);
+		max_substeps = u32::max(max_substeps, report.substeps);
+		if report.clamped {
+			clamped_steps += 1;
+		}
+		if let Some(soil) = &mut soil {
+			soil.update(args.dt, &mut water);
+		}
+		if let Some(erosion) = &mut erosion {
+			erosion.update(args.dt, &water, &mut terrain);
+		}
+		for hold in args.holds.iter().filter(|hold| hold.is_active(step)) {
+			match hold.action {
+				Action::Wave => water.wave(args.strength),
+				Action::Tide => water.tide(&terrain, args.strength),
+				Action::Rain => rain.spawn(&water, args.strength, &mut rng),
+				Action::Drain => water.drain(&terrain, args.strength),
+			}
+		}
+		rain.update(args.dt, &terrain, &mut water);
+	}
+
+	write_depths(&args.output, &water).map_err(|err| format!("Failed to write file: {}", err))?;
+	if let Some(path) = &args.save_state {
+		mod1::save_state(path, &water, &rain)?;
+	}
+	if let Some(path) = &args.velocity_output {
+		mod1::save_velocities(path, &water)?;
+	}
+	println!(
+		"{} steps of {}s simulated with seed {}, average depth {:.4}, depths written to '{}'",
+		args.steps,
+		args.dt,
+		args.seed,
+		water.get_avg_depth(),
+		args.output
+	);
+	println!(
+		"up to {} substeps per step, {} steps clamped",
+		max_substeps, clamped_steps
+	);
+	println!("{}", water.ledger());
+	if let Some(path) = &args.velocity_output {
+		let (dim_x, dim_y) = water.dims();
+		let top_speed = water
+			.velocities()
+			.iter()
+			.fold(0.0, |top: f32, velocity| top.max(velocity.length()));
+		let top_froude = (0..dim_x * dim_y).fold(0.0, |top: f32, index| {
+			top.max(water.froude(index % dim_x, index / dim_x))
+		});
+		println!(
+			"top speed {:.4}, top Froude number {:.4}, velocities written to '{}'",
+			top_speed, top_froude, path
+		);
+	}
+	for source in water.sources() {
+		let (x, y) = source.cell();
+		println!(
+			"{} '{}' at {}x{}: {} {:.4}",
+			source.kind().name(),
+			source.name(),
+			x,
+			y,
+			if source.kind() == SourceKind::Drain {
+				"removed"
+			} else {
+				"added"
+			},
+			source.total()
+		);
+	}
+	if let Some(soil) = &soil {
+		println!(
+			"water evaporated {:.4}, soaked into the ground {:.4}",
+			soil.evaporated(),
+			soil.infiltrated()
+		);
+	}
+	if let Some(erosion) = &erosion {
+		println!(
+			"terrain eroded {:.4}, deposited {:.4}",
+			erosion.eroded(),
+			erosion.deposited()
+		);
+	}
+	Ok(())
+}
+
+fn write_depths(path: &str, water: &Water) -> std::io::Result<()> {
+	let mut writer = BufWriter::new(File::create(path)?);
+	let (dim_x, dim_y) = water.dims();
+	writeln!(writer, "{} {}", dim_x, dim_y)?;
+	for row in water.depths().chunks(dim_x).take(dim_y) {
+		let values: Vec<String> = row.iter().map(|depth| format!("{:.4}", depth)).collect();
+		writeln!(writer, "{}", values.join(" "))?;
+	}
+	writer.flush()
+}
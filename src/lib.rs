@@ -0,0 +1,26 @@
+//! Water simulation on a heightmap using the virtual pipe model.
+//!
+//! The solver does not depend on any window or GL context, so it can be
+//! embedded in other tools:
+//!
+//! ```
+//! use mod1::{HeightMap, Water};
+//!
+//! let terrain = HeightMap::from_heights((3, 2), vec![0.0, 1.0, 2.0, 0.0, 1.0, 2.0]).unwrap();
+//! let mut water = Water::new(terrain.dims());
+//! water.add_depth(2, 0, 5.0);
+//! for _ in 0..10 {
+//!     water.update(&terrain, 0.08);
+//! }
+//! assert!(water.depth(0, 0) > 0.0);
+//! ```
+
+mod algo;
+
+pub use algo::{
+	load_roughness, load_state, new_solver, save_state, save_velocities, Boundaries, Boundary,
+	DemScale, Edge, Erosion, FloatingBody, HeightMap, Interpolation, Location, Map, MapError, MapInfo,
+	MapReport, MapWarning, PipeSolver, Rain, Rate, Sea, ShallowWater, SimRng, Soil, Source,
+	SourceKind, StepReport, Tracers, VolumeLedger, Water, WaterParams, WaterSolver, DEFAULT_DIM,
+	FORMAT_VERSION, MAX_DIM, MIN_DIM, SOLVERS, STATE_VERSION, SURFACES, ZERO_DEPTH,
+};
@@ -1,8 +1,17 @@
-mod algo;
+mod args;
+mod check;
 mod engine;
+mod headless;
+mod scene;
 
-use algo::{HeightMap, Rain, Water};
+use args::Args;
 use engine::{Camera, EntityStore, PolygonMode, Renderer};
+use mod1::{Erosion, HeightMap, Rain, SimRng, Water, WaterParams};
+use rand::SeedableRng;
+use scene::{
+    ErosionEntity, FloatingEntity, RainEntity, SnapshotEntity, SoilEntity, TerrainEntity,
+    TracerEntity, WaterEntity,
+};
 
 fn main() {
     match exec_main() {
@@ -16,18 +25,77 @@ fn load_shaders(renderer: &mut Renderer) {
     renderer.load_shader("border", true);
     renderer.load_shader("water", true);
     renderer.load_shader("rain", false);
+    renderer.load_shader("tracer", false);
+    renderer.load_shader("floating", true);
     renderer.load_shader("sun", false);
 }
 
+fn initial_state(args: &Args, terrain: &HeightMap) -> Result<(Water, Rain), String> {
+    let (mut water, rain) = match &args.load_state {
+        Some(path) => {
+            let (water, rain) = mod1::load_state(path)?;
+            if water.dims() != terrain.dims() {
+                return Err(format!(
+                    "State file '{}' is for a {}x{} grid, the map is {}x{}",
+                    path,
+                    water.dims().0,
+                    water.dims().1,
+                    terrain.dims().0,
+                    terrain.dims().1
+                ));
+            }
+            (water, rain)
+        }
+        None => (Water::new(terrain.dims()), Rain::new()),
+    };
+    let mut boundaries = *terrain.boundaries();
+    for (edge, boundary) in args.boundaries.iter() {
+        boundaries.apply(edge, boundary)?;
+    }
+    water.set_boundaries(boundaries)?;
+    let mut params = WaterParams::default();
+    if let Some(cell_size) = terrain.info().cell_size {
+        params.cell_size = cell_size;
+    }
+    water.set_params(args.params(params)?)?;
+    water.set_solver(mod1::new_solver(&args.solver)?);
+    if let Some(path) = &args.roughness_file {
+        water.set_roughness(Some(mod1::load_roughness(path, terrain.dims())?))?;
+    }
+    water.set_sources(terrain.sources().to_vec())?;
+    let mut sea = *terrain.sea();
+    if let Some(level) = args.sea_level {
+        sea.set_level(level);
+    }
+    if args.sea_from.is_some() {
+        sea.set_from(args.sea_from);
+    }
+    water.set_sea(sea)?;
+    Ok((water, rain))
+}
+
 fn exec_main() -> Result<(), Box<dyn std::error::Error>> {
-    let file_arg = std::env::args()
-        .skip(1)
-        .next()
-        .ok_or(String::from("Not enough argument, need 1 file path."))?;
+    let args = Args::parse()?;
+    if args.check {
+        return Ok(check::run(&args)?);
+    }
+    if args.headless {
+        return Ok(headless::run(&args)?);
+    }
+
+    let height_map = args.terrain()?;
+    if let Some(scale) = height_map.info().scale {
+        println!("Elevation model rescaled: {}", scale);
+    }
+    let (water, rain) = initial_state(&args, &height_map)?;
+    let title = match &height_map.info().name {
+        Some(name) => format!("mod1 - {}", name),
+        None => String::from("mod1"),
+    };
 
     let event_loop = glutin::event_loop::EventLoop::new();
     let mut renderer = engine::RendererBuilder::new()
-        .title("mod1")
+        .title(&title)
         .size((1280, 720))
         .resizable(false)
         .build(&event_loop)?;
@@ -38,17 +106,47 @@ fn exec_main() -> Result<(), Box<dyn std::error::Error>> {
     renderer.set_cam(Some(cam_key));
     load_shaders(&mut renderer);
 
-    let light = renderer.light_source(algo::DIM);
+    let light = renderer.light_source();
     entities.insert(Box::new(light));
 
-    let terrain = Box::new(HeightMap::new(&file_arg)?);
-    let terrain_id = entities.insert(terrain);
+    let dims = height_map.dims();
+    let terrain_id = entities.insert(Box::new(TerrainEntity::new(height_map)));
 
-    let water = Water::new(&entities, terrain_id);
+    println!("Using seed {}", args.seed);
+    let mut rng = SimRng::seed_from_u64(args.seed);
+    let water = WaterEntity::new(&entities, terrain_id, water, &mut rng);
     let water_id = entities.insert(Box::new(water));
 
-    let rain = Rain::new(&entities, water_id);
-    entities.insert(Box::new(rain));
+    let tracers = TracerEntity::new(&entities, water_id, cam_key, dims);
+    entities.insert(Box::new(tracers));
+
+    let floating = FloatingEntity::new(&entities, water_id, cam_key, dims);
+    entities.insert(Box::new(floating));
+
+    let rain = RainEntity::new(&entities, water_id, rain, rng, dims);
+    let rain_id = entities.insert(Box::new(rain));
+
+    let state_file = args
+        .save_state
+        .clone()
+        .unwrap_or_else(|| String::from(args::DEFAULT_STATE));
+    let velocity_file = args
+        .velocity_output
+        .clone()
+        .unwrap_or_else(|| String::from(args::DEFAULT_VELOCITY));
+    entities.insert(Box::new(SnapshotEntity::new(
+        water_id,
+        rain_id,
+        state_file,
+        velocity_file,
+    )));
+
+    let erosion = ErosionEntity::new(Erosion::new(dims), args.erosion, terrain_id, water_id);
+    entities.insert(Box::new(erosion));
+
+    if let Some(soil) = args.soil(dims)? {
+        entities.insert(Box::new(SoilEntity::new(soil, water_id)));
+    }
 
     let proxy = event_loop.create_proxy();
     event_loop.run(engine::core_loop(renderer, entities, proxy));
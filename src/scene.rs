@@ -0,0 +1,19 @@
+mod erosion;
+mod floating;
+mod pick;
+mod rain;
+mod snapshot;
+mod soil;
+mod terrain;
+mod tracers;
+mod water;
+
+pub use erosion::ErosionEntity;
+pub use floating::FloatingEntity;
+use pick::pick;
+pub use rain::RainEntity;
+pub use snapshot::SnapshotEntity;
+pub use soil::SoilEntity;
+pub use terrain::TerrainEntity;
+pub use tracers::TracerEntity;
+pub use water::WaterEntity;
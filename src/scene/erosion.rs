@@ -0,0 +1,57 @@
+use super::{TerrainEntity, WaterEntity};
+use crate::engine::{Entity, EntityStore, Inputs, KeyCode};
+use mod1::Erosion;
+
+use std::any::Any;
+
+/// Let the water of the water entity reshape the terrain of the terrain
+/// entity, while enabled. `E` turns it on and off.
+#[derive(Debug)]
+pub struct ErosionEntity {
+	erosion: Erosion,
+	enabled: bool,
+	terrain_id: u128,
+	water_id: u128,
+}
+
+impl ErosionEntity {
+	pub fn new(erosion: Erosion, enabled: bool, terrain_id: u128, water_id: u128) -> Self {
+		ErosionEntity {
+			erosion,
+			enabled,
+			terrain_id,
+			water_id,
+		}
+	}
+}
+
+impl Entity for ErosionEntity {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn update(&mut self, delta: f32, inputs: &Inputs, store: &EntityStore) {
+		if inputs.is_just_pressed(KeyCode::E) {
+			self.enabled = !self.enabled;
+			println!("Erosion {}", if self.enabled { "on" } else { "off" });
+		}
+		if !self.enabled {
+			return;
+		}
+		if let (Some(ent_water), Some(mut ent_terrain)) =
+			(store.get(self.water_id), store.get_mut(self.terrain_id))
+		{
+			if let (Some(water), Some(terrain)) = (
+				ent_water.as_any().downcast_ref::<WaterEntity>(),
+				ent_terrain.as_any_mut().downcast_mut::<TerrainEntity>(),
+			) {
+				self.erosion
+					.update(delta, water.water(), terrain.height_map_mut());
+			}
+		}
+	}
+}
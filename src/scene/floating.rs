@@ -0,0 +1,139 @@
+use super::{pick, TerrainEntity, WaterEntity};
+use crate::engine::{Entity, EntityStore, Inputs, KeyCode, Mesh};
+use mod1::{FloatingBody, HeightMap, Water};
+
+use glam::{Vec2, Vec3};
+use std::any::Any;
+
+const MAX_BODIES: usize = 64;
+/// Faces of a body as indices of its corners, see [`FloatingBody::corners`].
+const FACES: [[usize; 4]; 6] = [
+	[0, 1, 2, 3],
+	[4, 5, 6, 7],
+	[0, 1, 5, 4],
+	[1, 2, 6, 5],
+	[2, 3, 7, 6],
+	[3, 0, 4, 7],
+];
+/// Floats per body: 6 faces of 2 triangles, with a position and a normal
+/// per vertex.
+const BODY_FLOATS: usize = 6 * 2 * 3 * 6;
+
+/// Boxes and logs floating on the water of the water entity, dropped where
+/// the cursor points with `B` and `O`.
+#[derive(Debug)]
+pub struct FloatingEntity {
+	bodies: Vec<FloatingBody>,
+	mesh_id: u128,
+	water_id: u128,
+	cam_id: u128,
+}
+
+impl FloatingEntity {
+	pub fn new(store: &EntityStore, water_id: u128, cam_id: u128, dims: (usize, usize)) -> Self {
+		let body_vert = vec![0.0; MAX_BODIES * BODY_FLOATS];
+		let body_mesh = Box::new(Mesh::new("floating", &body_vert, dims, true, false, None));
+		let mesh_id = store.to_new_queue(body_mesh);
+		FloatingEntity {
+			bodies: Vec::new(),
+			mesh_id,
+			water_id,
+			cam_id,
+		}
+	}
+
+	fn drop_body(
+		&mut self,
+		inputs: &Inputs,
+		store: &EntityStore,
+		terrain: &HeightMap,
+		water: &Water,
+		new_body: fn(Vec2, &HeightMap, &Water) -> Result<FloatingBody, String>,
+	) {
+		if self.bodies.len() >= MAX_BODIES {
+			return;
+		}
+		if let Some(point) = pick(inputs, store, self.cam_id, terrain, water) {
+			match new_body(point, terrain, water) {
+				Ok(body) => self.bodies.push(body),
+				Err(err) => eprintln!("{}", err),
+			}
+		}
+	}
+
+	fn update_mesh(&self, store: &EntityStore, cell_size: f32) {
+		if let Some(ent_mesh) = store.get(self.mesh_id) {
+			if let Some(mesh) = ent_mesh.as_any().downcast_ref::<Mesh>() {
+				mesh.update_vertices(|data| {
+					for (id, body) in self.bodies.iter().enumerate() {
+						let vertices = FloatingEntity::body_vertices(body, cell_size);
+						data[id * BODY_FLOATS..(id + 1) * BODY_FLOATS].copy_from_slice(&vertices);
+					}
+					for value in data[self.bodies.len() * BODY_FLOATS..].iter_mut() {
+						*value = 0.0;
+					}
+				});
+			}
+		}
+	}
+
+	/// Triangles of the faces of `body`, turning counterclockwise when seen
+	/// from outside, with the inward normal the light shader expects.
+	fn body_vertices(body: &FloatingBody, cell_size: f32) -> [f32; BODY_FLOATS] {
+		let corners = body.corners(cell_size);
+		let center = corners.iter().fold(Vec3::ZERO, |sum, corner| sum + *corner) / 8.0;
+		let mut vertices = [0.0; BODY_FLOATS];
+		let mut k = 0;
+		for face in FACES.iter() {
+			let face_center = face.iter().fold(Vec3::ZERO, |sum, id| sum + corners[*id]) / 4.0;
+			let outward = face_center - center;
+			for triangle in [[face[0], face[1], face[2]], [face[0], face[2], face[3]]].iter() {
+				let [a, mut b, mut c] = triangle.map(|id| corners[id]);
+				let mut normal = (b - a).cross(c - a);
+				if normal.dot(outward) < 0.0 {
+					std::mem::swap(&mut b, &mut c);
+					normal = -normal;
+				}
+				let normal = -normal.normalize_or_zero();
+				for point in [a, b, c].iter() {
+					vertices[k..k + 6].copy_from_slice(&[
+						point.x, point.z, point.y, normal.x, normal.z, normal.y,
+					]);
+					k += 6;
+				}
+			}
+		}
+		vertices
+	}
+}
+
+impl Entity for FloatingEntity {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn update(&mut self, delta: f32, inputs: &Inputs, store: &EntityStore) {
+		if let Some(ent_water) = store.get(self.water_id) {
+			if let Some(water_ent) = ent_water.as_any().downcast_ref::<WaterEntity>() {
+				if let Some(ent_terrain) = store.get(water_ent.terrain_id()) {
+					if let Some(terrain) = ent_terrain.as_any().downcast_ref::<TerrainEntity>() {
+						let (terrain, water) = (terrain.height_map(), water_ent.water());
+						if inputs.is_just_pressed(KeyCode::B) {
+							self.drop_body(inputs, store, terrain, water, FloatingBody::crate_box);
+						}
+						if inputs.is_just_pressed(KeyCode::O) {
+							self.drop_body(inputs, store, terrain, water, FloatingBody::log);
+						}
+						self.bodies
+							.retain_mut(|body| body.update(delta, terrain, water));
+						self.update_mesh(store, water.params().cell_size);
+					}
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,37 @@
+use crate::engine::{grid_placement, Camera, EntityStore, Inputs};
+use mod1::{HeightMap, Water};
+
+use glam::Vec2;
+
+/// Point of the water surface or of the terrain under the cursor, in cells,
+/// found by marching along the ray of the camera `cam_id`.
+pub fn pick(
+	inputs: &Inputs,
+	store: &EntityStore,
+	cam_id: u128,
+	terrain: &HeightMap,
+	water: &Water,
+) -> Option<Vec2> {
+	let ent_cam = store.get(cam_id)?;
+	let camera = ent_cam.as_any().downcast_ref::<Camera>()?;
+	let (origin, direction) = camera.ray(inputs.mouse_ndc());
+	let dims = terrain.dims();
+	let (offset, scale) = grid_placement(dims);
+	let (origin, direction) = ((origin - offset) / scale, direction / scale);
+	// Steps of a quarter of a cell across the grid, far enough to go from the
+	// camera past the other side of the grid.
+	let step = direction * 0.25 / Vec2::new(direction.x, direction.z).length().max(1e-6);
+	let max_steps = 16 * (dims.0 + dims.1);
+	let mut point = origin;
+	for _ in 0..max_steps {
+		point += step;
+		let (x, y) = (point.x.round(), point.z.round());
+		if x < 0.0 || y < 0.0 || x >= dims.0 as f32 || y >= dims.1 as f32 {
+			continue;
+		}
+		if point.y <= water.get_height(terrain, x as usize, y as usize) {
+			return Some(Vec2::new(point.x, point.z));
+		}
+	}
+	None
+}
@@ -0,0 +1,87 @@
+use super::{TerrainEntity, WaterEntity};
+use crate::engine::{Entity, EntityStore, Inputs, KeyCode, MeshPoints};
+use mod1::{Rain, SimRng};
+
+use glam::Vec3;
+use std::any::Any;
+
+#[derive(Debug)]
+pub struct RainEntity {
+	rain: Rain,
+	rng: SimRng,
+	mesh_id: u128,
+	water_id: u128,
+}
+
+impl RainEntity {
+	pub fn new(
+		store: &EntityStore,
+		water_id: u128,
+		rain: Rain,
+		rng: SimRng,
+		dims: (usize, usize),
+	) -> Self {
+		let rain_vert =
+			MeshPoints::points_vertices(&vec![Vec3::new(50.0, 50.0, -100.0); Rain::MAX_COUNT]);
+		let rain_mesh = Box::new(MeshPoints::new("rain", &rain_vert, dims, false, false));
+		let mesh_id = store.to_new_queue(rain_mesh);
+		RainEntity {
+			rain,
+			rng,
+			mesh_id,
+			water_id,
+		}
+	}
+
+	pub fn rain(&self) -> &Rain {
+		&self.rain
+	}
+
+	fn update_mesh(&self, store: &EntityStore) {
+		if let Some(ent_mesh) = store.get(self.mesh_id) {
+			if let Some(mesh) = ent_mesh.as_any().downcast_ref::<MeshPoints>() {
+				let droplets = self.rain.droplets();
+				mesh.update_vertices(|data| {
+					for (id, droplet) in droplets.iter().enumerate() {
+						data[id * 3 + 0] = droplet.x;
+						data[id * 3 + 1] = droplet.z;
+						data[id * 3 + 2] = droplet.y;
+					}
+					for id in droplets.len()..Rain::MAX_COUNT {
+						data[id * 3 + 0] = 50.0;
+						data[id * 3 + 1] = -100.0;
+						data[id * 3 + 2] = 50.0;
+					}
+				});
+			}
+		}
+	}
+}
+
+impl Entity for RainEntity {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn update(&mut self, delta: f32, inputs: &Inputs, store: &EntityStore) {
+		if let Some(mut ent_water) = store.get_mut(self.water_id) {
+			if let Some(water_ent) = ent_water.as_any_mut().downcast_mut::<WaterEntity>() {
+				if let Some(ent_terrain) = store.get(water_ent.terrain_id()) {
+					if let Some(terrain) = ent_terrain.as_any().downcast_ref::<TerrainEntity>() {
+						self.rain
+							.update(delta, terrain.height_map(), water_ent.water_mut());
+					}
+				}
+				if inputs.is_pressed(KeyCode::R) {
+					let strength = water_ent.input_str();
+					self.rain.spawn(water_ent.water(), strength, &mut self.rng);
+				}
+			}
+		}
+		self.update_mesh(store);
+	}
+}
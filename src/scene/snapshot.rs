@@ -0,0 +1,76 @@
+use super::{RainEntity, WaterEntity};
+use crate::engine::{Entity, EntityStore, Inputs, KeyCode};
+
+use std::any::Any;
+
+/// Save the water and rain state to a file when `S` is pressed, and the
+/// velocity field when `V` is.
+#[derive(Debug)]
+pub struct SnapshotEntity {
+	water_id: u128,
+	rain_id: u128,
+	path: String,
+	velocity_path: String,
+}
+
+impl SnapshotEntity {
+	pub fn new(water_id: u128, rain_id: u128, path: String, velocity_path: String) -> Self {
+		SnapshotEntity {
+			water_id,
+			rain_id,
+			path,
+			velocity_path,
+		}
+	}
+
+	fn save_velocities(&self, store: &EntityStore) -> Result<(), String> {
+		let ent_water = store
+			.get(self.water_id)
+			.ok_or(String::from("Missing water entity"))?;
+		match ent_water.as_any().downcast_ref::<WaterEntity>() {
+			Some(water) => mod1::save_velocities(&self.velocity_path, water.water()),
+			None => Err(String::from("Invalid water entity")),
+		}
+	}
+
+	fn save(&self, store: &EntityStore) -> Result<(), String> {
+		let ent_water = store
+			.get(self.water_id)
+			.ok_or(String::from("Missing water entity"))?;
+		let ent_rain = store
+			.get(self.rain_id)
+			.ok_or(String::from("Missing rain entity"))?;
+		match (
+			ent_water.as_any().downcast_ref::<WaterEntity>(),
+			ent_rain.as_any().downcast_ref::<RainEntity>(),
+		) {
+			(Some(water), Some(rain)) => mod1::save_state(&self.path, water.water(), rain.rain()),
+			_ => Err(String::from("Invalid water or rain entity")),
+		}
+	}
+}
+
+impl Entity for SnapshotEntity {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn update(&mut self, _delta: f32, inputs: &Inputs, store: &EntityStore) {
+		if inputs.is_just_pressed(KeyCode::S) {
+			match self.save(store) {
+				Ok(()) => println!("State saved to '{}'", self.path),
+				Err(err) => eprintln!("{}", err),
+			}
+		}
+		if inputs.is_just_pressed(KeyCode::V) {
+			match self.save_velocities(store) {
+				Ok(()) => println!("Velocities saved to '{}'", self.velocity_path),
+				Err(err) => eprintln!("{}", err),
+			}
+		}
+	}
+}
@@ -0,0 +1,36 @@
+use super::WaterEntity;
+use crate::engine::{Entity, EntityStore, Inputs};
+use mod1::Soil;
+
+use std::any::Any;
+
+/// Let the water of the water entity evaporate and soak into the ground.
+#[derive(Debug)]
+pub struct SoilEntity {
+	soil: Soil,
+	water_id: u128,
+}
+
+impl SoilEntity {
+	pub fn new(soil: Soil, water_id: u128) -> Self {
+		SoilEntity { soil, water_id }
+	}
+}
+
+impl Entity for SoilEntity {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn update(&mut self, delta: f32, _inputs: &Inputs, store: &EntityStore) {
+		if let Some(mut ent_water) = store.get_mut(self.water_id) {
+			if let Some(water) = ent_water.as_any_mut().downcast_mut::<WaterEntity>() {
+				self.soil.update(delta, water.water_mut());
+			}
+		}
+	}
+}
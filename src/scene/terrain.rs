@@ -0,0 +1,71 @@
+use crate::engine::{Entity, EntityStore, Inputs, Mesh};
+use mod1::HeightMap;
+
+use std::any::Any;
+
+#[derive(Debug)]
+pub struct TerrainEntity {
+	height_map: HeightMap,
+	mesh_id: Option<u128>,
+	changed: bool,
+}
+
+impl TerrainEntity {
+	pub fn new(height_map: HeightMap) -> Self {
+		TerrainEntity {
+			height_map,
+			mesh_id: None,
+			changed: false,
+		}
+	}
+
+	pub fn height_map(&self) -> &HeightMap {
+		&self.height_map
+	}
+
+	/// Borrow the heights to change them, the mesh is rebuilt on the next
+	/// update.
+	pub fn height_map_mut(&mut self) -> &mut HeightMap {
+		self.changed = true;
+		&mut self.height_map
+	}
+
+	fn update_mesh(&self, store: &EntityStore) {
+		if let Some(ent_mesh) = self.mesh_id.and_then(|mesh_id| store.get(mesh_id)) {
+			if let Some(mesh) = ent_mesh.as_any().downcast_ref::<Mesh>() {
+				let terrain_vert = Mesh::heights_gen_vertices(
+					self.height_map.dims(),
+					self.height_map.height_points(),
+				);
+				mesh.update_vertices(|data| data.copy_from_slice(&terrain_vert));
+			}
+		}
+	}
+}
+
+impl Entity for TerrainEntity {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn start(&mut self, store: &EntityStore) {
+		let dims = self.height_map.dims();
+		let terrain_vert = Mesh::heights_gen_vertices(dims, self.height_map.height_points());
+		let terrain_mesh = Box::new(Mesh::new("terrain", &terrain_vert, dims, true, true, None));
+		let border_vert = Mesh::wall_gen_vertices(&self.height_map.border_wall());
+		let border_mesh = Box::new(Mesh::new("border", &border_vert, dims, true, true, None));
+		self.mesh_id = Some(store.to_new_queue(terrain_mesh));
+		store.to_new_queue(border_mesh);
+	}
+
+	fn update(&mut self, _delta: f32, _inputs: &Inputs, store: &EntityStore) {
+		if self.changed {
+			self.update_mesh(store);
+			self.changed = false;
+		}
+	}
+}
@@ -0,0 +1,102 @@
+use super::{pick, TerrainEntity, WaterEntity};
+use crate::engine::{ClickButton, Entity, EntityStore, Inputs, KeyCode, MeshPoints};
+use mod1::{HeightMap, Tracers, Water};
+
+use glam::Vec3;
+use std::any::Any;
+
+/// Distance between two tracers seeded on the water with `G`, in cells.
+const GRID_SPACING: usize = 4;
+const CLICK_RADIUS: f32 = 2.0;
+const CLICK_COUNT: usize = 20;
+/// Height the tracers float at above the water surface.
+const FLOAT_HEIGHT: f32 = 0.1;
+
+/// Tracer particles drifting with the water of the water entity. A right
+/// click seeds some where it lands, `G` on the whole water, `I` on the
+/// springs and inlets, and `C` clears them.
+#[derive(Debug)]
+pub struct TracerEntity {
+	tracers: Tracers,
+	mesh_id: u128,
+	water_id: u128,
+	cam_id: u128,
+	dims: (usize, usize),
+}
+
+impl TracerEntity {
+	pub fn new(store: &EntityStore, water_id: u128, cam_id: u128, dims: (usize, usize)) -> Self {
+		let tracer_vert =
+			MeshPoints::points_vertices(&vec![Vec3::new(50.0, 50.0, -100.0); Tracers::MAX_COUNT]);
+		let tracer_mesh = Box::new(MeshPoints::new("tracer", &tracer_vert, dims, false, false));
+		let mesh_id = store.to_new_queue(tracer_mesh);
+		TracerEntity {
+			tracers: Tracers::new(),
+			mesh_id,
+			water_id,
+			cam_id,
+			dims,
+		}
+	}
+
+	fn update_mesh(&self, store: &EntityStore, terrain: &HeightMap, water: &Water) {
+		if let Some(ent_mesh) = store.get(self.mesh_id) {
+			if let Some(mesh) = ent_mesh.as_any().downcast_ref::<MeshPoints>() {
+				let positions = self.tracers.positions();
+				let (max_x, max_y) = (self.dims.0 - 1, self.dims.1 - 1);
+				mesh.update_vertices(|data| {
+					for (id, position) in positions.iter().enumerate() {
+						let x = (position.x.round().max(0.0) as usize).min(max_x);
+						let y = (position.y.round().max(0.0) as usize).min(max_y);
+						data[id * 3] = position.x;
+						data[id * 3 + 1] = water.get_height(terrain, x, y) + FLOAT_HEIGHT;
+						data[id * 3 + 2] = position.y;
+					}
+					for id in positions.len()..Tracers::MAX_COUNT {
+						data[id * 3] = 50.0;
+						data[id * 3 + 1] = -100.0;
+						data[id * 3 + 2] = 50.0;
+					}
+				});
+			}
+		}
+	}
+}
+
+impl Entity for TracerEntity {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn update(&mut self, delta: f32, inputs: &Inputs, store: &EntityStore) {
+		if let Some(ent_water) = store.get(self.water_id) {
+			if let Some(water_ent) = ent_water.as_any().downcast_ref::<WaterEntity>() {
+				if let Some(ent_terrain) = store.get(water_ent.terrain_id()) {
+					if let Some(terrain) = ent_terrain.as_any().downcast_ref::<TerrainEntity>() {
+						let (terrain, water) = (terrain.height_map(), water_ent.water());
+						if inputs.is_click_just_pressed(ClickButton::Right) {
+							if let Some(point) = pick(inputs, store, self.cam_id, terrain, water) {
+								self.tracers.seed_disk(point, CLICK_RADIUS, CLICK_COUNT);
+							}
+						}
+						if inputs.is_just_pressed(KeyCode::G) {
+							self.tracers.seed_grid(water, GRID_SPACING);
+						}
+						if inputs.is_just_pressed(KeyCode::I) {
+							self.tracers.seed_sources(water);
+						}
+						if inputs.is_just_pressed(KeyCode::C) {
+							self.tracers.clear();
+						}
+						self.tracers.update(delta, water);
+						self.update_mesh(store, terrain, water);
+					}
+				}
+			}
+		}
+	}
+}
@@ -0,0 +1,417 @@
+use super::TerrainEntity;
+use crate::engine::{Entity, EntityStore, Inputs, KeyCode, Mesh};
+use mod1::{Water, WaterParams};
+
+use glam::Vec3;
+use noise::{NoiseFn, Perlin, Seedable, Worley};
+use rand::Rng;
+use std::any::Any;
+
+/// Factor applied to a parameter by each press on the tuning keys.
+const PARAM_STEP: f32 = 1.1;
+
+#[derive(Debug)]
+pub struct WaterEntity {
+	water: Water,
+	terrain_id: u128,
+	mesh_id: u128,
+	border_id: u128,
+	input_str: u8,
+	clamped: bool,
+	/// Index in [`WaterParams::RANGES`] of the parameter tuned by the keys.
+	param: usize,
+}
+
+impl WaterEntity {
+	pub fn new(store: &EntityStore, terrain_id: u128, water: Water, rng: &mut impl Rng) -> Self {
+		let dims = water.dims();
+		let noise = WaterEntity::foam_noise(rng);
+		let water_vert = Mesh::heights_gen_vertices(dims, &vec![-0.1; dims.0 * dims.1]);
+		let water_mesh = Box::new(Mesh::new(
+			"water",
+			&water_vert,
+			dims,
+			false,
+			false,
+			Some(&noise),
+		));
+		let mesh_id = store.to_new_queue(water_mesh);
+		let border_vert = Mesh::wall_gen_vertices(&vec![Vec3::ZERO; 4 * (dims.0 + dims.1)]);
+		let border_mesh = Box::new(Mesh::new("water", &border_vert, dims, false, false, None));
+		let border_id = store.to_new_queue(border_mesh);
+		WaterEntity {
+			water,
+			terrain_id,
+			mesh_id,
+			border_id,
+			input_str: 0,
+			clamped: false,
+			param: 0,
+		}
+	}
+
+	fn interpolate_noise(x: i32, y: i32, scale: f64, noise: &Perlin) -> u8 {
+		if x < 974 && y < 974 {
+			(((noise.get([x as f64 / scale * 10.0, y as f64 / scale * 10.0]) + 1.0) / 2.0) * 255.0)
+				as u8
+		} else {
+			if y < 974 {
+				let last =
+					(noise.get([974 as f64 / scale * 10.0, y as f64 / scale * 10.0]) + 1.0) / 2.0;
+				let first =
+					(noise.get([0 as f64 / scale * 10.0, y as f64 / scale * 10.0]) + 1.0) / 2.0;
+				let k = (x - 974) as f64 / 50.0;
+				((last * (1.0 - k) + first * k) * 255.0) as u8
+			} else if x < 974 {
+				let last =
+					(noise.get([x as f64 / scale * 10.0, 974 as f64 / scale * 10.0]) + 1.0) / 2.0;
+				let first =
+					(noise.get([x as f64 / scale * 10.0, 0 as f64 / scale * 10.0]) + 1.0) / 2.0;
+				let k = (y - 974) as f64 / 50.0;
+				((last * (1.0 - k) + first * k) * 255.0) as u8
+			} else {
+				let last =
+					(noise.get([974 as f64 / scale * 10.0, 974 as f64 / scale * 10.0]) + 1.0) / 2.0;
+				let first =
+					(noise.get([974 as f64 / scale * 10.0, 0 as f64 / scale * 10.0]) + 1.0) / 2.0;
+				let k = (y - 974) as f64 / 50.0;
+				let last_interpol = last * (1.0 - k) + first * k;
+				let last =
+					(noise.get([0 as f64 / scale * 10.0, 974 as f64 / scale * 10.0]) + 1.0) / 2.0;
+				let first =
+					(noise.get([0 as f64 / scale * 10.0, 0 as f64 / scale * 10.0]) + 1.0) / 2.0;
+				let k = (y - 974) as f64 / 50.0;
+				let first_interpol = last * (1.0 - k) + first * k;
+				let k = (x - 974) as f64 / 50.0;
+				((last_interpol * (1.0 - k) + first_interpol * k) * 255.0) as u8
+			}
+		}
+	}
+
+	fn foam_noise(rng: &mut impl Rng) -> [[u8; 3]; 1024 * 1024] {
+		let whorley = Worley::default()
+			.set_frequency(10.0)
+			.set_seed(rng.gen::<u32>());
+		let perlin_x = Perlin::default().set_seed(rng.gen::<u32>());
+		let perlin_y = Perlin::default().set_seed(rng.gen::<u32>());
+
+		let edge_w = 3;
+		let scale = 1024.0;
+		let mut edge = [[0; 3]; 1024 * 1024];
+		for x in 0..1024 {
+			for y in 0..1024 {
+				let x_pr = if x >= edge_w {
+					x - edge_w
+				} else {
+					1023 - x - edge_w
+				};
+				let x_nx = if x <= 1023 - edge_w {
+					x + edge_w
+				} else {
+					x + edge_w - 1023
+				};
+				let y_pr = if y >= edge_w {
+					y - edge_w
+				} else {
+					1023 - y - edge_w
+				};
+				let y_nx = if y <= 1023 - edge_w {
+					y + edge_w
+				} else {
+					y + edge_w - 1023
+				};
+				let val_l = (whorley.get([x_pr as f64 / scale, y as f64 / scale]) + 1.0) / 2.0;
+				let val_r = (whorley.get([x_nx as f64 / scale, y as f64 / scale]) + 1.0) / 2.0;
+				let val_top = (whorley.get([x as f64 / scale, y_pr as f64 / scale]) + 1.0) / 2.0;
+				let val_bot = (whorley.get([x as f64 / scale, y_nx as f64 / scale]) + 1.0) / 2.0;
+				let d_x = (val_l - val_r).abs();
+				let d_y = (val_top - val_bot).abs();
+				let noise_x = WaterEntity::interpolate_noise(x, y, scale, &perlin_x);
+				let noise_y = WaterEntity::interpolate_noise(x, y, scale, &perlin_y);
+				if d_x > 0.0 || d_y > 0.0 {
+					edge[(x + y * 1024) as usize] = [255, noise_x, noise_y];
+				} else {
+					edge[(x + y * 1024) as usize] = [0, noise_x, noise_y];
+				}
+			}
+		}
+		return edge;
+	}
+
+	fn update_mesh(&self, store: &EntityStore) {
+		let (dim_x, dim_y) = self.water.dims();
+		let depths = self.water.depths();
+		let zero_depth = self.water.params().zero_depth;
+		if let Some(ent_terrain) = store.get(self.terrain_id) {
+			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<TerrainEntity>() {
+				let terrain = terrain.height_map();
+				if let Some(ent_mesh) = store.get_mut(self.mesh_id) {
+					if let Some(mesh) = ent_mesh.as_any().downcast_ref::<Mesh>() {
+						mesh.update_vertices(|data| {
+							for i in 0..(data.len() / 6) as usize {
+								let x = data[i * 6] as usize;
+								let y = data[i * 6 + 2] as usize;
+								if depths[x + y * dim_x] > zero_depth {
+									data[i * 6 + 1] = depths[x + y * dim_x]
+										+ terrain.height_points()[x + y * dim_x];
+								} else {
+									let check_neighbor = |n_index: usize, sum: &mut f32| {
+										if depths[n_index] > zero_depth
+											&& terrain.height_points()[x + y * dim_x]
+												> terrain.height_points()[n_index]
+										{
+											*sum +=
+												terrain.height_points()[n_index] + depths[n_index];
+											return 1.0;
+										}
+										return 0.0;
+									};
+									let mut n_count = 0.0;
+									let mut n_sum = 0.0;
+									if x > 0 {
+										n_count += check_neighbor(x - 1 + y * dim_x, &mut n_sum);
+									}
+
+									if x < dim_x - 1 {
+										n_count += check_neighbor(x + 1 + y * dim_x, &mut n_sum);
+									}
+
+									if y > 0 {
+										n_count += check_neighbor(x + (y - 1) * dim_x, &mut n_sum);
+										if x > 0 {
+											n_count +=
+												check_neighbor(x - 1 + (y - 1) * dim_x, &mut n_sum);
+										}
+										if x < dim_x - 1 {
+											n_count +=
+												check_neighbor(x + 1 + (y - 1) * dim_x, &mut n_sum);
+										}
+									}
+
+									if y < dim_y - 1 {
+										n_count += check_neighbor(x + (y + 1) * dim_x, &mut n_sum);
+										if x > 0 {
+											n_count +=
+												check_neighbor(x - 1 + (y + 1) * dim_x, &mut n_sum);
+										}
+										if x < dim_x - 1 {
+											n_count +=
+												check_neighbor(x + 1 + (y + 1) * dim_x, &mut n_sum);
+										}
+									}
+
+									if n_count == 0.0 {
+										data[i * 6 + 1] =
+											terrain.height_points()[x + y * dim_x] - 1.0;
+									} else {
+										data[i * 6 + 1] = n_sum / n_count;
+									}
+								}
+								let prev_x = if x > 0 && depths[x - 1 + y * dim_x] > zero_depth {
+									terrain.height_points()[x - 1 + y * dim_x]
+										+ depths[x - 1 + y * dim_x]
+								} else {
+									terrain.height_points()[x + y * dim_x] + depths[x + y * dim_x]
+								};
+								let next_x = if x < dim_x - 1
+									&& depths[x + 1 + y * dim_x] > zero_depth
+								{
+									terrain.height_points()[x + 1 + y * dim_x]
+										+ depths[x + 1 + y * dim_x]
+								} else {
+									terrain.height_points()[x + y * dim_x] + depths[x + y * dim_x]
+								};
+								let prev_y = if y > 0 && depths[x + (y - 1) * dim_x] > zero_depth {
+									terrain.height_points()[x + (y - 1) * dim_x]
+										+ depths[x + (y - 1) * dim_x]
+								} else {
+									terrain.height_points()[x + y * dim_x] + depths[x + y * dim_x]
+								};
+								let next_y = if y < dim_y - 1
+									&& depths[x + (y + 1) * dim_x] > zero_depth
+								{
+									terrain.height_points()[x + (y + 1) * dim_x]
+										+ depths[x + (y + 1) * dim_x]
+								} else {
+									terrain.height_points()[x + y * dim_x] + depths[x + y * dim_x]
+								};
+								let normal = Mesh::normal(prev_x, next_x, prev_y, next_y);
+								data[i * 6 + 3] = normal.x;
+								data[i * 6 + 4] = normal.z;
+								data[i * 6 + 5] = normal.y;
+							}
+						});
+					}
+				}
+			}
+		}
+	}
+
+	fn update_border_mesh(&self, store: &EntityStore) {
+		let (dim_x, dim_y) = self.water.dims();
+		let depths = self.water.depths();
+		if let Some(ent_terrain) = store.get(self.terrain_id) {
+			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<TerrainEntity>() {
+				let terrain = terrain.height_map();
+				let mut bord_points = Vec::new();
+				for x in 0..(dim_x - 1) {
+					bord_points.push(Vec3::new(x as f32, 0.0, 0.0));
+					bord_points.push(Vec3::new(
+						x as f32,
+						0.0,
+						depths[x] + terrain.height_points()[x],
+					));
+				}
+				for y in 0..(dim_y - 1) {
+					bord_points.push(Vec3::new((dim_x - 1) as f32, y as f32, 0.0));
+					bord_points.push(Vec3::new(
+						(dim_x - 1) as f32,
+						y as f32,
+						depths[dim_x - 1 + y * dim_x]
+							+ terrain.height_points()[dim_x - 1 + y * dim_x],
+					));
+				}
+
+				for x in (0..dim_x).rev() {
+					bord_points.push(Vec3::new(x as f32, (dim_y - 1) as f32, 0.0));
+					bord_points.push(Vec3::new(
+						x as f32,
+						(dim_y - 1) as f32,
+						depths[x + (dim_y - 1) * dim_x]
+							+ terrain.height_points()[x + (dim_y - 1) * dim_x],
+					));
+				}
+				for y in (0..dim_y).rev() {
+					bord_points.push(Vec3::new(0.0, y as f32, 0.0));
+					bord_points.push(Vec3::new(
+						0.0,
+						y as f32,
+						depths[0 + y * dim_x] + terrain.height_points()[0 + y * dim_x],
+					));
+				}
+
+				if let Some(ent_mesh) = store.get_mut(self.border_id) {
+					if let Some(mesh) = ent_mesh.as_any().downcast_ref::<Mesh>() {
+						mesh.update_vertices(|data| {
+							let bord_vert = Mesh::wall_gen_vertices(&bord_points);
+							for (i, val) in bord_vert.into_iter().enumerate() {
+								data[i] = val;
+							}
+						});
+					}
+				}
+			}
+		}
+	}
+
+	fn handle_inputs(&mut self, inputs: &Inputs, store: &EntityStore) {
+		if self.input_str > 0
+			&& (inputs.is_just_pressed(KeyCode::Minus) || inputs.is_just_pressed(KeyCode::M))
+		{
+			self.input_str = self.input_str - 1;
+		}
+		if self.input_str < Water::MAX_STRENGTH
+			&& (inputs.is_just_pressed(KeyCode::Plus)
+				|| inputs.is_just_pressed(KeyCode::Equals)
+				|| inputs.is_just_pressed(KeyCode::P))
+		{
+			self.input_str = self.input_str + 1;
+		}
+
+		if inputs.is_just_pressed(KeyCode::L) {
+			println!("{}", self.water.ledger());
+		}
+
+		if inputs.is_just_pressed(KeyCode::Tab) {
+			self.param = (self.param + 1) % WaterParams::RANGES.len();
+			self.tune_param(1.0);
+		}
+		if inputs.is_just_pressed(KeyCode::LBracket) {
+			self.tune_param(1.0 / PARAM_STEP);
+		}
+		if inputs.is_just_pressed(KeyCode::RBracket) {
+			self.tune_param(PARAM_STEP);
+		}
+
+		if inputs.is_pressed(KeyCode::W) {
+			self.water.wave(self.input_str);
+		}
+
+		if let Some(ent_terrain) = store.get(self.terrain_id) {
+			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<TerrainEntity>() {
+				if inputs.is_pressed(KeyCode::T) {
+					self.water.tide(terrain.height_map(), self.input_str);
+				}
+				if inputs.is_pressed(KeyCode::D) {
+					self.water.drain(terrain.height_map(), self.input_str);
+				}
+			}
+		}
+	}
+
+	/// Multiply the selected parameter by `factor` and print its new value,
+	/// keeping it in its range.
+	fn tune_param(&mut self, factor: f32) {
+		let (name, min, max) = WaterParams::RANGES[self.param];
+		let mut params = *self.water.params();
+		let value = params.get(name).unwrap_or_default() * factor;
+		// A parameter at 0 can not grow by a factor.
+		let value = if value == 0.0 && factor > 1.0 {
+			max / 100.0
+		} else {
+			value
+		};
+		if params.set(name, value.clamp(min, max)).is_ok() {
+			self.water.set_params(params).ok();
+		}
+		println!(
+			"{} {}",
+			name,
+			self.water.params().get(name).unwrap_or_default()
+		);
+	}
+
+	pub fn water(&self) -> &Water {
+		&self.water
+	}
+
+	pub fn water_mut(&mut self) -> &mut Water {
+		&mut self.water
+	}
+
+	pub fn terrain_id(&self) -> u128 {
+		self.terrain_id
+	}
+
+	pub fn input_str(&self) -> u8 {
+		self.input_str
+	}
+}
+
+impl Entity for WaterEntity {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+
+	fn update(&mut self, delta: f32, inputs: &Inputs, store: &EntityStore) {
+		if let Some(ent_terrain) = store.get(self.terrain_id) {
+			if let Some(terrain) = ent_terrain.as_any().downcast_ref::<TerrainEntity>() {
+				let report = self.water.update(terrain.height_map(), delta);
+				if report.clamped && !self.clamped {
+					eprintln!(
+						"Water solver clamped to {} substeps, the flow is too fast to stay stable",
+						report.substeps
+					);
+				}
+				self.clamped = report.clamped;
+			}
+		}
+		self.handle_inputs(inputs, store);
+		self.update_mesh(store);
+		self.update_border_mesh(store);
+	}
+}